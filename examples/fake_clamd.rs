@@ -0,0 +1,76 @@
+// clamd de mentira para desenvolvimento e testes: fala o protocolo INSTREAM
+// (e PING) e acusa só o arquivo de teste EICAR.
+//
+//     cargo run --example fake_clamd          # escuta em 127.0.0.1:3310
+//     FAKE_CLAMD_ADDR=127.0.0.1:3311 cargo run --example fake_clamd
+use std::io;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+const EICAR_MARKER: &[u8] = b"EICAR-STANDARD-ANTIVIRUS-TEST-FILE";
+
+// Mesmo padrão do StreamMaxLength do clamd
+const STREAM_MAX_LENGTH: usize = 25 * 1024 * 1024;
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let address = std::env::var("FAKE_CLAMD_ADDR").unwrap_or_else(|_| "127.0.0.1:3310".to_string());
+    let listener = TcpListener::bind(&address).await?;
+    println!("Fake clamd listening on {}", address);
+
+    loop {
+        let (connection, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(error) = handle(connection).await {
+                eprintln!("Connection failed: {}", error);
+            }
+        });
+    }
+}
+
+async fn handle(mut connection: TcpStream) -> io::Result<()> {
+    // Comandos com prefixo z terminam em NUL; com prefixo n, em quebra de linha
+    let mut command = Vec::new();
+    loop {
+        let byte = connection.read_u8().await?;
+        if byte == 0 || byte == b'\n' {
+            break;
+        }
+        command.push(byte);
+    }
+
+    let reply = match &command[..] {
+        b"zPING" | b"nPING" => "PONG".to_string(),
+        b"zINSTREAM" | b"nINSTREAM" => instream(&mut connection).await?,
+        _ => "UNKNOWN COMMAND".to_string(),
+    };
+
+    connection.write_all(reply.as_bytes()).await?;
+    connection.write_all(b"\0").await?;
+    connection.shutdown().await
+}
+
+async fn instream(connection: &mut TcpStream) -> io::Result<String> {
+    let mut content = Vec::new();
+    loop {
+        let length = connection.read_u32().await? as usize;
+        if length == 0 {
+            break;
+        }
+        if content.len() + length > STREAM_MAX_LENGTH {
+            return Ok("INSTREAM size limit exceeded. ERROR".to_string());
+        }
+        let start = content.len();
+        content.resize(start + length, 0);
+        connection.read_exact(&mut content[start..]).await?;
+    }
+
+    if content.windows(EICAR_MARKER.len()).any(|window| window == EICAR_MARKER) {
+        Ok("stream: Eicar-Test-Signature FOUND".to_string())
+    } else {
+        Ok("stream: OK".to_string())
+    }
+}
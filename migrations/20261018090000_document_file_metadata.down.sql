@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE documents
+    DROP COLUMN IF EXISTS sha256,
+    DROP COLUMN IF EXISTS mime_type,
+    DROP COLUMN IF EXISTS size_bytes,
+    DROP COLUMN IF EXISTS original_filename;
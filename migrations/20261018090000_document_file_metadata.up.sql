@@ -0,0 +1,9 @@
+-- Add up migration script here
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS original_filename TEXT NOT NULL DEFAULT '',
+    ADD COLUMN IF NOT EXISTS size_bytes BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS mime_type TEXT NOT NULL DEFAULT 'application/octet-stream',
+    ADD COLUMN IF NOT EXISTS sha256 TEXT NOT NULL DEFAULT '';
+
+-- Documents created before uploads existed keep their generated name
+UPDATE documents SET original_filename = filename WHERE original_filename = '';
@@ -0,0 +1,13 @@
+-- Add up migration script here
+-- Uploads resumíveis (protocolo tus); viram documentos ao completar
+CREATE TABLE IF NOT EXISTS uploads (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL,
+    doc_type TEXT NOT NULL,
+    original_filename TEXT NOT NULL,
+    mime_type TEXT NOT NULL,
+    upload_length BIGINT NOT NULL,
+    upload_offset BIGINT NOT NULL DEFAULT 0,
+    document_id UUID REFERENCES documents (id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS documents_sha256_idx;
+DROP TABLE IF EXISTS blobs;
@@ -0,0 +1,20 @@
+-- Add up migration script here
+-- Conteúdo armazenado, compartilhado pelos documentos com o mesmo SHA-256.
+-- A chave é o documents.filename; novos uploads usam o próprio SHA-256 como chave.
+CREATE TABLE IF NOT EXISTS blobs (
+    key TEXT PRIMARY KEY NOT NULL,
+    sha256 TEXT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    ref_count INTEGER NOT NULL CHECK (ref_count >= 0),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS blobs_sha256_idx ON blobs (sha256);
+CREATE INDEX IF NOT EXISTS documents_sha256_idx ON documents (sha256);
+
+-- Arquivos enviados antes desta migração continuam sob o nome antigo
+INSERT INTO blobs (key, sha256, size_bytes, ref_count)
+SELECT filename, sha256, max(size_bytes), count(*)
+FROM documents
+GROUP BY filename, sha256
+ON CONFLICT (key) DO NOTHING;
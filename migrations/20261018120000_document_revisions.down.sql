@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS document_revisions;
+ALTER TABLE documents DROP COLUMN IF EXISTS revision;
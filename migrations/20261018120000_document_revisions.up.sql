@@ -0,0 +1,23 @@
+-- Add up migration script here
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS revision INTEGER NOT NULL DEFAULT 1;
+
+-- Histórico do conteúdo de cada documento; a revisão atual é a de documents.revision.
+-- Cada revisão é uma referência ao blob de documents.filename.
+CREATE TABLE IF NOT EXISTS document_revisions (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    document_id UUID NOT NULL REFERENCES documents (id) ON DELETE CASCADE,
+    revision INTEGER NOT NULL,
+    filename TEXT NOT NULL,
+    original_filename TEXT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    mime_type TEXT NOT NULL,
+    sha256 TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now(),
+    UNIQUE (document_id, revision)
+);
+
+-- A referência de cada documento existente passa para a sua revisão 1
+INSERT INTO document_revisions (document_id, revision, filename, original_filename, size_bytes, mime_type, sha256, created_at)
+SELECT id, 1, filename, original_filename, size_bytes, mime_type, sha256, created_at
+FROM documents
+ON CONFLICT (document_id, revision) DO NOTHING;
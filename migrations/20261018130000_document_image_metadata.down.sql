@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE document_revisions DROP COLUMN IF EXISTS image_metadata;
+ALTER TABLE documents DROP COLUMN IF EXISTS image_metadata;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Campos úteis do EXIF das imagens (captura, orientação, câmera); o arquivo armazenado fica sem EXIF
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS image_metadata JSONB;
+ALTER TABLE document_revisions ADD COLUMN IF NOT EXISTS image_metadata JSONB;
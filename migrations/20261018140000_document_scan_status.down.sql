@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS documents_scan_status_idx;
+ALTER TABLE document_revisions DROP COLUMN IF EXISTS scan_status;
+ALTER TABLE documents
+    DROP COLUMN IF EXISTS scanned_at,
+    DROP COLUMN IF EXISTS scan_detail,
+    DROP COLUMN IF EXISTS scan_status;
@@ -0,0 +1,14 @@
+-- Add up migration script here
+-- Resultado do antivírus (clamd) para o conteúdo; só conteúdo clean pode ser baixado.
+-- scan_detail guarda a assinatura encontrada ou o erro da verificação.
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS scan_status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (scan_status IN ('pending', 'clean', 'infected', 'error')),
+    ADD COLUMN IF NOT EXISTS scan_detail TEXT,
+    ADD COLUMN IF NOT EXISTS scanned_at TIMESTAMP WITH TIME ZONE;
+
+ALTER TABLE document_revisions
+    ADD COLUMN IF NOT EXISTS scan_status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (scan_status IN ('pending', 'clean', 'infected', 'error'));
+
+CREATE INDEX IF NOT EXISTS documents_scan_status_idx ON documents (scan_status) WHERE scan_status <> 'clean';
@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS documents_review_status_idx;
+ALTER TABLE documents
+    DROP COLUMN IF EXISTS rejection_reason,
+    DROP COLUMN IF EXISTS decided_at,
+    DROP COLUMN IF EXISTS reviewer_id,
+    DROP COLUMN IF EXISTS review_status;
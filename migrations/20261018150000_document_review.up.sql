@@ -0,0 +1,10 @@
+-- Add up migration script here
+-- Revisão dos documentos: submitted -> in_review -> approved | rejected, e rejected -> submitted
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS review_status TEXT NOT NULL DEFAULT 'submitted'
+        CHECK (review_status IN ('submitted', 'in_review', 'approved', 'rejected')),
+    ADD COLUMN IF NOT EXISTS reviewer_id UUID,
+    ADD COLUMN IF NOT EXISTS decided_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN IF NOT EXISTS rejection_reason TEXT;
+
+CREATE INDEX IF NOT EXISTS documents_review_status_idx ON documents (review_status);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE uploads DROP CONSTRAINT IF EXISTS uploads_doc_type_fkey, DROP COLUMN IF EXISTS metadata;
+ALTER TABLE documents DROP CONSTRAINT IF EXISTS documents_doc_type_fkey, DROP COLUMN IF EXISTS metadata;
+DROP TABLE IF EXISTS document_types;
@@ -0,0 +1,57 @@
+-- Add up migration script here
+-- Tipos de documento canônicos. Cada tipo tem um JSON Schema para documents.metadata;
+-- aliases são grafias aceitas na entrada e trocadas pela chave canônica.
+CREATE TABLE IF NOT EXISTS document_types (
+    key TEXT PRIMARY KEY NOT NULL CHECK (key ~ '^[a-z0-9_]+$'),
+    display_name TEXT NOT NULL,
+    metadata_schema JSONB NOT NULL DEFAULT '{"type": "object"}',
+    aliases TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
+
+INSERT INTO document_types (key, display_name, metadata_schema, aliases) VALUES
+    ('passport', 'Passport', '{
+        "type": "object",
+        "properties": {
+            "document_number": {"type": "string", "minLength": 1},
+            "issuing_country": {"type": "string", "pattern": "^[A-Z]{3}$"},
+            "expires_on": {"type": "string", "format": "date"}
+        }
+    }', '{passaporte}'),
+    ('id_card', 'Identity card', '{
+        "type": "object",
+        "properties": {
+            "document_number": {"type": "string", "minLength": 1},
+            "issuing_state": {"type": "string"}
+        }
+    }', '{identity_card,carteira_de_identidade}'),
+    ('driver_license', 'Driver''s license', '{
+        "type": "object",
+        "properties": {
+            "document_number": {"type": "string", "minLength": 1},
+            "categories": {"type": "array", "items": {"type": "string"}},
+            "expires_on": {"type": "string", "format": "date"}
+        }
+    }', '{drivers_license,cnh}')
+ON CONFLICT (key) DO NOTHING;
+
+-- Os doc_types já gravados que já têm a forma de uma chave viram tipos registrados.
+-- Os demais ficam como estão: a chave estrangeira é NOT VALID, então vale só para
+-- o que for gravado daqui em diante, e nenhuma linha existente é alterada.
+INSERT INTO document_types (key, display_name)
+SELECT DISTINCT doc_type, doc_type FROM documents WHERE doc_type ~ '^[a-z0-9_]+$'
+ON CONFLICT (key) DO NOTHING;
+INSERT INTO document_types (key, display_name)
+SELECT DISTINCT doc_type, doc_type FROM uploads WHERE doc_type ~ '^[a-z0-9_]+$'
+ON CONFLICT (key) DO NOTHING;
+
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS metadata JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE documents
+    ADD CONSTRAINT documents_doc_type_fkey FOREIGN KEY (doc_type) REFERENCES document_types (key)
+    ON UPDATE CASCADE NOT VALID;
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS metadata JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE uploads
+    ADD CONSTRAINT uploads_doc_type_fkey FOREIGN KEY (doc_type) REFERENCES document_types (key)
+    ON UPDATE CASCADE NOT VALID;
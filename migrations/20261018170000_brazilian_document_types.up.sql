@@ -0,0 +1,26 @@
+-- Add up migration script here
+-- Documentos brasileiros; o número em document_number é conferido e formatado pela API
+INSERT INTO document_types (key, display_name, metadata_schema, aliases) VALUES
+    ('cpf', 'CPF', '{
+        "type": "object",
+        "properties": {
+            "document_number": {"type": "string", "minLength": 1},
+            "holder_name": {"type": "string"}
+        }
+    }', '{cartao_cpf}'),
+    ('cnpj', 'CNPJ', '{
+        "type": "object",
+        "properties": {
+            "document_number": {"type": "string", "minLength": 1},
+            "company_name": {"type": "string"}
+        }
+    }', '{cartao_cnpj}'),
+    ('rg', 'RG', '{
+        "type": "object",
+        "properties": {
+            "document_number": {"type": "string", "minLength": 1},
+            "issuing_state": {"type": "string", "pattern": "^[A-Za-z]{2}$"},
+            "holder_name": {"type": "string"}
+        }
+    }', '{registro_geral,cedula_de_identidade}')
+ON CONFLICT (key) DO NOTHING;
@@ -0,0 +1,8 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS documents_expires_at_idx;
+UPDATE documents SET review_status = 'submitted' WHERE review_status = 'expired';
+ALTER TABLE documents DROP CONSTRAINT IF EXISTS documents_review_status_check;
+ALTER TABLE documents ADD CONSTRAINT documents_review_status_check
+    CHECK (review_status IN ('submitted', 'in_review', 'approved', 'rejected'));
+ALTER TABLE uploads DROP COLUMN IF EXISTS expires_at;
+ALTER TABLE documents DROP COLUMN IF EXISTS expires_at;
@@ -0,0 +1,24 @@
+-- Add up migration script here
+-- Validade dos documentos; a varredura periódica passa os vencidos para review_status = 'expired'
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS expires_at DATE;
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS expires_at DATE;
+
+ALTER TABLE documents DROP CONSTRAINT IF EXISTS documents_review_status_check;
+ALTER TABLE documents ADD CONSTRAINT documents_review_status_check
+    CHECK (review_status IN ('submitted', 'in_review', 'approved', 'rejected', 'expired'));
+
+-- Documentos já gravados herdam a data de metadata.expires_on, quando for uma data válida
+DO $$
+DECLARE
+    row RECORD;
+BEGIN
+    FOR row IN SELECT id, metadata->>'expires_on' AS expires_on FROM documents WHERE metadata ? 'expires_on' LOOP
+        BEGIN
+            UPDATE documents SET expires_at = row.expires_on::date WHERE id = row.id;
+        EXCEPTION WHEN others THEN
+            NULL;
+        END;
+    END LOOP;
+END $$;
+
+CREATE INDEX IF NOT EXISTS documents_expires_at_idx ON documents (expires_at) WHERE expires_at IS NOT NULL;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS share_link_accesses;
+DROP TABLE IF EXISTS share_links;
@@ -0,0 +1,24 @@
+-- Add up migration script here
+-- Links assinados para baixar um documento sem conta; cada acesso fica registrado
+CREATE TABLE IF NOT EXISTS share_links (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    document_id UUID NOT NULL REFERENCES documents (id) ON DELETE CASCADE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    max_downloads INTEGER CHECK (max_downloads > 0),
+    allowed_ip TEXT,
+    download_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS share_links_document_id_idx ON share_links (document_id);
+
+CREATE TABLE IF NOT EXISTS share_link_accesses (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    share_link_id UUID NOT NULL REFERENCES share_links (id) ON DELETE CASCADE,
+    accessed_at TIMESTAMP WITH TIME ZONE DEFAULT now(),
+    ip TEXT,
+    user_agent TEXT,
+    outcome TEXT NOT NULL CHECK (outcome IN ('granted', 'expired', 'exhausted', 'ip_denied', 'unavailable'))
+);
+
+CREATE INDEX IF NOT EXISTS share_link_accesses_share_link_id_idx ON share_link_accesses (share_link_id);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE document_revisions DROP COLUMN IF EXISTS wrapped_key;
+ALTER TABLE document_revisions DROP COLUMN IF EXISTS master_key_id;
+
+ALTER TABLE documents DROP COLUMN IF EXISTS wrapped_key;
+ALTER TABLE documents DROP COLUMN IF EXISTS master_key_id;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- Chave de dados (AES-256-GCM) de cada conteúdo, embrulhada pela chave mestra master_key_id.
+-- Linhas sem chave apontam para conteúdo gravado em claro, antes desta migração.
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS master_key_id TEXT;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS wrapped_key TEXT;
+
+ALTER TABLE document_revisions ADD COLUMN IF NOT EXISTS master_key_id TEXT;
+ALTER TABLE document_revisions ADD COLUMN IF NOT EXISTS wrapped_key TEXT;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS document_destructions;
+DROP TABLE IF EXISTS user_legal_holds;
+ALTER TABLE documents DROP COLUMN IF EXISTS legal_hold;
+DROP TABLE IF EXISTS retention_rules;
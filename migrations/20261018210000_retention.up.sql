@@ -0,0 +1,33 @@
+-- Add up migration script here
+-- Por quantos anos cada doc_type é guardado; passado o prazo, contado do upload,
+-- a limpeza destrói o documento e o seu conteúdo
+CREATE TABLE IF NOT EXISTS retention_rules (
+    doc_type TEXT PRIMARY KEY NOT NULL REFERENCES document_types (key) ON UPDATE CASCADE ON DELETE CASCADE,
+    retention_years INTEGER NOT NULL CHECK (retention_years > 0),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
+
+-- Retenção legal (litígio): nada sob hold é destruído, nem pela limpeza nem pelo DELETE
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS legal_hold BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE IF NOT EXISTS user_legal_holds (
+    user_id UUID PRIMARY KEY NOT NULL,
+    reason TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
+
+-- Registro de cada documento destruído pela limpeza; sobrevive ao documento
+CREATE TABLE IF NOT EXISTS document_destructions (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    document_id UUID NOT NULL,
+    user_id UUID NOT NULL,
+    doc_type TEXT NOT NULL,
+    original_filename TEXT NOT NULL,
+    sha256 TEXT NOT NULL,
+    uploaded_at TIMESTAMP WITH TIME ZONE,
+    retention_years INTEGER NOT NULL,
+    destroyed_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS document_destructions_destroyed_at_idx ON document_destructions (destroyed_at);
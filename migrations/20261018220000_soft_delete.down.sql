@@ -0,0 +1,6 @@
+-- Add down migration script here
+-- O que estava na lixeira volta a aparecer
+DROP INDEX IF EXISTS documents_deleted_at_idx;
+DROP INDEX IF EXISTS tasks_deleted_at_idx;
+ALTER TABLE tasks DROP COLUMN IF EXISTS deleted_at;
+ALTER TABLE documents DROP COLUMN IF EXISTS deleted_at;
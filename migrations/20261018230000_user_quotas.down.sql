@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS documents_user_id_idx;
+DROP TABLE IF EXISTS user_quotas;
@@ -0,0 +1,12 @@
+-- Add up migration script here
+-- Cota por usuário; coluna NULL cai no padrão da configuração (QUOTA_MAX_BYTES, QUOTA_MAX_DOCUMENTS)
+CREATE TABLE IF NOT EXISTS user_quotas (
+    user_id UUID PRIMARY KEY NOT NULL,
+    max_bytes BIGINT CHECK (max_bytes >= 0),
+    max_documents BIGINT CHECK (max_documents >= 0),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
+
+-- O consumo é somado a partir dos documentos do usuário
+CREATE INDEX IF NOT EXISTS documents_user_id_idx ON documents (user_id);
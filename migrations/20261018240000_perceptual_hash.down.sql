@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS documents_perceptual_hash_idx;
+ALTER TABLE document_revisions DROP COLUMN IF EXISTS perceptual_hash;
+ALTER TABLE documents DROP COLUMN IF EXISTS perceptual_hash;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+-- dHash de 64 bits do conteúdo das imagens, para achar a mesma foto enviada de novo
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS perceptual_hash BIGINT;
+ALTER TABLE document_revisions ADD COLUMN IF NOT EXISTS perceptual_hash BIGINT;
+
+CREATE INDEX IF NOT EXISTS documents_perceptual_hash_idx ON documents (perceptual_hash) WHERE perceptual_hash IS NOT NULL;
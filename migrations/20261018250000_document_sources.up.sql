@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- Documentos gerados a partir de outros (por exemplo, as fotos da frente e do
+-- verso juntas num PDF), com a posição de cada origem
+CREATE TABLE IF NOT EXISTS document_sources (
+    document_id UUID NOT NULL REFERENCES documents (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    source_document_id UUID NOT NULL REFERENCES documents (id) ON DELETE CASCADE,
+    PRIMARY KEY (document_id, position)
+);
+
+CREATE INDEX IF NOT EXISTS document_sources_source_document_id_idx ON document_sources (source_document_id);
@@ -0,0 +1,16 @@
+-- Add up migration script here
+-- Assinatura Ed25519 feita na aprovação: o payload assinado (hash do conteúdo e
+-- dados principais do documento, em JSON) e a assinatura destacada, em base64
+CREATE TABLE IF NOT EXISTS document_signatures (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4(),
+    document_id UUID NOT NULL REFERENCES documents (id) ON DELETE CASCADE,
+    revision INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    signed_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS document_signatures_document_id_idx ON document_signatures (document_id, signed_at);
+CREATE INDEX IF NOT EXISTS document_signatures_sha256_idx ON document_signatures (sha256);
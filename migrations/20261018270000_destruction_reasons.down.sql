@@ -0,0 +1,4 @@
+-- Add down migration script here
+DELETE FROM document_destructions WHERE retention_years IS NULL;
+ALTER TABLE document_destructions ALTER COLUMN retention_years SET NOT NULL;
+ALTER TABLE document_destructions DROP COLUMN IF EXISTS reason;
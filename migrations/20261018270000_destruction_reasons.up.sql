@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- A lixeira também destrói documentos; o registro diz o motivo, e o prazo só
+-- existe quando havia regra de retenção para o doc_type
+ALTER TABLE document_destructions ADD COLUMN IF NOT EXISTS reason TEXT NOT NULL DEFAULT 'retention';
+ALTER TABLE document_destructions ALTER COLUMN retention_years DROP NOT NULL;
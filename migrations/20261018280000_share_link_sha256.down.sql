@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE share_links DROP COLUMN IF EXISTS sha256;
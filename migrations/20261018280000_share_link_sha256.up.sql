@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- O link vale só para o conteúdo do documento quando foi criado; links antigos ficam sem esse vínculo
+ALTER TABLE share_links ADD COLUMN IF NOT EXISTS sha256 TEXT;
@@ -0,0 +1,537 @@
+// Exportação dos documentos de um usuário num ZIP montado enquanto é enviado:
+// cada arquivo sai do armazenamento direto para a resposta, sem arquivo
+// temporário. As entradas são gravadas sem compressão (PDFs e imagens já são
+// comprimidos), com data descriptor para o CRC calculado no caminho. Como o
+// tamanho só é conhecido no fim, toda entrada é gravada pronta para ZIP64; o
+// diretório central só usa ZIP64 quando uma entrada ou o arquivo passa de
+// 4 GiB. Um conteúdo que não pode ser lido fica de fora e é listado em skipped
+// no manifest.json.
+use std::io;
+
+use actix_web::{
+    get,
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web::{Data, Path},
+    HttpResponse, Responder,
+};
+use bytes::Bytes;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use futures_util::StreamExt;
+use serde_json::json;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{encryption, model::DocumentModel, scanner, storage::ByteStream, AppState};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const ZIP64_END_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const END_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+// Bit 3: tamanhos e CRC no data descriptor; bit 11: nomes em UTF-8
+const FLAGS: u16 = 0x0808;
+const VERSION: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+// Feito em Unix, para os atributos de arquivo valerem
+const VERSION_MADE_BY: u16 = (3 << 8) | VERSION_ZIP64;
+const FILE_ATTRIBUTES: u32 = 0o100644 << 16;
+
+// Quantos pedaços podem esperar o cliente antes de o envio parar
+const CHANNEL_CAPACITY: usize = 8;
+
+struct CentralEntry {
+    name: String,
+    crc32: u32,
+    size: u64,
+    offset: u64,
+    time: u16,
+    date: u16,
+}
+
+/// Como terminou a gravação de uma entrada cujo conteúdo não pôde ser lido.
+enum Added {
+    Complete,
+    /// O conteúdo falhou antes do primeiro byte; nada foi gravado.
+    Unreadable(io::Error),
+    /// O conteúdo falhou no meio; a entrada foi fechada com o que chegou.
+    Truncated(io::Error),
+}
+
+/// Escreve um ZIP num canal, entrada por entrada.
+struct ZipWriter {
+    sender: mpsc::Sender<io::Result<Bytes>>,
+    offset: u64,
+    entries: Vec<CentralEntry>,
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "client disconnected")
+}
+
+// Data e hora no formato do MS-DOS, que começa em 1980
+fn dos_datetime(time: DateTime<Utc>) -> (u16, u16) {
+    if time.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let dos_time = ((time.hour() << 11) | (time.minute() << 5) | (time.second() / 2)) as u16;
+    let dos_date = ((((time.year() - 1980) as u32) << 9) | (time.month() << 5) | time.day()) as u16;
+    (dos_time, dos_date)
+}
+
+// Valores de 32 bits que não cabem vão como 0xFFFFFFFF, com o valor real no campo extra ZIP64
+fn zip32(value: u64) -> u32 {
+    value.min(u32::MAX as u64) as u32
+}
+
+impl ZipWriter {
+    fn new(sender: mpsc::Sender<io::Result<Bytes>>) -> Self {
+        ZipWriter { sender, offset: 0, entries: Vec::new() }
+    }
+
+    async fn write(&mut self, bytes: Bytes) -> io::Result<()> {
+        self.offset += bytes.len() as u64;
+        self.sender.send(Ok(bytes)).await.map_err(|_| disconnected())
+    }
+
+    /// Grava uma entrada lendo o conteúdo de `body`. Só devolve erro se a
+    /// resposta não puder mais ser escrita; falhas de leitura do conteúdo
+    /// voltam em [`Added`] e o arquivo continua válido.
+    async fn add(&mut self, name: &str, modified: DateTime<Utc>, mut body: ByteStream) -> io::Result<Added> {
+        // O primeiro pedaço vem antes do cabeçalho, para um conteúdo ilegível não deixar entrada pela metade
+        let first = match body.next().await {
+            Some(Err(error)) => return Ok(Added::Unreadable(error)),
+            first => first,
+        };
+
+        let (time, date) = dos_datetime(modified);
+        let offset = self.offset;
+
+        let mut header = Vec::with_capacity(30 + name.len() + 20);
+        header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+        header.extend_from_slice(&FLAGS.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // sem compressão
+        header.extend_from_slice(&time.to_le_bytes());
+        header.extend_from_slice(&date.to_le_bytes());
+        header.extend_from_slice(&[0; 12]); // CRC e tamanhos vão no data descriptor
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        // Campo extra ZIP64 com os tamanhos zerados: é ele que diz ao leitor que
+        // o data descriptor traz os tamanhos em 64 bits (APPNOTE 4.3.9.2)
+        header.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(&[0; 16]);
+        self.write(header.into()).await?;
+
+        let mut hasher = crc32fast::Hasher::new();
+        let mut size: u64 = 0;
+        let mut failed = None;
+        let mut next = first;
+        while let Some(chunk) = next {
+            match chunk {
+                Ok(chunk) => {
+                    hasher.update(&chunk);
+                    size += chunk.len() as u64;
+                    self.write(chunk).await?;
+                }
+                Err(error) => {
+                    failed = Some(error);
+                    break;
+                }
+            }
+            next = body.next().await;
+        }
+        let crc32 = hasher.finalize();
+
+        // Com o campo ZIP64 no cabeçalho local, o data descriptor sempre leva os tamanhos em 64 bits
+        let mut descriptor = Vec::with_capacity(24);
+        descriptor.extend_from_slice(&DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+        descriptor.extend_from_slice(&crc32.to_le_bytes());
+        descriptor.extend_from_slice(&size.to_le_bytes());
+        descriptor.extend_from_slice(&size.to_le_bytes());
+        self.write(descriptor.into()).await?;
+
+        self.entries.push(CentralEntry { name: name.to_string(), crc32, size, offset, time, date });
+        Ok(match failed {
+            Some(error) => Added::Truncated(error),
+            None => Added::Complete,
+        })
+    }
+
+    /// Grava o diretório central e o fim do arquivo.
+    async fn finish(mut self) -> io::Result<()> {
+        let directory_offset = self.offset;
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            // Campo extra ZIP64: tamanho original, comprimido e posição, só os que não cabem em 32 bits
+            let mut extra = Vec::new();
+            if entry.size >= u32::MAX as u64 {
+                extra.extend_from_slice(&entry.size.to_le_bytes());
+                extra.extend_from_slice(&entry.size.to_le_bytes());
+            }
+            if entry.offset >= u32::MAX as u64 {
+                extra.extend_from_slice(&entry.offset.to_le_bytes());
+            }
+            let zip64 = !extra.is_empty();
+
+            directory.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            directory.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+            directory.extend_from_slice(&(if zip64 { VERSION_ZIP64 } else { VERSION }).to_le_bytes());
+            directory.extend_from_slice(&FLAGS.to_le_bytes());
+            directory.extend_from_slice(&0u16.to_le_bytes());
+            directory.extend_from_slice(&entry.time.to_le_bytes());
+            directory.extend_from_slice(&entry.date.to_le_bytes());
+            directory.extend_from_slice(&entry.crc32.to_le_bytes());
+            directory.extend_from_slice(&zip32(entry.size).to_le_bytes());
+            directory.extend_from_slice(&zip32(entry.size).to_le_bytes());
+            directory.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            directory.extend_from_slice(&(if zip64 { 4 + extra.len() as u16 } else { 0 }).to_le_bytes());
+            directory.extend_from_slice(&[0; 6]); // comentário, disco e atributos internos
+            directory.extend_from_slice(&FILE_ATTRIBUTES.to_le_bytes());
+            directory.extend_from_slice(&zip32(entry.offset).to_le_bytes());
+            directory.extend_from_slice(entry.name.as_bytes());
+            if zip64 {
+                directory.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+                directory.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+                directory.extend_from_slice(&extra);
+            }
+        }
+        let directory_size = directory.len() as u64;
+        let count = self.entries.len() as u64;
+
+        let zip64 = count >= u16::MAX as u64 || directory_offset >= u32::MAX as u64 || directory_size >= u32::MAX as u64;
+        if zip64 {
+            let end_offset = directory_offset + directory_size;
+            directory.extend_from_slice(&ZIP64_END_SIGNATURE.to_le_bytes());
+            directory.extend_from_slice(&44u64.to_le_bytes());
+            directory.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+            directory.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            directory.extend_from_slice(&[0; 8]); // discos
+            directory.extend_from_slice(&count.to_le_bytes());
+            directory.extend_from_slice(&count.to_le_bytes());
+            directory.extend_from_slice(&directory_size.to_le_bytes());
+            directory.extend_from_slice(&directory_offset.to_le_bytes());
+
+            directory.extend_from_slice(&ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+            directory.extend_from_slice(&0u32.to_le_bytes());
+            directory.extend_from_slice(&end_offset.to_le_bytes());
+            directory.extend_from_slice(&1u32.to_le_bytes());
+        }
+
+        directory.extend_from_slice(&END_SIGNATURE.to_le_bytes());
+        directory.extend_from_slice(&[0; 4]); // discos
+        let count16 = count.min(u16::MAX as u64) as u16;
+        directory.extend_from_slice(&count16.to_le_bytes());
+        directory.extend_from_slice(&count16.to_le_bytes());
+        directory.extend_from_slice(&zip32(directory_size).to_le_bytes());
+        directory.extend_from_slice(&zip32(directory_offset).to_le_bytes());
+        directory.extend_from_slice(&0u16.to_le_bytes());
+
+        self.write(directory.into()).await
+    }
+}
+
+// Caminho do arquivo dentro do ZIP; o id evita colisão entre nomes iguais
+fn archive_path(document: &DocumentModel) -> String {
+    let name = document.original_filename.replace(['/', '\\'], "_");
+    format!("documents/{}/{}", document.id, if name.is_empty() { "file" } else { &name })
+}
+
+async fn write_archive(
+    data: Data<AppState>,
+    user_id: Uuid,
+    documents: Vec<DocumentModel>,
+    sender: mpsc::Sender<io::Result<Bytes>>,
+) -> io::Result<()> {
+    let mut zip = ZipWriter::new(sender);
+
+    // Conteúdo que não passou no antivírus fica de fora, mas a linha continua no manifest
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+    for document in &documents {
+        if document.scan_status != scanner::CLEAN {
+            skipped.push(json!({
+                "document_id": document.id,
+                "reason": format!("scan_status is {}", document.scan_status)
+            }));
+            continue;
+        }
+
+        // Um conteúdo que falta ou não decifra vai para skipped, sem interromper o envio
+        let path = archive_path(document);
+        let body = match data.keyring.open(document.master_key_id.as_deref(), document.wrapped_key.as_deref()) {
+            Ok(data_key) => encryption::get(data.storage.as_ref(), &document.filename, data_key.as_ref(), None).await,
+            Err(error) => Err(error),
+        };
+        let added = match body {
+            Ok(body) => zip.add(&path, document.created_at.unwrap_or_else(Utc::now), body).await?,
+            Err(error) => Added::Unreadable(error),
+        };
+        match added {
+            Added::Complete => files.push(json!({ "document_id": document.id, "path": path })),
+            Added::Unreadable(error) => {
+                log::warn!("Skipping document {} in archive: {}", document.id, error);
+                skipped.push(json!({
+                    "document_id": document.id,
+                    "reason": format!("content could not be read: {}", error)
+                }));
+            }
+            Added::Truncated(error) => {
+                log::warn!("Document {} is incomplete in archive: {}", document.id, error);
+                skipped.push(json!({
+                    "document_id": document.id,
+                    "path": path,
+                    "reason": format!("content failed while reading, the archived file is incomplete: {}", error)
+                }));
+            }
+        }
+    }
+
+    let manifest = json!({
+        "user_id": user_id,
+        "generated_at": Utc::now(),
+        "documents": documents,
+        "files": files,
+        "skipped": skipped
+    });
+    let manifest = serde_json::to_vec_pretty(&manifest).map_err(io::Error::other)?;
+    let body: ByteStream = Box::pin(futures_util::stream::once(async move { Ok(Bytes::from(manifest)) }));
+    if let Added::Unreadable(error) | Added::Truncated(error) = zip.add("manifest.json", Utc::now(), body).await? {
+        return Err(error);
+    }
+
+    zip.finish().await
+}
+
+#[get("/users/{user_id}/documents/archive")]
+pub async fn get_user_documents_archive(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let user_id = path.into_inner();
+
+    let documents = match sqlx::query_as!(
+        DocumentModel,
+        "SELECT * FROM documents WHERE user_id = $1 AND deleted_at IS NULL ORDER BY created_at, id",
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await
+    {
+        Ok(documents) => documents,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get documents: {:?}", error)
+            }));
+        }
+    };
+
+    // Um erro no meio do envio só pode interromper a resposta, que já começou
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    let data = data.clone();
+    actix_web::rt::spawn(async move {
+        let failed = sender.clone();
+        if let Err(error) = write_archive(data, user_id, documents, sender).await {
+            log::warn!("Failed to stream archive for user {}: {}", user_id, error);
+            let _ = failed.send(Err(error)).await;
+        }
+    });
+
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("documents-{}.zip", user_id))],
+        })
+        .streaming(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Seek, SeekFrom};
+
+    use bytes::Bytes;
+    use chrono::Utc;
+    use tokio::sync::mpsc;
+
+    use super::{Added, ZipWriter};
+    use crate::storage::ByteStream;
+
+    const MIB: usize = 1024 * 1024;
+    static ZEROS: [u8; MIB] = [0; MIB];
+
+    /// O ZIP recebido do canal. Os pedaços ficam como vieram, então repetir o
+    /// mesmo `Bytes` estático não ocupa memória, e o leitor percorre os pedaços.
+    struct Received {
+        chunks: Vec<Bytes>,
+        starts: Vec<u64>,
+        len: u64,
+        position: u64,
+    }
+
+    impl Read for Received {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.position >= self.len || buf.is_empty() {
+                return Ok(0);
+            }
+            let index = self.starts.partition_point(|start| *start <= self.position) - 1;
+            let chunk = &self.chunks[index][(self.position - self.starts[index]) as usize..];
+            let read = chunk.len().min(buf.len());
+            buf[..read].copy_from_slice(&chunk[..read]);
+            self.position += read as u64;
+            Ok(read)
+        }
+    }
+
+    impl Seek for Received {
+        fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+            self.position = match position {
+                SeekFrom::Start(offset) => offset,
+                SeekFrom::End(offset) => (self.len as i64 + offset) as u64,
+                SeekFrom::Current(offset) => (self.position as i64 + offset) as u64,
+            };
+            Ok(self.position)
+        }
+    }
+
+    fn stream(chunks: Vec<io::Result<Bytes>>) -> ByteStream {
+        Box::pin(futures_util::stream::iter(chunks))
+    }
+
+    // Roda `write` com um ZipWriter e devolve o arquivo gravado
+    async fn archive<F, Fut>(write: F) -> zip::ZipArchive<Received>
+    where
+        F: FnOnce(ZipWriter) -> Fut,
+        Fut: std::future::Future<Output = io::Result<()>> + Send + 'static,
+    {
+        let (sender, mut receiver) = mpsc::channel(8);
+        let writer = tokio::spawn(write(ZipWriter::new(sender)));
+
+        let mut received = Received { chunks: Vec::new(), starts: Vec::new(), len: 0, position: 0 };
+        while let Some(chunk) = receiver.recv().await {
+            let chunk = chunk.unwrap();
+            received.starts.push(received.len);
+            received.len += chunk.len() as u64;
+            received.chunks.push(chunk);
+        }
+        writer.await.unwrap().unwrap();
+        zip::ZipArchive::new(received).unwrap()
+    }
+
+    fn read_entry(archive: &mut zip::ZipArchive<Received>, index: usize) -> (String, Vec<u8>) {
+        let mut entry = archive.by_index(index).unwrap();
+        let mut content = Vec::new();
+        // A leitura até o fim confere o CRC
+        entry.read_to_end(&mut content).unwrap();
+        (entry.name().to_string(), content)
+    }
+
+    #[tokio::test]
+    async fn round_trips_entries() {
+        let mut archive = archive(|mut zip| async move {
+            let hello = stream(vec![Ok(Bytes::from_static(b"hello"))]);
+            assert!(matches!(zip.add("hello.txt", Utc::now(), hello).await?, Added::Complete));
+            let parts = stream(vec![Ok(Bytes::from_static(b"%PDF-")), Ok(Bytes::from("1.4 ação"))]);
+            assert!(matches!(zip.add("documents/1/contrato ação.pdf", Utc::now(), parts).await?, Added::Complete));
+            let empty = stream(vec![]);
+            assert!(matches!(zip.add("empty", Utc::now(), empty).await?, Added::Complete));
+            zip.finish().await
+        })
+        .await;
+
+        assert_eq!(archive.len(), 3);
+        assert_eq!(read_entry(&mut archive, 0), ("hello.txt".to_string(), b"hello".to_vec()));
+        assert_eq!(
+            read_entry(&mut archive, 1),
+            ("documents/1/contrato ação.pdf".to_string(), "%PDF-1.4 ação".as_bytes().to_vec())
+        );
+        assert_eq!(read_entry(&mut archive, 2), ("empty".to_string(), Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn unreadable_content_leaves_no_entry() {
+        let mut archive = archive(|mut zip| async move {
+            let missing = stream(vec![Err(io::Error::new(io::ErrorKind::NotFound, "blob is missing"))]);
+            let added = zip.add("missing.pdf", Utc::now(), missing).await?;
+            assert!(matches!(added, Added::Unreadable(error) if error.kind() == io::ErrorKind::NotFound));
+            assert_eq!(zip.offset, 0);
+
+            let partial = stream(vec![
+                Ok(Bytes::from_static(b"first segment")),
+                Err(io::Error::new(io::ErrorKind::InvalidData, "tag mismatch")),
+            ]);
+            assert!(matches!(zip.add("partial.pdf", Utc::now(), partial).await?, Added::Truncated(_)));
+
+            let manifest = stream(vec![Ok(Bytes::from_static(b"{}"))]);
+            zip.add("manifest.json", Utc::now(), manifest).await?;
+            zip.finish().await
+        })
+        .await;
+
+        // O que falhou no meio fica com o que chegou, e o arquivo segue válido
+        assert_eq!(archive.len(), 2);
+        assert_eq!(read_entry(&mut archive, 0), ("partial.pdf".to_string(), b"first segment".to_vec()));
+        assert_eq!(read_entry(&mut archive, 1), ("manifest.json".to_string(), b"{}".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn local_headers_announce_zip64_descriptors() {
+        let archive = archive(|mut zip| async move {
+            zip.add("a.txt", Utc::now(), stream(vec![Ok(Bytes::from_static(b"abc"))])).await?;
+            zip.finish().await
+        })
+        .await;
+        let mut raw = Vec::new();
+        let mut received = archive.into_inner();
+        received.seek(SeekFrom::Start(0)).unwrap();
+        received.read_to_end(&mut raw).unwrap();
+
+        // Cabeçalho local: nome com 5 bytes e campo extra ZIP64 de 20 bytes, com os tamanhos zerados
+        assert_eq!(u16::from_le_bytes([raw[26], raw[27]]), 5);
+        assert_eq!(u16::from_le_bytes([raw[28], raw[29]]), 20);
+        assert_eq!(&raw[35..39], &[0x01, 0x00, 16, 0x00]);
+        assert_eq!(&raw[39..55], &[0; 16]);
+        assert_eq!(&raw[55..58], b"abc");
+
+        // Data descriptor: assinatura, CRC e os dois tamanhos em 64 bits
+        assert_eq!(&raw[58..62], &0x08074b50u32.to_le_bytes());
+        assert_eq!(&raw[66..74], &3u64.to_le_bytes());
+        assert_eq!(&raw[74..82], &3u64.to_le_bytes());
+    }
+
+    // Grava mais de 4 GiB; rode com `cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn entries_over_4_gib_use_zip64() {
+        const LARGE_MIB: usize = 4 * 1024 + 1;
+
+        let mut archive = archive(|mut zip| async move {
+            let zeros = (0..LARGE_MIB).map(|_| Ok(Bytes::from_static(&ZEROS))).collect();
+            assert!(matches!(zip.add("large.bin", Utc::now(), stream(zeros)).await?, Added::Complete));
+            // Esta começa depois dos 4 GiB, então a posição também vai no campo ZIP64
+            let after = stream(vec![Ok(Bytes::from_static(b"after the large one"))]);
+            zip.add("after.txt", Utc::now(), after).await?;
+            zip.finish().await
+        })
+        .await;
+
+        let mut zeros = crc32fast::Hasher::new();
+        zeros.update(&ZEROS);
+        let mut expected = crc32fast::Hasher::new();
+        for _ in 0..LARGE_MIB {
+            expected.combine(&zeros);
+        }
+
+        let large = archive.by_index(0).unwrap();
+        assert_eq!(large.size(), (LARGE_MIB * MIB) as u64);
+        assert_eq!(large.crc32(), expected.finalize());
+        drop(large);
+        assert_eq!(read_entry(&mut archive, 1), ("after.txt".to_string(), b"after the large one".to_vec()));
+    }
+}
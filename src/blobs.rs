@@ -0,0 +1,99 @@
+// Contagem de referências do conteúdo armazenado. Documentos com o mesmo
+// SHA-256 apontam para o mesmo blob, que só sai do armazenamento quando o
+// último deles é apagado, e só depois que essa exclusão foi confirmada.
+use sqlx::{Pool, Postgres, Transaction};
+
+use crate::{storage::Storage, thumbnails};
+
+/// Registra mais uma referência ao blob `key`, criando-o se preciso.
+///
+/// Devolve `true` quando o blob é novo e o conteúdo ainda precisa ser gravado.
+/// A linha do blob fica travada até o fim da transação, o que impede que um
+/// `release` concorrente apague o conteúdo no meio do caminho; a trava da
+/// chave faz o mesmo com `remove_unused`.
+pub async fn acquire(
+    tx: &mut Transaction<'_, Postgres>,
+    key: &str,
+    sha256: &str,
+    size_bytes: i64,
+) -> Result<bool, sqlx::Error> {
+    lock(tx, key).await?;
+    let row = sqlx::query!(
+        r#"INSERT INTO blobs (key, sha256, size_bytes, ref_count)
+           VALUES ($1, $2, $3, 1)
+           ON CONFLICT (key) DO UPDATE SET ref_count = blobs.ref_count + 1
+           RETURNING (xmax = 0) AS "inserted!""#,
+        key,
+        sha256,
+        size_bytes
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    Ok(row.inserted)
+}
+
+/// Remove uma referência ao blob `key` e diz se era a última.
+///
+/// O conteúdo continua no armazenamento: quem chamou passa as chaves que
+/// ficaram sem referência para `remove_unused` depois do commit, para que um
+/// rollback não deixe o banco apontando para um arquivo apagado.
+pub async fn release(tx: &mut Transaction<'_, Postgres>, key: &str) -> Result<bool, sqlx::Error> {
+    let remaining = sqlx::query_scalar!(
+        "UPDATE blobs SET ref_count = ref_count - 1 WHERE key = $1 RETURNING ref_count",
+        key
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    // Sem linha em blobs o conteúdo não é compartilhado com ninguém
+    if remaining.is_some_and(|count| count > 0) {
+        return Ok(false);
+    }
+    sqlx::query!("DELETE FROM blobs WHERE key = $1", key)
+        .execute(&mut *tx)
+        .await?;
+    Ok(true)
+}
+
+/// Apaga do armazenamento o conteúdo e as miniaturas das chaves que ninguém
+/// mais usa. Roda depois do commit de quem soltou a última referência; um
+/// `acquire` que chegou no meio já gravou o conteúdo de novo e fica com ele.
+pub async fn remove_unused(db: &Pool<Postgres>, storage: &dyn Storage, keys: &[String]) {
+    for key in keys {
+        let removed = async {
+            let mut tx = db.begin().await?;
+            lock(&mut tx, key).await?;
+            let in_use = sqlx::query_scalar!(
+                r#"SELECT EXISTS (SELECT 1 FROM blobs WHERE key = $1)
+                       OR EXISTS (SELECT 1 FROM document_revisions WHERE filename = $1) AS "in_use!""#,
+                key
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+            if !in_use {
+                if let Err(error) = storage.delete(key).await {
+                    log::warn!("Failed to remove stored file {}: {}", key, error);
+                }
+                thumbnails::remove(storage, key).await;
+            }
+            tx.commit().await
+        }
+        .await;
+
+        if let Err(error) = removed {
+            log::warn!("Failed to remove unused content {}: {:?}", key, error);
+        }
+    }
+}
+
+/// Serializa, por chave, quem grava, cifra e apaga o conteúdo.
+pub async fn lock(tx: &mut Transaction<'_, Postgres>, key: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"SELECT true AS "locked!" FROM pg_advisory_xact_lock(hashtextextended($1, 1))"#,
+        key
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,252 @@
+// Documentos de identificação brasileiros: dígitos verificadores de CPF e CNPJ
+// (inclusive o CNPJ alfanumérico) e normalização do número do RG por estado.
+use actix_web::{
+    post,
+    web::{Json, Path},
+    HttpResponse, Responder,
+};
+use serde_json::{json, Value};
+
+use crate::schema::ValidateIdentitySchema;
+
+pub const CPF: &str = "cpf";
+pub const CNPJ: &str = "cnpj";
+pub const RG: &str = "rg";
+
+const STATES: [&str; 27] = [
+    "AC", "AL", "AP", "AM", "BA", "CE", "DF", "ES", "GO", "MA", "MT", "MS", "MG", "PA",
+    "PB", "PR", "PE", "PI", "RJ", "RN", "RS", "RO", "RR", "SC", "SP", "SE", "TO",
+];
+
+// Pontuação aceita nas máscaras (000.000.000-00, 00.000.000/0000-00)
+fn strip(value: &str) -> Result<String, String> {
+    let stripped: String = value
+        .chars()
+        .filter(|c| !matches!(c, '.' | '-' | '/') && !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    if stripped.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Ok(stripped)
+    } else {
+        Err("contains invalid characters".to_string())
+    }
+}
+
+fn repeated(value: &str) -> bool {
+    value.chars().all(|c| value.starts_with(c))
+}
+
+/// Confere um CPF e devolve-o no formato 000.000.000-00.
+pub fn validate_cpf(value: &str) -> Result<String, String> {
+    let cpf = strip(value)?;
+    if cpf.len() != 11 || !cpf.chars().all(|c| c.is_ascii_digit()) {
+        return Err("a CPF has 11 digits".to_string());
+    }
+    if repeated(&cpf) {
+        return Err("a CPF cannot repeat a single digit".to_string());
+    }
+
+    let digits: Vec<u32> = cpf.chars().map(|c| c.to_digit(10).unwrap()).collect();
+    let check = |length: usize| {
+        let sum: u32 = digits[..length].iter().zip((2..=length as u32 + 1).rev()).map(|(d, w)| d * w).sum();
+        sum * 10 % 11 % 10
+    };
+    if check(9) != digits[9] || check(10) != digits[10] {
+        return Err("CPF check digits do not match".to_string());
+    }
+
+    Ok(format!("{}.{}.{}-{}", &cpf[0..3], &cpf[3..6], &cpf[6..9], &cpf[9..11]))
+}
+
+/// Confere um CNPJ, numérico ou alfanumérico, e devolve-o no formato
+/// 00.000.000/0000-00.
+pub fn validate_cnpj(value: &str) -> Result<String, String> {
+    let cnpj = strip(value)?;
+    if cnpj.len() != 14 || !cnpj[12..].chars().all(|c| c.is_ascii_digit()) {
+        return Err("a CNPJ has 12 letters or digits followed by 2 check digits".to_string());
+    }
+    if repeated(&cnpj) {
+        return Err("a CNPJ cannot repeat a single character".to_string());
+    }
+
+    // No CNPJ alfanumérico cada caractere vale o seu código ASCII menos 48
+    let values: Vec<u32> = cnpj.chars().map(|c| c as u32 - '0' as u32).collect();
+    let check = |length: usize| {
+        let sum: u32 = values[..length]
+            .iter()
+            .rev()
+            .zip((2..=9).cycle())
+            .map(|(v, w)| v * w)
+            .sum();
+        match sum % 11 {
+            0 | 1 => 0,
+            rest => 11 - rest,
+        }
+    };
+    if check(12) != values[12] || check(13) != values[13] {
+        return Err("CNPJ check digits do not match".to_string());
+    }
+
+    Ok(format!("{}.{}.{}/{}-{}", &cnpj[0..2], &cnpj[2..5], &cnpj[5..8], &cnpj[8..12], &cnpj[12..14]))
+}
+
+/// Normaliza um RG conforme o formato do estado emissor.
+///
+/// SP (00.000.000-X), RJ (00.000.000-0) e MG (MG-00.000.000) têm máscara
+/// própria; nos demais estados fica só a sequência de dígitos.
+pub fn normalize_rg(value: &str, state: &str) -> Result<String, String> {
+    let state = state.trim().to_uppercase();
+    if !STATES.contains(&state.as_str()) {
+        return Err(format!("unknown state {}", state));
+    }
+
+    let rg = strip(value)?;
+    let rg = rg.strip_prefix(state.as_str()).unwrap_or(&rg);
+    let (body, last) = rg.split_at(rg.len().saturating_sub(1));
+    let digits = |value: &str| !value.is_empty() && value.chars().all(|c| c.is_ascii_digit());
+
+    match state.as_str() {
+        "SP" if rg.len() == 9 && digits(body) && (digits(last) || last == "X") => {
+            Ok(format!("{}.{}.{}-{}", &rg[0..2], &rg[2..5], &rg[5..8], last))
+        }
+        "SP" => Err("an RG from SP has 8 digits and a check digit (0-9 or X)".to_string()),
+        "RJ" if rg.len() == 9 && digits(rg) => Ok(format!("{}.{}.{}-{}", &rg[0..2], &rg[2..5], &rg[5..8], last)),
+        "RJ" => Err("an RG from RJ has 9 digits".to_string()),
+        "MG" if rg.len() == 8 && digits(rg) => Ok(format!("MG-{}.{}.{}", &rg[0..2], &rg[2..5], &rg[5..8])),
+        "MG" => Err("an RG from MG has 8 digits".to_string()),
+        _ if (4..=14).contains(&rg.len()) && digits(body) && (digits(last) || last == "X") => Ok(rg.to_string()),
+        _ => Err(format!("an RG from {} has 4 to 14 digits", state)),
+    }
+}
+
+// Troca o valor de `field` pelo normalizado, ou registra o erro
+fn normalize_field(
+    metadata: &mut Value,
+    field: &str,
+    errors: &mut Vec<String>,
+    normalize: impl FnOnce(&str) -> Result<String, String>,
+) {
+    if let Some(Value::String(value)) = metadata.get_mut(field) {
+        match normalize(value) {
+            Ok(normalized) => *value = normalized,
+            Err(message) => errors.push(format!("/{}: {}", field, message)),
+        }
+    }
+}
+
+/// Confere e normaliza o número dos documentos `cpf`, `cnpj` e `rg` no
+/// metadata; os demais doc_types passam sem mudança.
+pub fn normalize_metadata(doc_type: &str, metadata: &mut Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    match doc_type {
+        CPF => normalize_field(metadata, "document_number", &mut errors, validate_cpf),
+        CNPJ => normalize_field(metadata, "document_number", &mut errors, validate_cnpj),
+        RG if metadata.get("document_number").is_some() => {
+            match metadata.get("issuing_state").and_then(Value::as_str).map(str::to_uppercase) {
+                Some(state) => {
+                    normalize_field(metadata, "document_number", &mut errors, |value| normalize_rg(value, &state));
+                    if STATES.contains(&state.as_str()) {
+                        metadata["issuing_state"] = json!(state);
+                    }
+                }
+                None => errors.push("/issuing_state: required to normalize the RG number".to_string()),
+            }
+        }
+        _ => {}
+    }
+    errors
+}
+
+// Validação avulsa para o front-end conferir o número antes do upload
+#[post("/validate/{kind}")]
+pub async fn validate_identity(path: Path<String>, body: Json<ValidateIdentitySchema>) -> impl Responder {
+    let kind = path.into_inner();
+
+    let result = match kind.as_str() {
+        CPF => validate_cpf(&body.value),
+        CNPJ => validate_cnpj(&body.value),
+        RG => match &body.state {
+            Some(state) => normalize_rg(&body.value, state),
+            None => {
+                return HttpResponse::BadRequest().json(json!({
+                    "status": "fail",
+                    "message": "state is required to validate an RG"
+                }));
+            }
+        },
+        _ => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Unknown kind {}; expected cpf, cnpj or rg", kind)
+            }));
+        }
+    };
+
+    match result {
+        Ok(normalized) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "kind": kind,
+            "valid": true,
+            "normalized": normalized
+        })),
+        Err(message) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "kind": kind,
+            "valid": false,
+            "message": message
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_and_formats_cpf() {
+        assert_eq!(validate_cpf("52998224725"), Ok("529.982.247-25".to_string()));
+        assert_eq!(validate_cpf(" 529.982.247-25 "), Ok("529.982.247-25".to_string()));
+        assert_eq!(validate_cpf("529.982.247-26"), Err("CPF check digits do not match".to_string()));
+        assert_eq!(validate_cpf("111.111.111-11"), Err("a CPF cannot repeat a single digit".to_string()));
+        assert_eq!(validate_cpf("5299822472"), Err("a CPF has 11 digits".to_string()));
+        assert_eq!(validate_cpf("529.982.247_25"), Err("contains invalid characters".to_string()));
+    }
+
+    #[test]
+    fn validates_numeric_cnpj() {
+        assert_eq!(validate_cnpj("11222333000181"), Ok("11.222.333/0001-81".to_string()));
+        assert_eq!(validate_cnpj("11.222.333/0001-82"), Err("CNPJ check digits do not match".to_string()));
+        assert_eq!(validate_cnpj("00.000.000/0000-00"), Err("a CNPJ cannot repeat a single character".to_string()));
+    }
+
+    #[test]
+    fn validates_alphanumeric_cnpj() {
+        // Exemplo da Receita Federal para o CNPJ alfanumérico
+        assert_eq!(validate_cnpj("12.abc.345/01de-35"), Ok("12.ABC.345/01DE-35".to_string()));
+        assert_eq!(validate_cnpj("12.ABC.345/01DE-36"), Err("CNPJ check digits do not match".to_string()));
+        assert!(validate_cnpj("12.ABC.345/01DE-3X").is_err());
+    }
+
+    #[test]
+    fn normalizes_rg_by_state() {
+        assert_eq!(normalize_rg("12345678x", "sp"), Ok("12.345.678-X".to_string()));
+        assert_eq!(normalize_rg("12.345.678-9", "RJ"), Ok("12.345.678-9".to_string()));
+        assert_eq!(normalize_rg("MG-12.345.678", "MG"), Ok("MG-12.345.678".to_string()));
+        assert_eq!(normalize_rg("1234567", "BA"), Ok("1234567".to_string()));
+        assert!(normalize_rg("1234567", "SP").is_err());
+        assert_eq!(normalize_rg("1234567", "XX"), Err("unknown state XX".to_string()));
+    }
+
+    #[test]
+    fn normalizes_metadata_in_place() {
+        let mut metadata = json!({"document_number": "12345678x", "issuing_state": "sp"});
+        assert!(normalize_metadata(RG, &mut metadata).is_empty());
+        assert_eq!(metadata, json!({"document_number": "12.345.678-X", "issuing_state": "SP"}));
+
+        let mut metadata = json!({"document_number": "12345678901"});
+        assert_eq!(normalize_metadata(CPF, &mut metadata), ["/document_number: CPF check digits do not match"]);
+        let mut metadata = json!({"document_number": "123"});
+        assert_eq!(normalize_metadata(RG, &mut metadata), ["/issuing_state: required to normalize the RG number"]);
+        assert!(normalize_metadata("passport", &mut json!({"document_number": "x"})).is_empty());
+    }
+}
@@ -0,0 +1,348 @@
+// Registro dos tipos de documento. O doc_type enviado pelo cliente é trocado
+// pela chave canônica (aceitando os aliases) e o metadata do documento é
+// validado contra o JSON Schema do tipo.
+use actix_web::{
+    delete, get, patch, post,
+    web::{Data, Json, Path},
+    HttpResponse, Responder,
+};
+use serde_json::{json, Value};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    br_ids,
+    model::DocumentTypeModel,
+    schema::{CreateDocumentTypeSchema, UpdateDocumentTypeSchema},
+    upload::UploadError,
+    AppState,
+};
+
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+const UNIQUE_VIOLATION: &str = "23505";
+
+/// Forma canônica de uma chave ou alias: minúsculas, com `_` no lugar de
+/// qualquer sequência fora de `[a-z0-9_]`.
+pub fn normalize(raw: &str) -> String {
+    let mut key = String::new();
+    let mut separator = false;
+    for c in raw.trim().to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            key.push(c);
+            separator = false;
+        } else if !separator {
+            key.push('_');
+            separator = true;
+        }
+    }
+    key
+}
+
+/// Busca um tipo pela chave ou por um alias.
+pub async fn resolve(db: &Pool<Postgres>, doc_type: &str) -> Result<Option<DocumentTypeModel>, sqlx::Error> {
+    let key = normalize(doc_type);
+    sqlx::query_as!(
+        DocumentTypeModel,
+        "SELECT * FROM document_types WHERE key = $1 OR $1 = ANY (aliases) ORDER BY key = $1 DESC LIMIT 1",
+        key
+    )
+    .fetch_optional(db)
+    .await
+}
+
+// Por padrão o jsonschema não confere "format"; as datas dos schemas ("format": "date") precisam dele
+fn validator(schema: &Value) -> Result<jsonschema::Validator, String> {
+    jsonschema::options()
+        .should_validate_formats(true)
+        .build(schema)
+        .map_err(|error| error.to_string())
+}
+
+/// Erros de validação do metadata contra o JSON Schema, um por campo.
+pub fn metadata_errors(schema: &Value, metadata: &Value) -> Vec<String> {
+    if !metadata.is_object() {
+        return vec!["metadata must be a JSON object".to_string()];
+    }
+
+    match validator(schema) {
+        Ok(validator) => validator
+            .iter_errors(metadata)
+            .map(|error| {
+                let path = error.instance_path.to_string();
+                if path.is_empty() {
+                    error.to_string()
+                } else {
+                    format!("{}: {}", path, error)
+                }
+            })
+            .collect(),
+        Err(error) => vec![format!("The metadata_schema of this doc_type is invalid: {}", error)],
+    }
+}
+
+/// Confere o doc_type e o metadata de um documento e devolve a chave canônica
+/// junto com o metadata normalizado (números de CPF, CNPJ e RG formatados).
+pub async fn check(db: &Pool<Postgres>, doc_type: &str, metadata: &Value) -> Result<(String, Value), UploadError> {
+    let document_type = resolve(db, doc_type)
+        .await
+        .map_err(UploadError::Database)?
+        .ok_or_else(|| UploadError::Invalid(format!("Unknown doc_type: {}", doc_type.trim())))?;
+
+    let mut metadata = metadata.clone();
+    let mut errors = metadata_errors(&document_type.metadata_schema, &metadata);
+    if errors.is_empty() {
+        errors = br_ids::normalize_metadata(&document_type.key, &mut metadata);
+    }
+    if !errors.is_empty() {
+        return Err(UploadError::InvalidMetadata {
+            doc_type: document_type.key,
+            errors,
+        });
+    }
+
+    Ok((document_type.key, metadata))
+}
+
+fn fail(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "status": "fail",
+        "message": message
+    }))
+}
+
+fn not_found(key: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "status": "not found",
+        "message": format!("Document type {} not found", key)
+    }))
+}
+
+fn database_error(action: &str, error: sqlx::Error) -> HttpResponse {
+    let code = error.as_database_error().and_then(|error| error.code()).map(|code| code.to_string());
+    match code.as_deref() {
+        Some(FOREIGN_KEY_VIOLATION) => HttpResponse::Conflict().json(json!({
+            "status": "fail",
+            "message": "Document type is still used by documents or uploads"
+        })),
+        Some(UNIQUE_VIOLATION) => HttpResponse::Conflict().json(json!({
+            "status": "fail",
+            "message": "Document type already exists"
+        })),
+        _ => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to {} document type: {:?}", action, error)
+        })),
+    }
+}
+
+// Um JSON Schema que não compila nunca aceitaria documento algum
+fn check_schema(schema: &Value) -> Result<(), HttpResponse> {
+    if !schema.is_object() {
+        return Err(fail("metadata_schema must be a JSON object".to_string()));
+    }
+    validator(schema)
+        .map(|_| ())
+        .map_err(|error| fail(format!("Invalid metadata_schema: {}", error)))
+}
+
+// Aliases não podem coincidir com a chave ou os aliases de outro tipo
+async fn check_aliases(db: &Pool<Postgres>, key: &str, aliases: &[String]) -> Result<(), HttpResponse> {
+    if aliases.iter().any(|alias| alias.is_empty() || alias == key) {
+        return Err(fail("aliases must be non-empty and differ from the key".to_string()));
+    }
+
+    let taken = sqlx::query_scalar!(
+        "SELECT key FROM document_types WHERE key <> $1 AND (key = ANY ($2) OR aliases && $2)",
+        key,
+        aliases
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|error| database_error("check", error))?;
+
+    if taken.is_empty() {
+        Ok(())
+    } else {
+        Err(HttpResponse::Conflict().json(json!({
+            "status": "fail",
+            "message": format!("Aliases clash with document types: {}", taken.join(", "))
+        })))
+    }
+}
+
+#[get("/admin/document-types")]
+pub async fn get_document_types(data: Data<AppState>) -> impl Responder {
+    match sqlx::query_as!(DocumentTypeModel, "SELECT * FROM document_types ORDER BY key")
+        .fetch_all(&data.db)
+        .await
+    {
+        Ok(document_types) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "results": document_types.len(),
+            "document_types": document_types
+        })),
+        Err(error) => database_error("list", error),
+    }
+}
+
+#[get("/admin/document-types/{key}")]
+pub async fn get_document_type(path: Path<String>, data: Data<AppState>) -> impl Responder {
+    let key = path.into_inner();
+
+    match sqlx::query_as!(DocumentTypeModel, "SELECT * FROM document_types WHERE key = $1", key)
+        .fetch_optional(&data.db)
+        .await
+    {
+        Ok(Some(document_type)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "document_type": document_type
+        })),
+        Ok(None) => not_found(&key),
+        Err(error) => database_error("get", error),
+    }
+}
+
+#[post("/admin/document-types")]
+pub async fn create_document_type(body: Json<CreateDocumentTypeSchema>, data: Data<AppState>) -> impl Responder {
+    let body = body.into_inner();
+
+    if body.key.is_empty() || normalize(&body.key) != body.key {
+        return fail("key must contain only lowercase letters, digits and underscores".to_string());
+    }
+    let display_name = body.display_name.trim();
+    if display_name.is_empty() {
+        return fail("display_name must not be empty".to_string());
+    }
+    let metadata_schema = body.metadata_schema.unwrap_or_else(|| json!({"type": "object"}));
+    if let Err(response) = check_schema(&metadata_schema) {
+        return response;
+    }
+    let aliases: Vec<String> = body.aliases.unwrap_or_default().iter().map(|alias| normalize(alias)).collect();
+    if let Err(response) = check_aliases(&data.db, &body.key, &aliases).await {
+        return response;
+    }
+
+    match sqlx::query_as!(
+        DocumentTypeModel,
+        r#"INSERT INTO document_types (key, display_name, metadata_schema, aliases)
+           VALUES ($1, $2, $3, $4)
+           RETURNING *"#,
+        body.key,
+        display_name,
+        metadata_schema,
+        &aliases
+    )
+    .fetch_one(&data.db)
+    .await
+    {
+        Ok(document_type) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "document_type": document_type
+        })),
+        Err(error) => database_error("create", error),
+    }
+}
+
+// O novo schema vale para as próximas gravações; documentos já salvos não são revalidados
+#[patch("/admin/document-types/{key}")]
+pub async fn update_document_type(
+    path: Path<String>,
+    body: Json<UpdateDocumentTypeSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    let key = path.into_inner();
+    let body = body.into_inner();
+
+    let display_name = body.display_name.as_deref().map(str::trim);
+    if display_name == Some("") {
+        return fail("display_name must not be empty".to_string());
+    }
+    if let Some(schema) = &body.metadata_schema {
+        if let Err(response) = check_schema(schema) {
+            return response;
+        }
+    }
+    let aliases: Option<Vec<String>> = body
+        .aliases
+        .map(|aliases| aliases.iter().map(|alias| normalize(alias)).collect());
+    if let Some(aliases) = &aliases {
+        if let Err(response) = check_aliases(&data.db, &key, aliases).await {
+            return response;
+        }
+    }
+
+    match sqlx::query_as!(
+        DocumentTypeModel,
+        r#"UPDATE document_types
+           SET display_name = COALESCE($2, display_name),
+               metadata_schema = COALESCE($3, metadata_schema),
+               aliases = COALESCE($4, aliases),
+               updated_at = now()
+           WHERE key = $1
+           RETURNING *"#,
+        key,
+        display_name,
+        body.metadata_schema,
+        aliases.as_deref()
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(document_type)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "document_type": document_type
+        })),
+        Ok(None) => not_found(&key),
+        Err(error) => database_error("update", error),
+    }
+}
+
+#[delete("/admin/document-types/{key}")]
+pub async fn delete_document_type(path: Path<String>, data: Data<AppState>) -> impl Responder {
+    let key = path.into_inner();
+
+    match sqlx::query!("DELETE FROM document_types WHERE key = $1", key)
+        .execute(&data.db)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => not_found(&key),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(error) => database_error("delete", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::metadata_errors;
+
+    fn passport_schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "document_number": {"type": "string", "minLength": 1},
+                "expires_on": {"type": "string", "format": "date"}
+            }
+        })
+    }
+
+    #[test]
+    fn accepts_matching_metadata() {
+        let errors = metadata_errors(&passport_schema(), &json!({"document_number": "X1", "expires_on": "2030-01-31"}));
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn checks_date_format() {
+        for value in ["not-a-date", "2030-02-30", "31/01/2030"] {
+            let errors = metadata_errors(&passport_schema(), &json!({ "expires_on": value }));
+            assert_eq!(errors.len(), 1, "{}", value);
+            assert!(errors[0].starts_with("/expires_on"), "{}", errors[0]);
+        }
+    }
+
+    #[test]
+    fn rejects_non_object_metadata() {
+        assert_eq!(metadata_errors(&passport_schema(), &json!([1, 2])), vec!["metadata must be a JSON object"]);
+    }
+}
@@ -0,0 +1,364 @@
+use actix_web::{
+    http::{
+        header::{
+            self, ByteRangeSpec, ContentDisposition, ContentRange, ContentRangeSpec, DispositionParam,
+            DispositionType, EntityTag, ExtendedValue, Header, IfMatch, IfNoneMatch, IfRange, Range,
+        },
+        StatusCode,
+    },
+    HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use serde_json::json;
+use std::io::ErrorKind;
+
+use crate::{
+    encryption,
+    model::{DocumentModel, DocumentRevisionModel},
+    scanner, AppState,
+};
+
+/// O que deve ser enviado ao cliente depois de avaliar os cabeçalhos
+/// condicionais (If-Match, If-None-Match, If-Range) e o Range.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Selection {
+    Full,
+    Partial { start: u64, end: u64 },
+    NotModified,
+    PreconditionFailed,
+    Unsatisfiable,
+}
+
+/// O que é preciso saber de um conteúdo armazenado para servi-lo.
+pub struct StoredContent<'a> {
+    pub key: &'a str,
+    pub sha256: &'a str,
+    pub mime_type: &'a str,
+    pub original_filename: &'a str,
+    pub scan_status: &'a str,
+    pub master_key_id: Option<&'a str>,
+    pub wrapped_key: Option<&'a str>,
+}
+
+impl<'a> From<&'a DocumentModel> for StoredContent<'a> {
+    fn from(document: &'a DocumentModel) -> Self {
+        StoredContent {
+            key: &document.filename,
+            sha256: &document.sha256,
+            mime_type: &document.mime_type,
+            original_filename: &document.original_filename,
+            scan_status: &document.scan_status,
+            master_key_id: document.master_key_id.as_deref(),
+            wrapped_key: document.wrapped_key.as_deref(),
+        }
+    }
+}
+
+impl<'a> From<&'a DocumentRevisionModel> for StoredContent<'a> {
+    fn from(revision: &'a DocumentRevisionModel) -> Self {
+        StoredContent {
+            key: &revision.filename,
+            sha256: &revision.sha256,
+            mime_type: &revision.mime_type,
+            original_filename: &revision.original_filename,
+            scan_status: &revision.scan_status,
+            master_key_id: revision.master_key_id.as_deref(),
+            wrapped_key: revision.wrapped_key.as_deref(),
+        }
+    }
+}
+
+/// ETag forte derivado do SHA-256 do conteúdo; sem hash (`sha256` vazio)
+/// não há ETag, em vez de um `""` que valeria para qualquer conteúdo.
+pub fn etag(content: &StoredContent) -> Option<EntityTag> {
+    if content.sha256.is_empty() {
+        return None;
+    }
+    Some(EntityTag::new_strong(content.sha256.to_string()))
+}
+
+/// Avalia a requisição na ordem da RFC 9110 §13.2.2.
+///
+/// Só um intervalo por requisição é atendido; pedidos com vários intervalos
+/// recebem o conteúdo inteiro, o que a RFC permite. Sem `etag`, nenhuma
+/// ETag enviada pelo cliente coincide.
+pub fn select(req: &HttpRequest, etag: Option<&EntityTag>, length: u64) -> Selection {
+    let matches = |tag: &EntityTag, strong: bool| match etag {
+        Some(etag) if strong => tag.strong_eq(etag),
+        Some(etag) => tag.weak_eq(etag),
+        None => false,
+    };
+
+    if req.headers().contains_key(header::IF_MATCH) {
+        match IfMatch::parse(req) {
+            Ok(IfMatch::Any) => {}
+            Ok(IfMatch::Items(tags)) if tags.iter().any(|tag| matches(tag, true)) => {}
+            _ => return Selection::PreconditionFailed,
+        }
+    }
+
+    if req.headers().contains_key(header::IF_NONE_MATCH) {
+        match IfNoneMatch::parse(req) {
+            Ok(IfNoneMatch::Any) => return Selection::NotModified,
+            Ok(IfNoneMatch::Items(tags)) if tags.iter().any(|tag| matches(tag, false)) => {
+                return Selection::NotModified;
+            }
+            _ => {}
+        }
+    }
+
+    if !req.headers().contains_key(header::RANGE) {
+        return Selection::Full;
+    }
+
+    // Se o cliente tem uma versão diferente, ignora o Range e manda tudo
+    if req.headers().contains_key(header::IF_RANGE) {
+        match IfRange::parse(req) {
+            Ok(IfRange::EntityTag(tag)) if matches(&tag, true) => {}
+            _ => return Selection::Full,
+        }
+    }
+
+    match Range::parse(req) {
+        Ok(Range::Bytes(specs)) if specs.len() == 1 => satisfiable(&specs[0], length),
+        _ => Selection::Full,
+    }
+}
+
+fn satisfiable(spec: &ByteRangeSpec, length: u64) -> Selection {
+    match spec.to_satisfiable_range(length) {
+        Some((start, end)) => Selection::Partial { start, end },
+        None => Selection::Unsatisfiable,
+    }
+}
+
+/// Content-Disposition com o nome original; o parâmetro `filename*` carrega
+/// nomes com caracteres fora do ASCII.
+pub fn content_disposition(original_filename: &str) -> ContentDisposition {
+    let ascii_name: String = original_filename
+        .chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() && c != '"' { c } else { '_' })
+        .collect();
+
+    let mut parameters = vec![DispositionParam::Filename(ascii_name)];
+    if !original_filename.is_ascii() {
+        parameters.push(DispositionParam::FilenameExt(ExtendedValue {
+            charset: header::Charset::Ext("UTF-8".to_string()),
+            language_tag: None,
+            value: original_filename.as_bytes().to_vec(),
+        }));
+    }
+
+    ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters,
+    }
+}
+
+/// Só conteúdo aprovado pelo antivírus sai: o infectado fica em quarentena
+/// (403) e o ainda não verificado, ou que falhou na verificação, dá 409.
+pub fn check_scan(scan_status: &str) -> Result<(), HttpResponse> {
+    match scan_status {
+        scanner::CLEAN => Ok(()),
+        scanner::INFECTED => Err(HttpResponse::Forbidden().json(json!({
+            "status": "fail",
+            "message": "Document content is quarantined: it failed the malware scan"
+        }))),
+        scan_status => Err(HttpResponse::Conflict().json(json!({
+            "status": "fail",
+            "message": format!("Document content is not available until it passes the malware scan (scan_status: {})", scan_status)
+        }))),
+    }
+}
+
+/// Serve um conteúdo do armazenamento, decifrado e respeitando Range e os
+/// cabeçalhos condicionais; antes, passa por `check_scan`.
+pub async fn serve(req: &HttpRequest, data: &AppState, content: StoredContent<'_>) -> HttpResponse {
+    if let Err(response) = check_scan(content.scan_status) {
+        return response;
+    }
+
+    let data_key = match data.keyring.open(content.master_key_id, content.wrapped_key) {
+        Ok(data_key) => data_key,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to decrypt document content: {}", error)
+            }));
+        }
+    };
+    let storage = data.storage.as_ref();
+
+    let length = match encryption::size(storage, content.key, data_key.as_ref()).await {
+        Ok(length) => length,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Document content not available: {}", error)
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to read document content: {}", error)
+            }));
+        }
+    };
+
+    let selection = select(req, etag(&content).as_ref(), length);
+    let range = match selection {
+        Selection::Full => None,
+        Selection::Partial { start, end } => Some((start, end)),
+        _ => return response_for(&content, &selection, length).finish(),
+    };
+
+    match encryption::get(storage, content.key, data_key.as_ref(), range).await {
+        Ok(body) => response_for(&content, &selection, length).streaming(body),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to read document content: {}", error)
+        })),
+    }
+}
+
+/// Monta o início da resposta de um conteúdo; o chamador só precisa anexar
+/// o corpo (inteiro ou o intervalo selecionado).
+fn response_for(content: &StoredContent, selection: &Selection, length: u64) -> HttpResponseBuilder {
+    let mut builder = match selection {
+        Selection::Full => HttpResponse::Ok(),
+        Selection::Partial { .. } => HttpResponse::PartialContent(),
+        Selection::NotModified => HttpResponse::NotModified(),
+        Selection::PreconditionFailed => HttpResponse::PreconditionFailed(),
+        Selection::Unsatisfiable => HttpResponse::build(StatusCode::RANGE_NOT_SATISFIABLE),
+    };
+
+    if let Some(etag) = etag(content) {
+        builder.insert_header(header::ETag(etag));
+    }
+    builder.insert_header((header::ACCEPT_RANGES, "bytes"));
+
+    match selection {
+        Selection::Full => {
+            builder
+                .insert_header((header::CONTENT_TYPE, content.mime_type))
+                .insert_header(content_disposition(content.original_filename));
+        }
+        Selection::Partial { start, end } => {
+            builder
+                .insert_header((header::CONTENT_TYPE, content.mime_type))
+                .insert_header(content_disposition(content.original_filename))
+                .insert_header(ContentRange(ContentRangeSpec::Bytes {
+                    range: Some((*start, *end)),
+                    instance_length: Some(length),
+                }));
+        }
+        Selection::Unsatisfiable => {
+            builder.insert_header(ContentRange(ContentRangeSpec::Bytes {
+                range: None,
+                instance_length: Some(length),
+            }));
+        }
+        Selection::NotModified | Selection::PreconditionFailed => {}
+    }
+
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    const SHA256: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    fn select_with(headers: &[(header::HeaderName, &str)], length: u64) -> Selection {
+        let mut request = TestRequest::get();
+        for (name, value) in headers {
+            request = request.insert_header((name.clone(), *value));
+        }
+        select(&request.to_http_request(), Some(&EntityTag::new_strong(SHA256.to_string())), length)
+    }
+
+    fn quoted(tag: &str) -> String {
+        format!("\"{}\"", tag)
+    }
+
+    fn content(sha256: &str) -> StoredContent<'_> {
+        StoredContent {
+            key: "key",
+            sha256,
+            mime_type: "application/pdf",
+            original_filename: "scan.pdf",
+            scan_status: scanner::CLEAN,
+            master_key_id: None,
+            wrapped_key: None,
+        }
+    }
+
+    #[test]
+    fn serves_everything_without_range() {
+        assert_eq!(select_with(&[], 10), Selection::Full);
+    }
+
+    #[test]
+    fn parses_single_ranges() {
+        let range = |value: &str| select_with(&[(header::RANGE, value)], 10);
+        assert_eq!(range("bytes=0-3"), Selection::Partial { start: 0, end: 3 });
+        assert_eq!(range("bytes=4-"), Selection::Partial { start: 4, end: 9 });
+        assert_eq!(range("bytes=-4"), Selection::Partial { start: 6, end: 9 });
+        assert_eq!(range("bytes=8-100"), Selection::Partial { start: 8, end: 9 });
+        assert_eq!(range("bytes=10-20"), Selection::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignores_multiple_and_malformed_ranges() {
+        assert_eq!(select_with(&[(header::RANGE, "bytes=0-1,4-5")], 10), Selection::Full);
+        assert_eq!(select_with(&[(header::RANGE, "lines=0-1")], 10), Selection::Full);
+    }
+
+    #[test]
+    fn evaluates_preconditions_in_order() {
+        let current = quoted(SHA256);
+        assert_eq!(select_with(&[(header::IF_NONE_MATCH, &current)], 10), Selection::NotModified);
+        assert_eq!(select_with(&[(header::IF_NONE_MATCH, &format!("W/{}", current))], 10), Selection::NotModified);
+        assert_eq!(select_with(&[(header::IF_NONE_MATCH, "\"other\"")], 10), Selection::Full);
+        assert_eq!(select_with(&[(header::IF_MATCH, "\"other\"")], 10), Selection::PreconditionFailed);
+        assert_eq!(select_with(&[(header::IF_MATCH, "*"), (header::RANGE, "bytes=0-0")], 10), Selection::Partial { start: 0, end: 0 });
+        // If-Match falha antes de olhar o If-None-Match
+        assert_eq!(
+            select_with(&[(header::IF_MATCH, "\"other\""), (header::IF_NONE_MATCH, &current)], 10),
+            Selection::PreconditionFailed
+        );
+    }
+
+    #[test]
+    fn if_range_needs_the_current_etag() {
+        let current = quoted(SHA256);
+        let partial = [(header::RANGE, "bytes=2-3"), (header::IF_RANGE, current.as_str())];
+        assert_eq!(select_with(&partial, 10), Selection::Partial { start: 2, end: 3 });
+        assert_eq!(select_with(&[(header::RANGE, "bytes=2-3"), (header::IF_RANGE, "\"other\"")], 10), Selection::Full);
+        assert_eq!(select_with(&[(header::RANGE, "bytes=2-3"), (header::IF_RANGE, &format!("W/{}", current))], 10), Selection::Full);
+    }
+
+    #[test]
+    fn content_without_hash_has_no_etag() {
+        assert_eq!(etag(&content(SHA256)), Some(EntityTag::new_strong(SHA256.to_string())));
+        assert_eq!(etag(&content("")), None);
+
+        let request = |name: header::HeaderName, value: &str| TestRequest::get().insert_header((name, value)).to_http_request();
+        assert_eq!(select(&request(header::IF_NONE_MATCH, "\"\""), None, 10), Selection::Full);
+        assert_eq!(select(&request(header::IF_MATCH, "\"\""), None, 10), Selection::PreconditionFailed);
+        assert_eq!(select(&request(header::RANGE, "bytes=0-1"), None, 10), Selection::Partial { start: 0, end: 1 });
+
+        let response = response_for(&content(""), &Selection::Full, 10).finish();
+        assert!(!response.headers().contains_key(header::ETAG));
+    }
+
+    #[test]
+    fn disposition_keeps_non_ascii_names() {
+        let disposition = content_disposition("relatório \"final\".pdf");
+        assert_eq!(disposition.get_filename(), Some("relat_rio _final_.pdf"));
+        let extended = disposition.get_filename_ext().unwrap();
+        assert_eq!(extended.value, "relatório \"final\".pdf".as_bytes());
+    }
+}
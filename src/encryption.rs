@@ -0,0 +1,780 @@
+// Criptografia em repouso por envelope: cada conteúdo é cifrado com AES-256-GCM
+// usando uma chave de dados própria, e essa chave fica na linha do documento
+// cifrada ("embrulhada") pela chave mestra de MASTER_KEY. Trocar a chave mestra
+// só exige reembrulhar as chaves de dados; o conteúdo armazenado não muda.
+//
+// O conteúdo é cifrado em segmentos de 64 KiB, cada um com a sua tag, para que
+// um download parcial (Range) leia e decifre só os segmentos do intervalo. O
+// nonce de cada segmento leva o número dele e marca o último, então trocar a
+// ordem dos segmentos ou truncar o arquivo faz a tag falhar.
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use actix_web::{post, web::Data, HttpResponse, Responder};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::{Bytes, BytesMut};
+use futures_util::StreamExt;
+use openssl::{
+    rand::rand_bytes,
+    symm::{decrypt_aead, encrypt_aead, Cipher},
+};
+use serde_json::json;
+use sqlx::{Executor, Pool, Postgres, Transaction};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::{
+    blobs,
+    storage::{ByteStream, Storage},
+    thumbnails, AppState,
+};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+// Cabeçalho do conteúdo cifrado: versão do formato e o prefixo aleatório dos nonces
+const FORMAT_VERSION: u8 = 1;
+const PREFIX_LEN: usize = 7;
+const HEADER_LEN: u64 = 1 + PREFIX_LEN as u64;
+
+const SEGMENT_SIZE: u64 = 64 * 1024;
+const SEALED_SEGMENT_SIZE: u64 = SEGMENT_SIZE + TAG_LEN as u64;
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn random<const N: usize>() -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    rand_bytes(&mut bytes).map_err(io::Error::other)?;
+    Ok(bytes)
+}
+
+/// Chave de dados de um conteúdo, em claro; só existe em memória.
+#[derive(Clone)]
+pub struct DataKey([u8; KEY_LEN]);
+
+impl DataKey {
+    pub fn generate() -> io::Result<Self> {
+        Ok(DataKey(random()?))
+    }
+}
+
+/// Chave de dados cifrada pela chave mestra `master_key_id`, como fica no banco.
+#[derive(Debug, Clone)]
+pub struct WrappedKey {
+    pub master_key_id: String,
+    pub wrapped_key: String,
+}
+
+/// Chaves mestras: a atual, que embrulha as chaves novas, e as anteriores,
+/// que só servem para abrir o que ainda não foi reembrulhado.
+pub struct Keyring {
+    current_id: String,
+    keys: HashMap<String, [u8; KEY_LEN]>,
+}
+
+fn parse_master_key(name: &str, value: &str) -> io::Result<[u8; KEY_LEN]> {
+    STANDARD
+        .decode(value.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} must be {} bytes encoded in base64", name, KEY_LEN),
+            )
+        })
+}
+
+impl Keyring {
+    /// Lê a chave mestra atual de MASTER_KEY (32 bytes em base64), com o
+    /// identificador em MASTER_KEY_ID, e as anteriores de PREVIOUS_MASTER_KEYS
+    /// (`id:chave,id:chave`).
+    pub fn from_env() -> io::Result<Self> {
+        let current = std::env::var("MASTER_KEY")
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "MASTER_KEY must be set"))?;
+        let current_id = std::env::var("MASTER_KEY_ID").unwrap_or_else(|_| "1".to_string());
+
+        let mut keys = HashMap::new();
+        if let Ok(previous) = std::env::var("PREVIOUS_MASTER_KEYS") {
+            for entry in previous.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+                let (id, key) = entry.split_once(':').ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "PREVIOUS_MASTER_KEYS entries must look like id:key")
+                })?;
+                keys.insert(id.trim().to_string(), parse_master_key("PREVIOUS_MASTER_KEYS", key)?);
+            }
+        }
+        keys.insert(current_id.clone(), parse_master_key("MASTER_KEY", &current)?);
+
+        Ok(Keyring { current_id, keys })
+    }
+
+    pub fn current_id(&self) -> &str {
+        &self.current_id
+    }
+
+    /// Embrulha uma chave de dados com a chave mestra atual.
+    pub fn wrap_key(&self, key: &DataKey) -> io::Result<WrappedKey> {
+        let master = &self.keys[&self.current_id];
+        let nonce: [u8; NONCE_LEN] = random()?;
+        let mut tag = [0u8; TAG_LEN];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            master,
+            Some(&nonce),
+            self.current_id.as_bytes(),
+            &key.0,
+            &mut tag,
+        )
+        .map_err(io::Error::other)?;
+
+        let mut wrapped = nonce.to_vec();
+        wrapped.extend_from_slice(&ciphertext);
+        wrapped.extend_from_slice(&tag);
+        Ok(WrappedKey {
+            master_key_id: self.current_id.clone(),
+            wrapped_key: STANDARD.encode(wrapped),
+        })
+    }
+
+    pub fn unwrap_key(&self, wrapped: &WrappedKey) -> io::Result<DataKey> {
+        let master = self
+            .keys
+            .get(&wrapped.master_key_id)
+            .ok_or_else(|| invalid(format!("Unknown master key {}", wrapped.master_key_id)))?;
+        let bytes = STANDARD
+            .decode(&wrapped.wrapped_key)
+            .map_err(|_| invalid("Wrapped data key is not valid base64"))?;
+        if bytes.len() != NONCE_LEN + KEY_LEN + TAG_LEN {
+            return Err(invalid("Wrapped data key has the wrong length"));
+        }
+
+        let (nonce, rest) = bytes.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(KEY_LEN);
+        let key = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            master,
+            Some(nonce),
+            wrapped.master_key_id.as_bytes(),
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| invalid(format!("Failed to unwrap data key with master key {}", wrapped.master_key_id)))?;
+
+        key.try_into()
+            .map(DataKey)
+            .map_err(|_| invalid("Unwrapped data key has the wrong length"))
+    }
+
+    /// Abre a chave guardada numa linha de `documents` ou `document_revisions`.
+    ///
+    /// Sem chave, o conteúdo foi gravado antes da criptografia e está em claro.
+    pub fn open(&self, master_key_id: Option<&str>, wrapped_key: Option<&str>) -> io::Result<Option<DataKey>> {
+        match (master_key_id, wrapped_key) {
+            (Some(master_key_id), Some(wrapped_key)) => self
+                .unwrap_key(&WrappedKey {
+                    master_key_id: master_key_id.to_string(),
+                    wrapped_key: wrapped_key.to_string(),
+                })
+                .map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Chave embrulhada do conteúdo `filename`, copiada de um documento ou
+/// revisão que já aponta para ele; `None` se o conteúdo está em claro.
+pub async fn find_wrapped_key<'c, E>(executor: E, filename: &str) -> Result<Option<WrappedKey>, sqlx::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let row = sqlx::query!(
+        r#"SELECT master_key_id, wrapped_key FROM documents WHERE filename = $1 AND wrapped_key IS NOT NULL
+           UNION ALL
+           SELECT master_key_id, wrapped_key FROM document_revisions WHERE filename = $1 AND wrapped_key IS NOT NULL
+           LIMIT 1"#,
+        filename
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.and_then(|row| match (row.master_key_id, row.wrapped_key) {
+        (Some(master_key_id), Some(wrapped_key)) => Some(WrappedKey { master_key_id, wrapped_key }),
+        _ => None,
+    }))
+}
+
+/// Chave de dados do conteúdo `filename`, para quem só conhece a chave do armazenamento.
+pub async fn content_key(db: &Pool<Postgres>, keyring: &Keyring, filename: &str) -> io::Result<Option<DataKey>> {
+    match find_wrapped_key(db, filename).await.map_err(io::Error::other)? {
+        Some(wrapped) => keyring.unwrap_key(&wrapped).map(Some),
+        None => Ok(None),
+    }
+}
+
+// Conteúdo vazio ainda tem um segmento, só com a tag
+fn segment_count(length: u64) -> u64 {
+    length.div_ceil(SEGMENT_SIZE).max(1)
+}
+
+// Tamanho em claro de um conteúdo cifrado com `sealed` bytes
+fn plain_size(sealed: u64) -> io::Result<u64> {
+    let body = sealed
+        .checked_sub(HEADER_LEN)
+        .ok_or_else(|| invalid("Encrypted content is truncated"))?;
+    let segments = body.div_ceil(SEALED_SEGMENT_SIZE).max(1);
+    body.checked_sub(segments * TAG_LEN as u64)
+        .ok_or_else(|| invalid("Encrypted content is truncated"))
+}
+
+fn segment_nonce(prefix: &[u8; PREFIX_LEN], index: u64, last: bool) -> io::Result<[u8; NONCE_LEN]> {
+    let index = u32::try_from(index).map_err(|_| invalid("Content is too large to encrypt"))?;
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..PREFIX_LEN].copy_from_slice(prefix);
+    nonce[PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&index.to_be_bytes());
+    nonce[NONCE_LEN - 1] = last as u8;
+    Ok(nonce)
+}
+
+fn seal_segment(key: &DataKey, prefix: &[u8; PREFIX_LEN], index: u64, last: bool, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+    let nonce = segment_nonce(prefix, index, last)?;
+    let mut tag = [0u8; TAG_LEN];
+    let mut sealed = encrypt_aead(Cipher::aes_256_gcm(), &key.0, Some(&nonce), &[], plaintext, &mut tag)
+        .map_err(io::Error::other)?;
+    sealed.extend_from_slice(&tag);
+    Ok(sealed)
+}
+
+fn open_segment(key: &DataKey, prefix: &[u8; PREFIX_LEN], index: u64, last: bool, sealed: &[u8]) -> io::Result<Vec<u8>> {
+    let nonce = segment_nonce(prefix, index, last)?;
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    decrypt_aead(Cipher::aes_256_gcm(), &key.0, Some(&nonce), &[], ciphertext, tag)
+        .map_err(|_| invalid(format!("Encrypted content failed authentication at segment {}", index)))
+}
+
+// Cifra `length` bytes de `reader`, segmento por segmento
+fn seal(key: &DataKey, length: u64, reader: &mut impl Read, writer: &mut impl Write) -> io::Result<()> {
+    let prefix: [u8; PREFIX_LEN] = random()?;
+    writer.write_all(&[FORMAT_VERSION])?;
+    writer.write_all(&prefix)?;
+
+    let segments = segment_count(length);
+    let mut buffer = vec![0u8; SEGMENT_SIZE as usize];
+    for index in 0..segments {
+        let size = (length - index * SEGMENT_SIZE).min(SEGMENT_SIZE) as usize;
+        reader.read_exact(&mut buffer[..size])?;
+        writer.write_all(&seal_segment(key, &prefix, index, index + 1 == segments, &buffer[..size])?)?;
+    }
+    writer.flush()
+}
+
+/// Cifra o arquivo `src` e grava-o no armazenamento sob `key`.
+///
+/// A cópia cifrada passa por um arquivo ao lado de `src`, que continua onde
+/// está para quem chamou apagar.
+pub async fn put_file(storage: &dyn Storage, key: &str, src: &Path, data_key: &DataKey) -> io::Result<()> {
+    let sealed_path = src.with_extension("sealed");
+
+    let source = src.to_path_buf();
+    let target = sealed_path.clone();
+    let data_key = data_key.clone();
+    tokio::task::spawn_blocking(move || {
+        let file = File::open(&source)?;
+        let length = file.metadata()?.len();
+        seal(&data_key, length, &mut BufReader::new(file), &mut BufWriter::new(File::create(&target)?))
+    })
+    .await
+    .map_err(io::Error::other)??;
+
+    let stored = storage.put_file(key, &sealed_path).await;
+    if tokio::fs::try_exists(&sealed_path).await.unwrap_or(false) {
+        if let Err(error) = tokio::fs::remove_file(&sealed_path).await {
+            log::warn!("Failed to remove encrypted copy {}: {}", sealed_path.display(), error);
+        }
+    }
+    stored
+}
+
+/// Cifra `bytes` e grava-os no armazenamento sob `key`.
+pub async fn put_bytes(storage: &dyn Storage, key: &str, bytes: &[u8], data_key: &DataKey) -> io::Result<()> {
+    let mut sealed = Vec::with_capacity(bytes.len() + HEADER_LEN as usize + TAG_LEN);
+    seal(data_key, bytes.len() as u64, &mut &bytes[..], &mut sealed)?;
+    storage.put_bytes(key, Bytes::from(sealed)).await
+}
+
+/// Cifra com uma chave de dados nova o conteúdo `key`, gravado em claro antes
+/// da criptografia em repouso, a partir da cópia em claro `plain`, e grava a
+/// chave embrulhada em todos os documentos e revisões que apontam para ele.
+///
+/// Quem chama segura a trava do conteúdo (`blobs::lock`) em `tx`. Se der erro,
+/// o armazenamento continua em claro; se `tx` não for confirmada depois,
+/// `restore_plaintext` devolve o conteúdo em claro.
+pub async fn encrypt_stored(
+    tx: &mut Transaction<'_, Postgres>,
+    storage: &dyn Storage,
+    keyring: &Keyring,
+    key: &str,
+    plain: &Path,
+    mime_type: &str,
+) -> io::Result<WrappedKey> {
+    let data_key = DataKey::generate()?;
+    let wrapped = keyring.wrap_key(&data_key)?;
+
+    fill_wrapped_key(tx, key, &wrapped).await.map_err(io::Error::other)?;
+
+    put_file(storage, key, plain, &data_key).await?;
+    thumbnails::remove(storage, key).await;
+    thumbnails::generate(storage, key, plain, mime_type, &data_key).await;
+    Ok(wrapped)
+}
+
+// Grava `wrapped` nos documentos e revisões do conteúdo `key` que ainda estão sem chave
+async fn fill_wrapped_key(
+    tx: &mut Transaction<'_, Postgres>,
+    key: &str,
+    wrapped: &WrappedKey,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE documents SET master_key_id = $2, wrapped_key = $3 WHERE filename = $1 AND wrapped_key IS NULL",
+        key,
+        wrapped.master_key_id,
+        wrapped.wrapped_key
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE document_revisions SET master_key_id = $2, wrapped_key = $3 WHERE filename = $1 AND wrapped_key IS NULL",
+        key,
+        wrapped.master_key_id,
+        wrapped.wrapped_key
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+/// Volta a gravar em claro o conteúdo `key` cifrado por `encrypt_stored` numa
+/// transação que não foi confirmada, a menos que outra já o tenha cifrado.
+/// O arquivo `plain` é consumido.
+pub async fn restore_plaintext(db: &Pool<Postgres>, storage: &dyn Storage, key: &str, plain: &Path) {
+    let restored = async {
+        let mut tx = db.begin().await.map_err(io::Error::other)?;
+        blobs::lock(&mut tx, key).await.map_err(io::Error::other)?;
+        if find_wrapped_key(&mut *tx, key).await.map_err(io::Error::other)?.is_none() {
+            thumbnails::remove(storage, key).await;
+            storage.put_file(key, plain).await?;
+        }
+        tx.commit().await.map_err(io::Error::other)
+    }
+    .await;
+
+    if let Err(error) = restored {
+        log::error!("Failed to restore plaintext content {}: {}", key, error);
+    }
+}
+
+/// Tamanho em claro do conteúdo `key`.
+pub async fn size(storage: &dyn Storage, key: &str, data_key: Option<&DataKey>) -> io::Result<u64> {
+    let stored = storage.size(key).await?;
+    match data_key {
+        Some(_) => plain_size(stored),
+        None => Ok(stored),
+    }
+}
+
+async fn read_all(mut body: ByteStream) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.next().await {
+        bytes.extend_from_slice(&chunk?);
+    }
+    Ok(bytes)
+}
+
+struct Opening {
+    body: ByteStream,
+    buffer: BytesMut,
+    key: DataKey,
+    prefix: [u8; PREFIX_LEN],
+    index: u64,
+    last_index: u64,
+    segments: u64,
+    stored: u64,
+    skip: usize,
+    remaining: u64,
+}
+
+/// Lê e decifra o conteúdo `key`, inteiro ou só o intervalo fechado
+/// `(início, fim)` do conteúdo em claro.
+///
+/// Sem chave de dados o conteúdo é lido como está.
+pub async fn get(
+    storage: &dyn Storage,
+    key: &str,
+    data_key: Option<&DataKey>,
+    range: Option<(u64, u64)>,
+) -> io::Result<ByteStream> {
+    let Some(data_key) = data_key else {
+        return storage.get(key, range).await;
+    };
+
+    let stored = storage.size(key).await?;
+    let length = plain_size(stored)?;
+    let (start, end) = match range {
+        Some((start, end)) if start <= end && end < length => (start, end),
+        Some((start, end)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Range {}-{} is outside of {} bytes", start, end, length),
+            ));
+        }
+        None if length == 0 => return Ok(Box::pin(futures_util::stream::empty())),
+        None => (0, length - 1),
+    };
+
+    let header = read_all(storage.get(key, Some((0, HEADER_LEN - 1))).await?).await?;
+    if header.len() as u64 != HEADER_LEN || header[0] != FORMAT_VERSION {
+        return Err(invalid(format!("Stored content {} is not in a known encrypted format", key)));
+    }
+    let mut prefix = [0u8; PREFIX_LEN];
+    prefix.copy_from_slice(&header[1..]);
+
+    // Só os segmentos que cobrem o intervalo saem do armazenamento
+    let first = start / SEGMENT_SIZE;
+    let last_index = end / SEGMENT_SIZE;
+    let body_start = HEADER_LEN + first * SEALED_SEGMENT_SIZE;
+    let body_end = (HEADER_LEN + (last_index + 1) * SEALED_SEGMENT_SIZE).min(stored) - 1;
+    let body = storage.get(key, Some((body_start, body_end))).await?;
+
+    let opening = Opening {
+        body,
+        buffer: BytesMut::new(),
+        key: data_key.clone(),
+        prefix,
+        index: first,
+        last_index,
+        segments: segment_count(length),
+        stored,
+        skip: (start - first * SEGMENT_SIZE) as usize,
+        remaining: end - start + 1,
+    };
+
+    Ok(Box::pin(futures_util::stream::try_unfold(opening, |mut opening| async move {
+        if opening.index > opening.last_index {
+            return Ok(None);
+        }
+
+        let is_last = opening.index + 1 == opening.segments;
+        let sealed_len = if is_last {
+            opening.stored - HEADER_LEN - opening.index * SEALED_SEGMENT_SIZE
+        } else {
+            SEALED_SEGMENT_SIZE
+        } as usize;
+        while opening.buffer.len() < sealed_len {
+            match opening.body.next().await {
+                Some(chunk) => opening.buffer.extend_from_slice(&chunk?),
+                None => return Err(invalid("Encrypted content is truncated")),
+            }
+        }
+
+        let sealed = opening.buffer.split_to(sealed_len);
+        let plain = open_segment(&opening.key, &opening.prefix, opening.index, is_last, &sealed)?;
+        let from = std::mem::take(&mut opening.skip).min(plain.len());
+        let to = (from as u64 + opening.remaining).min(plain.len() as u64) as usize;
+        opening.remaining -= (to - from) as u64;
+        opening.index += 1;
+
+        Ok(Some((Bytes::from(plain).slice(from..to), opening)))
+    })))
+}
+
+// Reembrulha com a chave mestra atual as chaves de dados de `documents` e
+// `document_revisions` que ainda usam uma chave anterior
+async fn rewrap(db: &Pool<Postgres>, keyring: &Keyring) -> io::Result<(usize, usize)> {
+    let documents = sqlx::query!(
+        r#"SELECT id, master_key_id AS "master_key_id!", wrapped_key AS "wrapped_key!" FROM documents
+           WHERE wrapped_key IS NOT NULL AND master_key_id <> $1"#,
+        keyring.current_id()
+    )
+    .fetch_all(db)
+    .await
+    .map_err(io::Error::other)?;
+
+    for row in &documents {
+        let old = WrappedKey { master_key_id: row.master_key_id.clone(), wrapped_key: row.wrapped_key.clone() };
+        let new = keyring.wrap_key(&keyring.unwrap_key(&old)?)?;
+        sqlx::query!(
+            "UPDATE documents SET master_key_id = $2, wrapped_key = $3 WHERE id = $1 AND wrapped_key = $4",
+            row.id,
+            new.master_key_id,
+            new.wrapped_key,
+            old.wrapped_key
+        )
+        .execute(db)
+        .await
+        .map_err(io::Error::other)?;
+    }
+
+    let revisions = sqlx::query!(
+        r#"SELECT id, master_key_id AS "master_key_id!", wrapped_key AS "wrapped_key!" FROM document_revisions
+           WHERE wrapped_key IS NOT NULL AND master_key_id <> $1"#,
+        keyring.current_id()
+    )
+    .fetch_all(db)
+    .await
+    .map_err(io::Error::other)?;
+
+    for row in &revisions {
+        let old = WrappedKey { master_key_id: row.master_key_id.clone(), wrapped_key: row.wrapped_key.clone() };
+        let new = keyring.wrap_key(&keyring.unwrap_key(&old)?)?;
+        sqlx::query!(
+            "UPDATE document_revisions SET master_key_id = $2, wrapped_key = $3 WHERE id = $1 AND wrapped_key = $4",
+            row.id,
+            new.master_key_id,
+            new.wrapped_key,
+            old.wrapped_key
+        )
+        .execute(db)
+        .await
+        .map_err(io::Error::other)?;
+    }
+
+    Ok((documents.len(), revisions.len()))
+}
+
+// Depois de trocar MASTER_KEY (com a antiga em PREVIOUS_MASTER_KEYS), passa
+// todas as chaves de dados para a nova; pode ser repetida sem efeito colateral
+#[post("/admin/key-rotation")]
+pub async fn rotate_master_key(data: Data<AppState>) -> impl Responder {
+    match rewrap(&data.db, &data.keyring).await {
+        Ok((documents, revisions)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "master_key_id": data.keyring.current_id(),
+            "documents": documents,
+            "revisions": revisions
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to rotate data keys: {}", error)
+        })),
+    }
+}
+
+// Cifra os conteúdos ainda em claro, de antes da criptografia em repouso;
+// devolve quantos foram cifrados e quantos falharam
+async fn encrypt_plaintext(
+    db: &Pool<Postgres>,
+    storage: &dyn Storage,
+    keyring: &Keyring,
+    staging_dir: &Path,
+) -> Result<(usize, usize), sqlx::Error> {
+    let contents = sqlx::query!(
+        r#"SELECT filename AS "filename!", mime_type AS "mime_type!" FROM documents WHERE wrapped_key IS NULL
+           UNION
+           SELECT filename, mime_type FROM document_revisions WHERE wrapped_key IS NULL"#
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut encrypted = 0;
+    let mut failed = 0;
+    for content in contents {
+        match encrypt_content(db, storage, keyring, staging_dir, &content.filename, &content.mime_type).await {
+            Ok(()) => encrypted += 1,
+            Err(error) => {
+                log::warn!("Failed to encrypt plaintext content {}: {}", content.filename, error);
+                failed += 1;
+            }
+        }
+    }
+    Ok((encrypted, failed))
+}
+
+async fn encrypt_content(
+    db: &Pool<Postgres>,
+    storage: &dyn Storage,
+    keyring: &Keyring,
+    staging_dir: &Path,
+    key: &str,
+    mime_type: &str,
+) -> io::Result<()> {
+    let mut tx = db.begin().await.map_err(io::Error::other)?;
+    blobs::lock(&mut tx, key).await.map_err(io::Error::other)?;
+
+    // Um upload do mesmo conteúdo pode já tê-lo cifrado; basta copiar a chave
+    if let Some(wrapped) = find_wrapped_key(&mut *tx, key).await.map_err(io::Error::other)? {
+        fill_wrapped_key(&mut tx, key, &wrapped).await.map_err(io::Error::other)?;
+        return tx.commit().await.map_err(io::Error::other);
+    }
+
+    let plain = staging_dir.join(format!("plaintext_{}", Uuid::new_v4()));
+    let downloaded = async {
+        let mut body = storage.get(key, None).await?;
+        let mut file = tokio::fs::File::create(&plain).await?;
+        while let Some(chunk) = body.next().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await
+    }
+    .await;
+
+    let result = match downloaded {
+        Ok(()) => match encrypt_stored(&mut tx, storage, keyring, key, &plain, mime_type).await {
+            Ok(_) => match tx.commit().await {
+                Ok(()) => Ok(()),
+                Err(error) => {
+                    restore_plaintext(db, storage, key, &plain).await;
+                    Err(io::Error::other(error))
+                }
+            },
+            Err(error) => Err(error),
+        },
+        Err(error) => Err(error),
+    };
+
+    if tokio::fs::try_exists(&plain).await.unwrap_or(false) {
+        if let Err(error) = tokio::fs::remove_file(&plain).await {
+            log::warn!("Failed to remove plaintext copy {}: {}", plain.display(), error);
+        }
+    }
+    result
+}
+
+// Cifra os documentos e revisões gravados em claro antes da criptografia em
+// repouso; pode ser repetida sem efeito colateral
+#[post("/admin/plaintext-encryption")]
+pub async fn encrypt_plaintext_content(data: Data<AppState>) -> impl Responder {
+    match encrypt_plaintext(&data.db, data.storage.as_ref(), &data.keyring, &data.staging_dir).await {
+        Ok((encrypted, failed)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "master_key_id": data.keyring.current_id(),
+            "encrypted": encrypted,
+            "failed": failed
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to encrypt plaintext content: {}", error)
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    const SEGMENT: usize = SEGMENT_SIZE as usize;
+
+    fn key() -> DataKey {
+        DataKey([7; KEY_LEN])
+    }
+
+    fn content(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn sealed(plain: &[u8]) -> Vec<u8> {
+        let mut sealed = Vec::new();
+        seal(&key(), plain.len() as u64, &mut &plain[..], &mut sealed).unwrap();
+        sealed
+    }
+
+    async fn stored(plain: &[u8]) -> MemoryStorage {
+        let storage = MemoryStorage::default();
+        storage.put_bytes("key", Bytes::from(sealed(plain))).await.unwrap();
+        storage
+    }
+
+    async fn read(storage: &MemoryStorage, range: Option<(u64, u64)>) -> io::Result<Vec<u8>> {
+        read_all(get(storage, "key", Some(&key()), range).await?).await
+    }
+
+    #[test]
+    fn each_segment_adds_a_tag_after_the_header() {
+        for (length, segments) in [(0, 1), (1, 1), (SEGMENT, 1), (SEGMENT + 1, 2), (3 * SEGMENT, 3)] {
+            let sealed = sealed(&content(length));
+            assert_eq!(sealed.len(), HEADER_LEN as usize + length + segments * TAG_LEN, "{} bytes", length);
+            assert_eq!(sealed[0], FORMAT_VERSION);
+            assert_eq!(segment_count(length as u64), segments as u64);
+            assert_eq!(plain_size(sealed.len() as u64).unwrap(), length as u64);
+        }
+        assert!(plain_size(HEADER_LEN - 1).is_err());
+        assert!(plain_size(HEADER_LEN + TAG_LEN as u64 - 1).is_err());
+    }
+
+    #[test]
+    fn nonce_carries_prefix_index_and_last_flag() {
+        let prefix = [1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(segment_nonce(&prefix, 0x0102_0304, false).unwrap(), [1, 2, 3, 4, 5, 6, 7, 1, 2, 3, 4, 0]);
+        assert_eq!(segment_nonce(&prefix, 0, true).unwrap(), [1, 2, 3, 4, 5, 6, 7, 0, 0, 0, 0, 1]);
+        assert!(segment_nonce(&prefix, u32::MAX as u64 + 1, false).is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_ranges_across_segments() {
+        let plain = content(2 * SEGMENT + 100);
+        let storage = stored(&plain).await;
+
+        assert_eq!(read(&storage, None).await.unwrap(), plain);
+        let ranges = [
+            (0, 0),
+            (10, 20),
+            (SEGMENT - 2, SEGMENT + 1),
+            (SEGMENT, 2 * SEGMENT + 99),
+            (2 * SEGMENT + 99, 2 * SEGMENT + 99),
+        ];
+        for (start, end) in ranges {
+            let range = read(&storage, Some((start as u64, end as u64))).await.unwrap();
+            assert_eq!(range, &plain[start..=end], "bytes {}-{}", start, end);
+        }
+        assert!(read(&storage, Some((0, plain.len() as u64))).await.is_err());
+
+        let empty = stored(&[]).await;
+        assert!(read(&empty, None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reordered_or_truncated_segments_fail_authentication() {
+        let plain = content(3 * SEGMENT);
+        let mut bytes = sealed(&plain);
+        let header = HEADER_LEN as usize;
+        let sealed_segment = SEALED_SEGMENT_SIZE as usize;
+
+        // Troca o primeiro segmento com o segundo
+        let mut swapped = bytes.clone();
+        swapped[header..header + 2 * sealed_segment].rotate_left(sealed_segment);
+        let storage = MemoryStorage::default();
+        storage.put_bytes("key", Bytes::from(swapped)).await.unwrap();
+        assert!(read(&storage, Some((0, 10))).await.is_err());
+
+        // Sem o último segmento, o que era o do meio passa a ser o último
+        bytes.truncate(header + 2 * sealed_segment);
+        storage.put_bytes("key", Bytes::from(bytes)).await.unwrap();
+        assert!(read(&storage, Some((SEGMENT as u64, SEGMENT as u64 + 10))).await.is_err());
+        assert_eq!(read(&storage, Some((0, 10))).await.unwrap(), &plain[..=10]);
+    }
+
+    #[test]
+    fn wrapped_keys_need_their_master_key() {
+        let keyring = Keyring {
+            current_id: "2".to_string(),
+            keys: HashMap::from([("1".to_string(), [1; KEY_LEN]), ("2".to_string(), [2; KEY_LEN])]),
+        };
+        let wrapped = keyring.wrap_key(&key()).unwrap();
+        assert_eq!(wrapped.master_key_id, "2");
+        assert_eq!(keyring.unwrap_key(&wrapped).unwrap().0, key().0);
+
+        // O id da chave mestra entra como dado autenticado
+        let relabeled = WrappedKey { master_key_id: "1".to_string(), ..wrapped.clone() };
+        assert!(keyring.unwrap_key(&relabeled).is_err());
+        let unknown = WrappedKey { master_key_id: "3".to_string(), ..wrapped };
+        assert!(keyring.unwrap_key(&unknown).is_err());
+        assert!(keyring.open(None, None).unwrap().is_none());
+    }
+}
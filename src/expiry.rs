@@ -0,0 +1,84 @@
+// Validade dos documentos. O expires_at vem do campo enviado ou, na falta
+// dele, de metadata.expires_on; uma varredura periódica passa os documentos
+// vencidos para o review_status `expired`.
+use std::{io, time::Duration};
+
+use actix_web::{post, web::Data, HttpResponse, Responder};
+use chrono::NaiveDate;
+use serde_json::{json, Value};
+use sqlx::{Pool, Postgres};
+
+use crate::{review, AppState};
+
+/// Maior janela aceita em `expiring_within`, em dias.
+pub const MAX_EXPIRING_WITHIN_DAYS: u32 = 3650;
+
+/// Data de validade declarada no metadata (campo `expires_on`, YYYY-MM-DD).
+/// Um `expires_on` que não é data é erro, e não "sem validade".
+pub fn from_metadata(metadata: &Value) -> Result<Option<NaiveDate>, String> {
+    match metadata.get("expires_on") {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| format!("/expires_on: \"{}\" is not a date (YYYY-MM-DD)", value)),
+        Some(value) => Err(format!("/expires_on: {} is not a date (YYYY-MM-DD)", value)),
+    }
+}
+
+/// Intervalo entre as varreduras, em EXPIRY_SWEEP_INTERVAL_SECS (padrão 1 hora).
+pub fn sweep_interval_from_env() -> io::Result<Duration> {
+    match std::env::var("EXPIRY_SWEEP_INTERVAL_SECS") {
+        Ok(value) => match value.parse::<u64>() {
+            Ok(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid EXPIRY_SWEEP_INTERVAL_SECS: {}", value),
+            )),
+        },
+        Err(_) => Ok(Duration::from_secs(60 * 60)),
+    }
+}
+
+/// Marca como `expired` os documentos vencidos e devolve quantos mudaram. A
+/// decisão anterior (quem aprovou e quando) deixa de valer e é apagada.
+pub async fn sweep(db: &Pool<Postgres>) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"UPDATE documents
+           SET review_status = $1, reviewer_id = NULL, decided_at = NULL, rejection_reason = NULL
+           WHERE expires_at < CURRENT_DATE AND review_status <> $1"#,
+        review::EXPIRED
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Roda a varredura ao subir o servidor e depois a cada `interval`.
+pub fn spawn_sweeper(db: Pool<Postgres>, interval: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match sweep(&db).await {
+                Ok(0) => {}
+                Ok(expired) => log::info!("Marked {} document(s) as expired", expired),
+                Err(error) => log::warn!("Failed to sweep expired documents: {:?}", error),
+            }
+        }
+    });
+}
+
+// Roda a varredura agora, sem esperar o próximo intervalo
+#[post("/admin/expiry-sweep")]
+pub async fn run_expiry_sweep(data: Data<AppState>) -> impl Responder {
+    match sweep(&data.db).await {
+        Ok(expired) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "expired": expired
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to sweep expired documents: {:?}", error)
+        })),
+    }
+}
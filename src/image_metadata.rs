@@ -0,0 +1,277 @@
+// EXIF das imagens enviadas. Fotos de celular trazem coordenadas GPS e dados
+// do aparelho: os campos úteis vão para `documents.image_metadata` e o
+// arquivo armazenado sai sem EXIF, já na orientação certa.
+use std::{
+    io::{self, Cursor},
+    path::Path,
+};
+
+use exif::{Context, Exif, In, Tag};
+use image::{codecs::jpeg::JpegEncoder, metadata::Orientation, DynamicImage, ImageFormat};
+use serde_json::{Map, Value};
+
+const JPEG_QUALITY: u8 = 90;
+
+// Segmentos APP1 do JPEG com metadados: EXIF e XMP (que também pode ter GPS)
+const JPEG_METADATA_PREFIXES: [&[u8]; 2] = [b"Exif\0\0", b"http://ns.adobe.com/x"];
+
+// Palavras-chave dos chunks de texto do PNG que carregam EXIF ou XMP
+const PNG_METADATA_KEYWORDS: [&[u8]; 2] = [b"XML:com.adobe.xmp", b"Raw profile type"];
+
+pub struct Sanitized {
+    /// Campos extraídos do EXIF, ou `None` se a imagem não tinha nada útil.
+    pub metadata: Option<Value>,
+    /// Se o arquivo foi regravado (tamanho e SHA-256 mudaram).
+    pub rewritten: bool,
+}
+
+/// Extrai o EXIF de uma imagem em disco e regrava o arquivo sem ele.
+///
+/// Sem rotação a remoção é feita sem recompressão (JPEG e PNG); se a
+/// orientação não é a normal, a imagem é girada e recodificada, o que também
+/// descarta os metadados. Erros de `io::ErrorKind::InvalidData` indicam uma
+/// imagem que não pôde ser lida.
+pub async fn sanitize(path: &Path, mime_type: &str) -> io::Result<Sanitized> {
+    let path = path.to_path_buf();
+    let mime_type = mime_type.to_string();
+    tokio::task::spawn_blocking(move || sanitize_file(&path, &mime_type))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Tipos que `sanitize` decodifica (e regrava).
+pub fn is_decoded(mime_type: &str) -> bool {
+    matches!(mime_type, "image/jpeg" | "image/png" | "image/webp" | "image/tiff")
+}
+
+fn sanitize_file(path: &Path, mime_type: &str) -> io::Result<Sanitized> {
+    let format = match mime_type {
+        "image/jpeg" => ImageFormat::Jpeg,
+        "image/png" => ImageFormat::Png,
+        "image/webp" => ImageFormat::WebP,
+        "image/tiff" => ImageFormat::Tiff,
+        _ => return Ok(Sanitized { metadata: None, rewritten: false }),
+    };
+
+    let original = std::fs::read(path)?;
+    let (metadata, has_exif, has_location, orientation) =
+        match exif::Reader::new().read_from_container(&mut Cursor::new(&original)) {
+            Ok(exif) => {
+                let has_location = has_location(&exif);
+                (describe(&exif, has_location), true, has_location, orientation(&exif))
+            }
+            Err(exif::Error::NotFound(_)) => (None, false, false, 1),
+            // EXIF ilegível (ou arquivo truncado): não dá para saber o que há nele,
+            // então sai inteiro se o arquivo puder ser percorrido
+            Err(_) => (None, false, true, 1),
+        };
+
+    let sanitized = if orientation != 1 {
+        Some(reencode(&original, format, orientation)?)
+    } else {
+        let stripped = match format {
+            ImageFormat::Jpeg => strip_jpeg(&original),
+            ImageFormat::Png => strip_png(&original),
+            _ if has_location => reencode(&original, format, 1).map(Some),
+            _ => Ok(None),
+        };
+        // Sem EXIF válido, um arquivo que não dá para percorrer segue como veio
+        match stripped {
+            Err(_) if !has_exif => None,
+            stripped => stripped?,
+        }
+    };
+
+    let rewritten = match sanitized {
+        Some(bytes) => {
+            std::fs::write(path, bytes)?;
+            true
+        }
+        None => false,
+    };
+
+    Ok(Sanitized { metadata, rewritten })
+}
+
+fn has_location(exif: &Exif) -> bool {
+    exif.fields()
+        .any(|field| field.tag.context() == Context::Gps || field.tag == Tag::GPSInfoIFDPointer)
+}
+
+fn orientation(exif: &Exif) -> u32 {
+    exif.get_field(Tag::Orientation, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .filter(|orientation| (1..=8).contains(orientation))
+        .unwrap_or(1)
+}
+
+// Só o que interessa para a análise do documento; nada de GPS nem números de série
+fn describe(exif: &Exif, has_location: bool) -> Option<Value> {
+    let mut fields = Map::new();
+
+    if let Some(captured_at) = captured_at(exif) {
+        fields.insert("captured_at".to_string(), Value::from(captured_at));
+    }
+    if let Some(field) = exif.get_field(Tag::Orientation, In::PRIMARY) {
+        if let Some(orientation) = field.value.get_uint(0) {
+            fields.insert("orientation".to_string(), Value::from(orientation));
+        }
+    }
+    if let Some(make) = ascii(exif, Tag::Make) {
+        fields.insert("camera_make".to_string(), Value::from(make));
+    }
+    if let Some(model) = ascii(exif, Tag::Model) {
+        fields.insert("camera_model".to_string(), Value::from(model));
+    }
+    if has_location {
+        fields.insert("location_removed".to_string(), Value::from(true));
+    }
+
+    (!fields.is_empty()).then_some(Value::Object(fields))
+}
+
+fn ascii(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        exif::Value::Ascii(values) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).trim().to_string())
+            .filter(|value| !value.is_empty()),
+        _ => None,
+    }
+}
+
+// Data da captura em ISO 8601, sem fuso (o EXIF raramente o informa)
+fn captured_at(exif: &Exif) -> Option<String> {
+    [Tag::DateTimeOriginal, Tag::DateTime].iter().find_map(|tag| {
+        let field = exif.get_field(*tag, In::PRIMARY)?;
+        let exif::Value::Ascii(values) = &field.value else {
+            return None;
+        };
+        let datetime = exif::DateTime::from_ascii(values.first()?).ok()?;
+        chrono::NaiveDate::from_ymd_opt(datetime.year.into(), datetime.month.into(), datetime.day.into())?
+            .and_hms_opt(datetime.hour.into(), datetime.minute.into(), datetime.second.into())
+            .map(|datetime| datetime.format("%Y-%m-%dT%H:%M:%S").to_string())
+    })
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// Gira a imagem conforme a orientação e a codifica de novo no mesmo formato
+fn reencode(original: &[u8], format: ImageFormat, orientation: u32) -> io::Result<Vec<u8>> {
+    let mut image = image::load_from_memory_with_format(original, format).map_err(|error| invalid(error.to_string()))?;
+    image.apply_orientation(Orientation::from_exif(orientation as u8).unwrap_or(Orientation::NoTransforms));
+
+    let mut bytes = Vec::new();
+    let encoded = match format {
+        ImageFormat::Jpeg => JpegEncoder::new_with_quality(Cursor::new(&mut bytes), JPEG_QUALITY)
+            .encode_image(&DynamicImage::ImageRgb8(image.to_rgb8())),
+        // O codificador WebP só aceita RGB(A) de 8 bits
+        ImageFormat::WebP => DynamicImage::ImageRgba8(image.to_rgba8()).write_to(&mut Cursor::new(&mut bytes), format),
+        _ => image.write_to(&mut Cursor::new(&mut bytes), format),
+    };
+    encoded.map_err(|error| invalid(error.to_string()))?;
+    Ok(bytes)
+}
+
+// Remove os segmentos de EXIF e XMP sem tocar nos dados da imagem
+fn strip_jpeg(data: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Err(invalid("Not a JPEG file"));
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(&data[..2]);
+    let mut pos = 2;
+    let mut changed = false;
+
+    loop {
+        if pos + 2 > data.len() || data[pos] != 0xFF {
+            return Err(invalid("Malformed JPEG segment"));
+        }
+        let marker = data[pos + 1];
+        match marker {
+            // Bytes de preenchimento entre segmentos
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            // Início dos dados comprimidos (SOS) ou fim da imagem: o resto vai como está
+            0xDA | 0xD9 => {
+                output.extend_from_slice(&data[pos..]);
+                break;
+            }
+            // Marcadores sem comprimento
+            0x01 | 0xD0..=0xD7 => {
+                output.extend_from_slice(&data[pos..pos + 2]);
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        if pos + 4 > data.len() {
+            return Err(invalid("Truncated JPEG segment"));
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if length < 2 || pos + 2 + length > data.len() {
+            return Err(invalid("Truncated JPEG segment"));
+        }
+
+        let segment = &data[pos..pos + 2 + length];
+        let payload = &segment[4..];
+        if marker == 0xE1 && JPEG_METADATA_PREFIXES.iter().any(|prefix| payload.starts_with(prefix)) {
+            changed = true;
+        } else {
+            output.extend_from_slice(segment);
+        }
+        pos += 2 + length;
+    }
+
+    Ok(changed.then_some(output))
+}
+
+// Remove o chunk eXIf e os chunks de texto com EXIF ou XMP
+fn strip_png(data: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !data.starts_with(SIGNATURE) {
+        return Err(invalid("Not a PNG file"));
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(SIGNATURE);
+    let mut pos = SIGNATURE.len();
+    let mut changed = false;
+
+    loop {
+        if pos + 8 > data.len() {
+            return Err(invalid("Truncated PNG chunk"));
+        }
+        let length = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let chunk_type = &data[pos + 4..pos + 8];
+        let end = pos + 12 + length;
+        if end > data.len() {
+            return Err(invalid("Truncated PNG chunk"));
+        }
+
+        let chunk_data = &data[pos + 8..pos + 8 + length];
+        let keyword = chunk_data.split(|byte| *byte == 0).next().unwrap_or_default();
+        let is_metadata = chunk_type == b"eXIf"
+            || (matches!(chunk_type, b"tEXt" | b"zTXt" | b"iTXt")
+                && PNG_METADATA_KEYWORDS.iter().any(|prefix| keyword.starts_with(prefix)));
+
+        if is_metadata {
+            changed = true;
+        } else {
+            output.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+
+        if chunk_type == b"IEND" {
+            break;
+        }
+    }
+
+    Ok(changed.then_some(output))
+}
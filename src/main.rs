@@ -1,13 +1,52 @@
+mod archive;
+mod blobs;
+mod br_ids;
+mod doc_types;
+mod download;
+mod encryption;
+mod expiry;
+mod image_metadata;
 mod services;
+mod share_links;
+mod signing;
+mod merge;
 mod model;
+mod mrz;
+mod pdf;
+mod phash;
+mod quotas;
+mod retention;
+mod review;
+mod revisions;
+mod scanner;
 mod schema;
+mod sniff;
+mod storage;
+mod thumbnails;
+mod trash;
+mod tus;
+mod upload;
+mod watermark;
 
 use actix_web::{web, App, HttpServer, middleware::Logger};
 use dotenv::dotenv;
+use std::{path::PathBuf, sync::Arc};
+use storage::Storage;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 
 pub struct AppState {
     db: Pool<Postgres>,
+    storage: Arc<dyn Storage>,
+    // Área local onde os uploads ficam até irem para o armazenamento
+    staging_dir: PathBuf,
+    upload_locks: tus::UploadLocks,
+    mime_policy: Arc<sniff::MimePolicy>,
+    scanner: Arc<scanner::Scanner>,
+    share_signer: Arc<share_links::ShareSigner>,
+    keyring: Arc<encryption::Keyring>,
+    quota_defaults: quotas::QuotaDefaults,
+    watermarker: Arc<watermark::Watermarker>,
+    signing_keys: Arc<signing::SigningKeys>,
 }
 
 #[actix_web::main]
@@ -36,9 +75,108 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
+    let staging_dir = PathBuf::from(std::env::var("UPLOAD_DIR").unwrap_or_else(|_| "uploads".to_string()));
+    std::fs::create_dir_all(&staging_dir)?;
+
+    let storage = match storage::from_env() {
+        Ok(storage) => storage,
+        Err(error) => {
+            println!("Failed to configure document storage: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let upload_locks = tus::UploadLocks::default();
+
+    let mime_policy = match sniff::MimePolicy::from_env() {
+        Ok(policy) => Arc::new(policy),
+        Err(error) => {
+            println!("Failed to load MIME allowlist: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let scanner = match scanner::Scanner::from_env() {
+        Ok(scanner) => Arc::new(scanner),
+        Err(error) => {
+            println!("Failed to configure the antivirus scanner: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let keyring = match encryption::Keyring::from_env() {
+        Ok(keyring) => Arc::new(keyring),
+        Err(error) => {
+            println!("Failed to load the master key: {}", error);
+            std::process::exit(1);
+        }
+    };
+    scanner::spawn_pending_scans(pool.clone(), storage.clone(), keyring.clone(), scanner.clone());
+    phash::spawn_backfill(pool.clone(), storage.clone(), keyring.clone());
+
+    let share_signer = match share_links::ShareSigner::from_env() {
+        Ok(signer) => Arc::new(signer),
+        Err(error) => {
+            println!("Failed to configure share links: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let quota_defaults = match quotas::QuotaDefaults::from_env() {
+        Ok(defaults) => defaults,
+        Err(error) => {
+            println!("Failed to configure storage quotas: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let watermarker = match watermark::Watermarker::from_env() {
+        Ok(watermarker) => Arc::new(watermarker),
+        Err(error) => {
+            println!("Failed to configure watermarks: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let signing_keys = match signing::SigningKeys::from_env() {
+        Ok(signing_keys) => Arc::new(signing_keys),
+        Err(error) => {
+            println!("Failed to load the signing key: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    match expiry::sweep_interval_from_env() {
+        Ok(interval) => expiry::spawn_sweeper(pool.clone(), interval),
+        Err(error) => {
+            println!("Failed to configure the expiry sweep: {}", error);
+            std::process::exit(1);
+        }
+    }
+
+    match retention::purge_interval_from_env() {
+        Ok(interval) => retention::spawn_purger(pool.clone(), storage.clone(), interval),
+        Err(error) => {
+            println!("Failed to configure the retention purge: {}", error);
+            std::process::exit(1);
+        }
+    }
+
     HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(AppState { db: pool.clone() }))
+            .app_data(web::Data::new(AppState {
+                db: pool.clone(),
+                storage: storage.clone(),
+                staging_dir: staging_dir.clone(),
+                upload_locks: upload_locks.clone(),
+                mime_policy: mime_policy.clone(),
+                scanner: scanner.clone(),
+                share_signer: share_signer.clone(),
+                keyring: keyring.clone(),
+                quota_defaults,
+                watermarker: watermarker.clone(),
+                signing_keys: signing_keys.clone(),
+            }))
             .configure(services::config)
             .wrap(Logger::default()) // <- aqui
     })
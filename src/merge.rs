@@ -0,0 +1,250 @@
+// Junta imagens de um mesmo usuário (a frente e o verso de uma identidade, por
+// exemplo) num novo documento PDF, uma página por imagem, na ordem pedida. O
+// novo documento passa pelo mesmo caminho de um upload e guarda as origens em
+// document_sources.
+use std::{collections::HashSet, io};
+
+use actix_web::{
+    get,
+    http::StatusCode,
+    post,
+    web::{Data, Json, Path},
+    HttpResponse, Responder,
+};
+use futures_util::StreamExt;
+use image::codecs::jpeg::JpegEncoder;
+use serde_json::json;
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::{
+    encryption,
+    model::DocumentModel,
+    pdf::{self, JpegPage},
+    scanner,
+    schema::{CreateDocumentSchema, MergeDocumentsSchema},
+    upload::{self, StagedFile},
+    AppState,
+};
+
+const MAX_SOURCES: usize = 20;
+
+const JPEG_QUALITY: u8 = 90;
+
+const IMAGE_MIME_TYPES: [&str; 4] = ["image/jpeg", "image/png", "image/webp", "image/tiff"];
+
+fn fail(status: StatusCode, message: String) -> HttpResponse {
+    let kind = if status.is_server_error() { "error" } else { "fail" };
+    HttpResponse::build(status).json(json!({
+        "status": kind,
+        "message": message
+    }))
+}
+
+#[post("/documents/merge")]
+pub async fn merge_documents(body: Json<MergeDocumentsSchema>, data: Data<AppState>) -> impl Responder {
+    let body = body.into_inner();
+    let ids = &body.document_ids;
+    if ids.is_empty() || ids.len() > MAX_SOURCES {
+        return fail(StatusCode::BAD_REQUEST, format!("document_ids must list 1 to {} documents", MAX_SOURCES));
+    }
+    if ids.iter().collect::<HashSet<_>>().len() != ids.len() {
+        return fail(StatusCode::BAD_REQUEST, "document_ids must not repeat a document".to_string());
+    }
+
+    let found = sqlx::query_as!(
+        DocumentModel,
+        "SELECT * FROM documents WHERE id = ANY($1) AND deleted_at IS NULL",
+        ids
+    )
+    .fetch_all(&data.db)
+    .await;
+    let found = match found {
+        Ok(found) => found,
+        Err(error) => return fail(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to get documents: {:?}", error)),
+    };
+
+    // Na ordem pedida
+    let mut sources = Vec::with_capacity(ids.len());
+    for id in ids {
+        match found.iter().find(|document| document.id == *id) {
+            Some(document) => sources.push(document),
+            None => return fail(StatusCode::NOT_FOUND, format!("Document {} not found", id)),
+        }
+    }
+
+    let user_id = sources[0].user_id;
+    for source in &sources {
+        if source.user_id != user_id {
+            return fail(StatusCode::BAD_REQUEST, "All documents must belong to the same user".to_string());
+        }
+        if !IMAGE_MIME_TYPES.contains(&source.mime_type.as_str()) {
+            return fail(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("Document {} is not an image ({})", source.id, source.mime_type),
+            );
+        }
+        // Só entra o que passou pelo antivírus, como no download
+        match source.scan_status.as_str() {
+            scanner::CLEAN => {}
+            scanner::INFECTED => {
+                return fail(
+                    StatusCode::FORBIDDEN,
+                    format!("Document {} is quarantined: it failed the malware scan", source.id),
+                );
+            }
+            scan_status => {
+                return fail(
+                    StatusCode::CONFLICT,
+                    format!("Document {} has not passed the malware scan yet (scan_status: {})", source.id, scan_status),
+                );
+            }
+        }
+    }
+
+    let mut pages = Vec::with_capacity(sources.len());
+    for source in &sources {
+        match read_page(&data, source).await {
+            Ok(page) => pages.push(page),
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                return fail(StatusCode::BAD_REQUEST, format!("Document {} is not a readable image: {}", source.id, error));
+            }
+            Err(error) => {
+                return fail(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to read document {}: {}", source.id, error),
+                );
+            }
+        }
+    }
+
+    let original_filename = body
+        .filename
+        .as_deref()
+        .map(upload::sanitize_filename)
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "merged.pdf".to_string());
+    let mut file = match stage_pdf(&data, &pages, original_filename).await {
+        Ok(file) => file,
+        Err(error) => return fail(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write PDF: {}", error)),
+    };
+
+    // Tipo e metadata vêm da primeira imagem, se não forem informados
+    let document = CreateDocumentSchema {
+        user_id,
+        doc_type: body.doc_type.unwrap_or_else(|| sources[0].doc_type.clone()),
+        metadata: body.metadata.unwrap_or_else(|| sources[0].metadata.clone()),
+        expires_at: sources[0].expires_at,
+    };
+
+    match upload::store_derived_document(&data, &document, &mut file, ids).await {
+        Ok(document) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "document": document,
+            "sources": ids
+        })),
+        Err(error) => error.error_response(),
+    }
+}
+
+// Decifra a imagem e a recodifica em JPEG RGB, o formato que o PDF embute direto
+async fn read_page(data: &AppState, source: &DocumentModel) -> io::Result<JpegPage> {
+    let data_key = data.keyring.open(source.master_key_id.as_deref(), source.wrapped_key.as_deref())?;
+    let mut body = encryption::get(data.storage.as_ref(), &source.filename, data_key.as_ref(), None).await?;
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.next().await {
+        bytes.extend_from_slice(&chunk?);
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let image = image::load_from_memory(&bytes)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?
+            .to_rgb8();
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+            .encode_image(&image)
+            .map_err(io::Error::other)?;
+        Ok(JpegPage { width: image.width(), height: image.height(), jpeg })
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+async fn stage_pdf(data: &AppState, pages: &[JpegPage], original_filename: String) -> io::Result<StagedFile> {
+    let path = data.staging_dir.join(upload::staged_filename("merged.pdf"));
+    fs::write(&path, pdf::from_jpeg_pages(pages)).await?;
+    let (size_bytes, sha256) = match upload::hash_file(&path).await {
+        Ok(hashed) => hashed,
+        Err(error) => {
+            upload::discard(&path).await;
+            return Err(error);
+        }
+    };
+
+    Ok(StagedFile {
+        path,
+        original_filename,
+        size_bytes,
+        mime_type: "application/pdf".to_string(),
+        sha256,
+        image_metadata: None,
+        perceptual_hash: None,
+    })
+}
+
+// Documentos de origem de um documento gerado, na ordem das páginas
+#[get("/documents/{id}/sources")]
+pub async fn get_document_sources(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let document_id = path.into_inner();
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM documents WHERE id = $1 AND deleted_at IS NULL) AS "exists!""#,
+        document_id
+    )
+    .fetch_one(&data.db)
+    .await;
+    match exists {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Document {} not found", document_id)
+            }));
+        }
+        Err(error) => return fail(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to get document: {:?}", error)),
+    }
+
+    let sources = sqlx::query!(
+        r#"SELECT s.position, s.source_document_id, d.original_filename, d.mime_type, d.deleted_at
+           FROM document_sources s JOIN documents d ON d.id = s.source_document_id
+           WHERE s.document_id = $1
+           ORDER BY s.position"#,
+        document_id
+    )
+    .fetch_all(&data.db)
+    .await;
+
+    match sources {
+        Ok(sources) => {
+            let sources: Vec<_> = sources
+                .into_iter()
+                .map(|source| {
+                    json!({
+                        "position": source.position,
+                        "document_id": source.source_document_id,
+                        "original_filename": source.original_filename,
+                        "mime_type": source.mime_type,
+                        "deleted_at": source.deleted_at
+                    })
+                })
+                .collect();
+            HttpResponse::Ok().json(json!({
+                "status": "success",
+                "document_id": document_id,
+                "results": sources.len(),
+                "sources": sources
+            }))
+        }
+        Err(error) => fail(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to get document sources: {:?}", error)),
+    }
+}
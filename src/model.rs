@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc}; // Corrigido para usar DateTime em vez de NaiveDateTime
+use chrono::{DateTime, NaiveDate, Utc}; // Corrigido para usar DateTime em vez de NaiveDateTime
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
@@ -9,6 +9,7 @@ pub struct TaskModel {
     pub title: String,
     pub content: String,
     pub created_at: Option<DateTime<Utc>>, // Alinhado com o tipo DateTime<Utc>
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, FromRow, Deserialize, Serialize)]
@@ -18,4 +19,142 @@ pub struct DocumentModel {
     pub doc_type: String,
     pub filename: String,
     pub created_at: Option<DateTime<Utc>>, // Ajuste para Option
-}
\ No newline at end of file
+    pub original_filename: String,
+    pub size_bytes: i64,
+    pub mime_type: String,
+    pub sha256: String,
+    pub revision: i32,
+    pub image_metadata: Option<serde_json::Value>,
+    pub scan_status: String,
+    pub scan_detail: Option<String>,
+    pub scanned_at: Option<DateTime<Utc>>,
+    pub review_status: String,
+    pub reviewer_id: Option<Uuid>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub rejection_reason: Option<String>,
+    pub metadata: serde_json::Value,
+    pub expires_at: Option<NaiveDate>,
+    pub master_key_id: Option<String>,
+    // Chave de dados embrulhada; não sai nas respostas
+    #[serde(skip_serializing)]
+    pub wrapped_key: Option<String>,
+    pub legal_hold: bool,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub perceptual_hash: Option<i64>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct DocumentRevisionModel {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub revision: i32,
+    pub filename: String,
+    pub original_filename: String,
+    pub size_bytes: i64,
+    pub mime_type: String,
+    pub sha256: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub image_metadata: Option<serde_json::Value>,
+    pub scan_status: String,
+    pub master_key_id: Option<String>,
+    #[serde(skip_serializing)]
+    pub wrapped_key: Option<String>,
+    pub perceptual_hash: Option<i64>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct UploadModel {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub doc_type: String,
+    pub original_filename: String,
+    pub mime_type: String,
+    pub upload_length: i64,
+    pub upload_offset: i64,
+    pub document_id: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub metadata: serde_json::Value,
+    pub expires_at: Option<NaiveDate>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct DocumentTypeModel {
+    pub key: String,
+    pub display_name: String,
+    pub metadata_schema: serde_json::Value,
+    pub aliases: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct ShareLinkModel {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub max_downloads: Option<i32>,
+    pub allowed_ip: Option<String>,
+    pub download_count: i32,
+    pub created_at: Option<DateTime<Utc>>,
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct ShareLinkAccessModel {
+    pub id: Uuid,
+    pub share_link_id: Uuid,
+    pub accessed_at: Option<DateTime<Utc>>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: String,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct RetentionRuleModel {
+    pub doc_type: String,
+    pub retention_years: i32,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct UserLegalHoldModel {
+    pub user_id: Uuid,
+    pub reason: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct UserQuotaModel {
+    pub user_id: Uuid,
+    pub max_bytes: Option<i64>,
+    pub max_documents: Option<i64>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct DocumentDestructionModel {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub user_id: Uuid,
+    pub doc_type: String,
+    pub original_filename: String,
+    pub sha256: String,
+    pub uploaded_at: Option<DateTime<Utc>>,
+    pub retention_years: Option<i32>,
+    pub destroyed_at: Option<DateTime<Utc>>,
+    pub reason: String,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct DocumentSignatureModel {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub revision: i32,
+    pub sha256: String,
+    pub key_id: String,
+    pub payload: String,
+    pub signature: String,
+    pub signed_at: Option<DateTime<Utc>>,
+}
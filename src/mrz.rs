@@ -0,0 +1,475 @@
+// Zona de leitura mecânica (MRZ) dos documentos de viagem, conforme o ICAO 9303.
+// Formatos aceitos: TD1 (3 linhas de 30), TD2 (2 de 36) e TD3 (2 de 44, o dos
+// passaportes). Todos os dígitos verificadores são conferidos.
+use actix_web::{
+    post,
+    web::{Data, Json, Path},
+    HttpResponse, Responder,
+};
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{doc_types, model::DocumentModel, schema::MrzSchema, AppState};
+
+// Só passaportes têm MRZ lida por este endpoint
+const MRZ_DOC_TYPE: &str = "passport";
+
+const WEIGHTS: [u32; 3] = [7, 3, 1];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum MrzFormat {
+    TD1,
+    TD2,
+    TD3,
+}
+
+/// Dados do titular lidos da MRZ.
+#[derive(Debug, Serialize)]
+pub struct Mrz {
+    pub format: MrzFormat,
+    pub document_code: String,
+    pub issuing_country: String,
+    pub surname: String,
+    pub given_names: String,
+    pub document_number: String,
+    pub nationality: String,
+    pub birth_date: NaiveDate,
+    pub sex: String,
+    pub expires_on: NaiveDate,
+}
+
+impl Mrz {
+    /// Campos gravados no metadata do documento.
+    pub fn metadata(&self) -> Value {
+        json!({
+            "mrz_format": self.format,
+            "document_number": self.document_number,
+            "issuing_country": self.issuing_country,
+            "nationality": self.nationality,
+            "surname": self.surname,
+            "given_names": self.given_names,
+            "birth_date": self.birth_date,
+            "sex": self.sex,
+            "expires_on": self.expires_on
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+// Posições de cada campo nas linhas, já recortadas conforme o formato
+struct Layout<'a> {
+    document_code: &'a str,
+    issuing_country: &'a str,
+    name: &'a str,
+    document_number: &'a str,
+    document_number_check: char,
+    nationality: &'a str,
+    birth_date: &'a str,
+    birth_date_check: char,
+    sex: char,
+    expires_on: &'a str,
+    expires_on_check: char,
+    optional_data: &'a str,
+    // Só o TD3 tem dígito próprio para os dados opcionais
+    optional_data_check: Option<char>,
+    composite: String,
+    composite_check: char,
+}
+
+fn char_at(line: &str, index: usize) -> char {
+    line.as_bytes()[index] as char
+}
+
+fn layout(format: MrzFormat, lines: &[String]) -> Layout<'_> {
+    match format {
+        MrzFormat::TD1 => {
+            let (l1, l2, l3) = (&lines[0], &lines[1], &lines[2]);
+            Layout {
+                document_code: &l1[0..2],
+                issuing_country: &l1[2..5],
+                name: l3,
+                document_number: &l1[5..14],
+                document_number_check: char_at(l1, 14),
+                nationality: &l2[15..18],
+                birth_date: &l2[0..6],
+                birth_date_check: char_at(l2, 6),
+                sex: char_at(l2, 7),
+                expires_on: &l2[8..14],
+                expires_on_check: char_at(l2, 14),
+                optional_data: &l1[15..30],
+                optional_data_check: None,
+                composite: format!("{}{}{}{}", &l1[5..30], &l2[0..7], &l2[8..15], &l2[18..29]),
+                composite_check: char_at(l2, 29),
+            }
+        }
+        MrzFormat::TD2 | MrzFormat::TD3 => {
+            let (l1, l2) = (&lines[0], &lines[1]);
+            let width = l2.len();
+            let optional_end = if format == MrzFormat::TD3 { width - 2 } else { width - 1 };
+            Layout {
+                document_code: &l1[0..2],
+                issuing_country: &l1[2..5],
+                name: &l1[5..],
+                document_number: &l2[0..9],
+                document_number_check: char_at(l2, 9),
+                nationality: &l2[10..13],
+                birth_date: &l2[13..19],
+                birth_date_check: char_at(l2, 19),
+                sex: char_at(l2, 20),
+                expires_on: &l2[21..27],
+                expires_on_check: char_at(l2, 27),
+                optional_data: &l2[28..optional_end],
+                optional_data_check: (format == MrzFormat::TD3).then(|| char_at(l2, width - 2)),
+                composite: format!("{}{}{}", &l2[0..10], &l2[13..20], &l2[21..width - 1]),
+                composite_check: char_at(l2, width - 1),
+            }
+        }
+    }
+}
+
+/// Dígito verificador do ICAO 9303: pesos 7, 3, 1; letras valem 10 a 35 e `<` vale 0.
+pub fn check_digit(value: &str) -> u32 {
+    value
+        .chars()
+        .zip(WEIGHTS.iter().cycle())
+        .map(|(c, weight)| {
+            let value = match c {
+                '0'..='9' => c as u32 - '0' as u32,
+                'A'..='Z' => c as u32 - 'A' as u32 + 10,
+                _ => 0,
+            };
+            value * weight
+        })
+        .sum::<u32>()
+        % 10
+}
+
+#[derive(Default)]
+struct Errors(Vec<FieldError>);
+
+impl Errors {
+    fn push(&mut self, field: &'static str, message: String) {
+        self.0.push(FieldError { field, message });
+    }
+
+    fn check(&mut self, field: &'static str, value: &str, digit: char) {
+        let expected = check_digit(value);
+        match digit.to_digit(10) {
+            Some(found) if found == expected => {}
+            Some(found) => self.push(field, format!("check digit is {}, expected {}", found, expected)),
+            None => self.push(field, format!("check digit '{}' is not a digit, expected {}", digit, expected)),
+        }
+    }
+
+    fn country(&mut self, field: &'static str, value: &str) -> String {
+        let code = value.trim_end_matches('<');
+        if code.is_empty() || !code.chars().all(|c| c.is_ascii_uppercase()) {
+            self.push(field, format!("'{}' is not a country code", value));
+        }
+        code.to_string()
+    }
+
+    // Datas YYMMDD; o século é deduzido a partir do ano atual
+    fn date(&mut self, field: &'static str, value: &str, future: bool) -> Option<NaiveDate> {
+        if !value.chars().all(|c| c.is_ascii_digit()) {
+            self.push(field, format!("'{}' is not a YYMMDD date", value));
+            return None;
+        }
+        let year: i32 = value[0..2].parse().unwrap();
+        let month: u32 = value[2..4].parse().unwrap();
+        let day: u32 = value[4..6].parse().unwrap();
+
+        let this_year = Utc::now().year() % 100;
+        let pivot = if future { this_year + 50 } else { this_year };
+        let century = if year > pivot { 1900 } else { 2000 };
+
+        let date = NaiveDate::from_ymd_opt(century + year, month, day);
+        if date.is_none() {
+            self.push(field, format!("'{}' is not a valid date", value));
+        }
+        date
+    }
+}
+
+fn clean(value: &str) -> String {
+    value.trim_matches('<').replace('<', " ").split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Lê uma MRZ (linhas separadas por quebra de linha) e confere cada campo.
+pub fn parse(text: &str) -> Result<Mrz, Vec<FieldError>> {
+    let mut errors = Errors::default();
+
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| line.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    for (index, line) in lines.iter().enumerate() {
+        let invalid = line
+            .char_indices()
+            .find(|(_, c)| !c.is_ascii_uppercase() && !c.is_ascii_digit() && *c != '<');
+        if let Some((position, c)) = invalid {
+            errors.push("mrz", format!("line {} has invalid character '{}' at position {}", index + 1, c, position + 1));
+        }
+    }
+    if !errors.0.is_empty() {
+        return Err(errors.0);
+    }
+
+    let widths: Vec<usize> = lines.iter().map(String::len).collect();
+    let format = match widths[..] {
+        [30, 30, 30] => MrzFormat::TD1,
+        [36, 36] => MrzFormat::TD2,
+        [44, 44] => MrzFormat::TD3,
+        _ => {
+            let widths: Vec<String> = widths.iter().map(usize::to_string).collect();
+            errors.push(
+                "mrz",
+                format!(
+                    "expected 3 lines of 30 (TD1), 2 lines of 36 (TD2) or 2 lines of 44 (TD3) characters; got {} line(s) of {}",
+                    lines.len(),
+                    if widths.is_empty() { "0".to_string() } else { widths.join(", ") }
+                ),
+            );
+            return Err(errors.0);
+        }
+    };
+    let layout = layout(format, &lines);
+
+    if !layout.document_code.starts_with(|c: char| c.is_ascii_uppercase()) {
+        errors.push("document_code", format!("'{}' is not a document code", layout.document_code));
+    }
+    let issuing_country = errors.country("issuing_country", layout.issuing_country);
+    let nationality = errors.country("nationality", layout.nationality);
+
+    let (surname, given_names) = match layout.name.trim_end_matches('<').split_once("<<") {
+        Some((surname, given_names)) => (clean(surname), clean(given_names)),
+        None => (clean(layout.name), String::new()),
+    };
+    if surname.is_empty() {
+        errors.push("name", "primary identifier (surname) is empty".to_string());
+    }
+
+    // Números com mais de 9 caracteres (TD1/TD2) continuam nos dados opcionais,
+    // com `<` no lugar do dígito e o dígito verdadeiro no fim da continuação
+    let mut number = layout.document_number.to_string();
+    let mut number_check = layout.document_number_check;
+    if number_check == '<' && format != MrzFormat::TD3 {
+        let extension = layout.optional_data.split('<').next().unwrap_or_default();
+        if let Some((last, rest)) = extension.chars().last().map(|last| (last, &extension[..extension.len() - 1])) {
+            number.push_str(rest);
+            number_check = last;
+        }
+    }
+    errors.check("document_number", &number, number_check);
+    let document_number = number.replace('<', "");
+    if document_number.is_empty() {
+        errors.push("document_number", "document number is empty".to_string());
+    }
+
+    errors.check("birth_date", layout.birth_date, layout.birth_date_check);
+    let birth_date = errors.date("birth_date", layout.birth_date, false);
+    errors.check("expires_on", layout.expires_on, layout.expires_on_check);
+    let expires_on = errors.date("expires_on", layout.expires_on, true);
+
+    let sex = match layout.sex {
+        'M' | 'F' | 'X' => layout.sex.to_string(),
+        '<' => "X".to_string(),
+        other => {
+            errors.push("sex", format!("'{}' is not M, F or <", other));
+            String::new()
+        }
+    };
+
+    if let Some(digit) = layout.optional_data_check {
+        // Sem dados opcionais o dígito pode ser `<`
+        let empty = layout.optional_data.chars().all(|c| c == '<');
+        if !(empty && digit == '<') {
+            errors.check("optional_data", layout.optional_data, digit);
+        }
+    }
+    errors.check("composite", &layout.composite, layout.composite_check);
+
+    match (birth_date, expires_on) {
+        (Some(birth_date), Some(expires_on)) if errors.0.is_empty() => Ok(Mrz {
+            format,
+            document_code: clean(layout.document_code),
+            issuing_country,
+            surname,
+            given_names,
+            document_number,
+            nationality,
+            birth_date,
+            sex,
+            expires_on,
+        }),
+        _ => Err(errors.0),
+    }
+}
+
+// Lê a MRZ e grava os dados do titular no metadata do passaporte
+#[post("/documents/{id}/mrz")]
+pub async fn parse_document_mrz(
+    path: Path<Uuid>,
+    body: Json<MrzSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    let document_id = path.into_inner();
+
+    let document = match sqlx::query_as!(DocumentModel, "SELECT * FROM documents WHERE id = $1 AND deleted_at IS NULL", document_id)
+        .fetch_optional(&data.db)
+        .await
+    {
+        Ok(Some(document)) => document,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Document {} not found", document_id)
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get document: {:?}", error)
+            }));
+        }
+    };
+
+    if document.doc_type != MRZ_DOC_TYPE {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "fail",
+            "message": format!("MRZ parsing is only available for {} documents, not {}", MRZ_DOC_TYPE, document.doc_type)
+        }));
+    }
+
+    let mrz = match parse(&body.mrz) {
+        Ok(mrz) => mrz,
+        Err(errors) => {
+            return HttpResponse::BadRequest().json(json!({
+                "status": "fail",
+                "message": "Invalid MRZ",
+                "errors": errors
+            }));
+        }
+    };
+
+    // Os campos da MRZ substituem os de mesmo nome; os demais são mantidos
+    let fields = mrz.metadata();
+    let mut metadata = document.metadata.clone();
+    if let (Some(metadata), Some(fields)) = (metadata.as_object_mut(), fields.as_object()) {
+        metadata.extend(fields.clone());
+    }
+    if let Err(error) = doc_types::check(&data.db, &document.doc_type, &metadata).await {
+        return error.error_response();
+    }
+
+    // A validade lida da MRZ passa a valer para o documento
+    match sqlx::query_as!(
+        DocumentModel,
+        r#"UPDATE documents
+           SET metadata = metadata || $2, expires_at = $3
+           WHERE id = $1
+           RETURNING *"#,
+        document_id,
+        fields,
+        mrz.expires_on
+    )
+    .fetch_one(&data.db)
+    .await
+    {
+        Ok(document) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "mrz": mrz,
+            "document": document
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to update document: {:?}", error)
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Espécimes do ICAO 9303 (partes 4, 5 e 6), da fictícia Utopia
+    const TD3: &str = "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<\nL898902C36UTO7408122F1204159ZE184226B<<<<<10";
+    const TD2: &str = "I<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<\nD231458907UTO7408122F1204159<<<<<<<6";
+    const TD1: &str = "I<UTOD231458907<<<<<<<<<<<<<<<\n7408122F1204159UTO<<<<<<<<<<<6\nERIKSSON<<ANNA<MARIA<<<<<<<<<<";
+
+    fn fields(errors: Vec<FieldError>) -> Vec<&'static str> {
+        errors.into_iter().map(|error| error.field).collect()
+    }
+
+    #[test]
+    fn check_digits_follow_icao_weights() {
+        assert_eq!(check_digit("L898902C3"), 6);
+        assert_eq!(check_digit("740812"), 2);
+        assert_eq!(check_digit("120415"), 9);
+        assert_eq!(check_digit("AB2134<<<"), 5);
+        assert_eq!(check_digit("<<<<<<"), 0);
+    }
+
+    #[test]
+    fn parses_passport_td3() {
+        let mrz = parse(TD3).unwrap();
+        assert_eq!(mrz.format, MrzFormat::TD3);
+        assert_eq!(mrz.document_code, "P");
+        assert_eq!(mrz.issuing_country, "UTO");
+        assert_eq!(mrz.surname, "ERIKSSON");
+        assert_eq!(mrz.given_names, "ANNA MARIA");
+        assert_eq!(mrz.document_number, "L898902C3");
+        assert_eq!(mrz.birth_date, NaiveDate::from_ymd_opt(1974, 8, 12).unwrap());
+        assert_eq!(mrz.sex, "F");
+        assert_eq!(mrz.expires_on, NaiveDate::from_ymd_opt(2012, 4, 15).unwrap());
+    }
+
+    #[test]
+    fn parses_td1_and_td2_cards() {
+        for (text, format) in [(TD1, MrzFormat::TD1), (TD2, MrzFormat::TD2)] {
+            let mrz = parse(text).unwrap();
+            assert_eq!(mrz.format, format);
+            assert_eq!(mrz.document_number, "D23145890");
+            assert_eq!(mrz.surname, "ERIKSSON");
+            assert_eq!(mrz.nationality, "UTO");
+        }
+    }
+
+    #[test]
+    fn accepts_lowercase_and_spaces() {
+        let typed = TD3.to_lowercase().replace("<<<<<10", "<< <<<10");
+        assert_eq!(parse(&typed).unwrap().document_number, "L898902C3");
+    }
+
+    #[test]
+    fn reports_each_wrong_check_digit() {
+        // Número de documento alterado: falham o dígito dele e o composto
+        let altered = TD3.replace("L898902C36", "L898902C46");
+        assert_eq!(fields(parse(&altered).unwrap_err()), ["document_number", "composite"]);
+
+        let composite = TD3.replace("<<<<<10", "<<<<<11");
+        let errors = parse(&composite).unwrap_err();
+        assert_eq!(errors[0].field, "composite");
+        assert_eq!(errors[0].message, "check digit is 1, expected 0");
+
+        let td1 = TD1.replace("<<<6\n", "<<<7\n");
+        assert_eq!(fields(parse(&td1).unwrap_err()), ["composite"]);
+    }
+
+    #[test]
+    fn rejects_unknown_layouts() {
+        let errors = parse("P<UTOERIKSSON<<ANNA\nL898902C36UTO").unwrap_err();
+        assert_eq!(errors[0].field, "mrz");
+        assert!(errors[0].message.ends_with("got 2 line(s) of 19, 13"));
+        assert_eq!(fields(parse(&TD3.replace("P<UTO", "P-UTO")).unwrap_err()), ["mrz"]);
+    }
+}
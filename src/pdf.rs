@@ -0,0 +1,112 @@
+// Escrita de PDFs simples, sem dependências: cada página é uma imagem JPEG
+// (DCTDecode) centralizada numa folha A4, na orientação da imagem.
+use std::fmt::Write as _;
+
+// A4 em pontos (1/72 de polegada)
+const A4_SHORT: f64 = 595.0;
+const A4_LONG: f64 = 842.0;
+
+/// Uma página: a imagem já codificada em JPEG (RGB) e o seu tamanho em pixels.
+pub struct JpegPage {
+    pub width: u32,
+    pub height: u32,
+    pub jpeg: Vec<u8>,
+}
+
+/// Monta um PDF com uma página por imagem, na ordem dada.
+pub fn from_jpeg_pages(pages: &[JpegPage]) -> Vec<u8> {
+    let mut writer = Writer::new();
+
+    // 1: catálogo, 2: árvore de páginas; cada página ocupa três objetos
+    let kids: Vec<String> = (0..pages.len()).map(|index| format!("{} 0 R", 3 + index * 3)).collect();
+    writer.object(1, b"<< /Type /Catalog /Pages 2 0 R >>");
+    writer.object(
+        2,
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()).as_bytes(),
+    );
+
+    for (index, page) in pages.iter().enumerate() {
+        let page_id = 3 + index * 3;
+        let (page_width, page_height) = if page.width > page.height { (A4_LONG, A4_SHORT) } else { (A4_SHORT, A4_LONG) };
+        let scale = (page_width / page.width as f64).min(page_height / page.height as f64);
+        let (drawn_width, drawn_height) = (page.width as f64 * scale, page.height as f64 * scale);
+
+        let mut content = String::new();
+        let _ = write!(
+            content,
+            "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im0 Do Q",
+            drawn_width,
+            drawn_height,
+            (page_width - drawn_width) / 2.0,
+            (page_height - drawn_height) / 2.0
+        );
+
+        writer.object(
+            page_id,
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                page_width,
+                page_height,
+                page_id + 2,
+                page_id + 1
+            )
+            .as_bytes(),
+        );
+        writer.stream(page_id + 1, "", content.as_bytes());
+        writer.stream(
+            page_id + 2,
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode",
+                page.width, page.height
+            ),
+            &page.jpeg,
+        );
+    }
+
+    writer.finish(1)
+}
+
+// Grava os objetos guardando a posição de cada um para a tabela xref
+struct Writer {
+    out: Vec<u8>,
+    offsets: Vec<(usize, usize)>,
+}
+
+impl Writer {
+    fn new() -> Self {
+        // O comentário binário avisa aos leitores que o arquivo não é texto puro
+        Writer { out: b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec(), offsets: Vec::new() }
+    }
+
+    fn object(&mut self, id: usize, body: &[u8]) {
+        self.offsets.push((id, self.out.len()));
+        self.out.extend_from_slice(format!("{} 0 obj\n", id).as_bytes());
+        self.out.extend_from_slice(body);
+        self.out.extend_from_slice(b"\nendobj\n");
+    }
+
+    fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) {
+        self.offsets.push((id, self.out.len()));
+        self.out.extend_from_slice(format!("{} 0 obj\n<< {} /Length {} >>\nstream\n", id, dictionary, data.len()).as_bytes());
+        self.out.extend_from_slice(data);
+        self.out.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    fn finish(mut self, root: usize) -> Vec<u8> {
+        self.offsets.sort_unstable();
+        let xref = self.out.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for (_, offset) in &self.offsets {
+            let _ = writeln!(table, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            table,
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            root,
+            xref
+        );
+        self.out.extend_from_slice(table.as_bytes());
+        self.out
+    }
+}
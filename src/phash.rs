@@ -0,0 +1,316 @@
+// Hash perceptual (dHash) das imagens, para achar a mesma foto enviada de novo,
+// por qualquer usuário, mesmo recomprimida ou levemente cortada. A imagem é
+// reduzida a 9x8 em tons de cinza e cada um dos 64 bits diz se um pixel é mais
+// claro que o vizinho da direita; fotos parecidas diferem em poucos bits.
+use std::{io, path::Path, sync::Arc};
+
+use actix_web::{
+    get,
+    web::{Data, Path as UrlPath, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use image::{imageops::FilterType, DynamicImage, ImageReader};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{
+    encryption::{self, Keyring},
+    schema::NearDuplicateQuery,
+    storage::Storage,
+    AppState,
+};
+
+const IMAGE_MIME_TYPES: [&str; 4] = ["image/jpeg", "image/png", "image/webp", "image/tiff"];
+
+/// Distância de Hamming padrão: até 10 dos 64 bits diferentes.
+pub const DEFAULT_MAX_DISTANCE: i32 = 10;
+
+/// Máximo de documentos numa busca; `limit` maior é reduzido a este.
+pub const MAX_NEAR_DUPLICATES: i64 = 100;
+
+/// dHash de uma imagem em disco, ou `None` se não é imagem ou não pôde ser lida.
+pub async fn compute(path: &Path, mime_type: &str) -> Option<i64> {
+    if !IMAGE_MIME_TYPES.contains(&mime_type) {
+        return None;
+    }
+    let path = path.to_path_buf();
+    let decoded = tokio::task::spawn_blocking(move || {
+        ImageReader::open(&path)?
+            .with_guessed_format()?
+            .decode()
+            .map(|image| dhash(&image))
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    })
+    .await
+    .map_err(io::Error::other)
+    .and_then(|hash| hash);
+
+    match decoded {
+        Ok(hash) => Some(hash),
+        Err(error) => {
+            log::warn!("Failed to compute perceptual hash: {}", error);
+            None
+        }
+    }
+}
+
+fn dhash(image: &DynamicImage) -> i64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash: u64 = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    // Guardado no BIGINT com os mesmos bits
+    hash as i64
+}
+
+/// Calcula, um conteúdo por vez, o hash das imagens enviadas antes dele existir.
+pub fn spawn_backfill(db: Pool<Postgres>, storage: Arc<dyn Storage>, keyring: Arc<Keyring>) {
+    actix_web::rt::spawn(async move {
+        let contents = sqlx::query!(
+            r#"SELECT filename AS "filename!", mime_type AS "mime_type!" FROM documents
+               WHERE perceptual_hash IS NULL AND mime_type = ANY($1)
+               UNION
+               SELECT filename, mime_type FROM document_revisions
+               WHERE perceptual_hash IS NULL AND mime_type = ANY($1)"#,
+            &IMAGE_MIME_TYPES.map(String::from)[..]
+        )
+        .fetch_all(&db)
+        .await;
+
+        match contents {
+            Ok(contents) => {
+                for content in contents {
+                    backfill(&db, storage.as_ref(), &keyring, &content.filename, &content.mime_type).await;
+                }
+            }
+            Err(error) => log::warn!("Failed to list images without perceptual hash: {:?}", error),
+        }
+    });
+}
+
+// O hash vale para o conteúdo, então vai para todos os documentos e revisões que apontam para ele
+async fn backfill(db: &Pool<Postgres>, storage: &dyn Storage, keyring: &Keyring, key: &str, mime_type: &str) {
+    let read = async {
+        let data_key = encryption::content_key(db, keyring, key).await?;
+        let mut body = encryption::get(storage, key, data_key.as_ref(), None).await?;
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.next().await {
+            bytes.extend_from_slice(&chunk?);
+        }
+        Ok::<_, io::Error>(bytes)
+    }
+    .await;
+    let bytes = match read {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            log::warn!("Failed to read stored content {}: {}", key, error);
+            return;
+        }
+    };
+
+    let decoded = tokio::task::spawn_blocking(move || image::load_from_memory(&bytes).map(|image| dhash(&image))).await;
+    let hash = match decoded {
+        Ok(Ok(hash)) => hash,
+        Ok(Err(error)) => {
+            log::warn!("Failed to compute perceptual hash of {} ({}): {}", key, mime_type, error);
+            return;
+        }
+        Err(error) => {
+            log::warn!("Failed to compute perceptual hash of {}: {}", key, error);
+            return;
+        }
+    };
+
+    let recorded = async {
+        let mut tx = db.begin().await?;
+        sqlx::query!("UPDATE documents SET perceptual_hash = $1 WHERE filename = $2", hash, key)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("UPDATE document_revisions SET perceptual_hash = $1 WHERE filename = $2", hash, key)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+    .await;
+    if let Err(error) = recorded {
+        log::warn!("Failed to record perceptual hash of {}: {:?}", key, error);
+    }
+}
+
+/// Documento com imagem parecida e quantos bits do hash diferem.
+#[derive(Debug, Serialize)]
+pub struct NearDuplicate {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub doc_type: String,
+    pub original_filename: String,
+    pub sha256: String,
+    pub review_status: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub distance: i64,
+}
+
+// Outros documentos, de qualquer usuário, a até `max_distance` bits do hash deste
+#[get("/documents/{id}/near-duplicates")]
+pub async fn get_near_duplicates(
+    path: UrlPath<Uuid>,
+    opts: Query<NearDuplicateQuery>,
+    data: Data<AppState>
+) -> impl Responder {
+    let document_id = path.into_inner();
+    let max_distance = opts.max_distance.unwrap_or(DEFAULT_MAX_DISTANCE);
+    if !(0..=64).contains(&max_distance) {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "fail",
+            "message": "max_distance must be between 0 and 64"
+        }));
+    }
+    let limit = opts.limit.unwrap_or(10);
+    if limit < 0 {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "fail",
+            "message": "limit must not be negative"
+        }));
+    }
+    let limit = limit.min(MAX_NEAR_DUPLICATES);
+
+    let hash = sqlx::query_scalar!(
+        "SELECT perceptual_hash FROM documents WHERE id = $1 AND deleted_at IS NULL",
+        document_id
+    )
+    .fetch_optional(&data.db)
+    .await;
+    let hash = match hash {
+        Ok(Some(Some(hash))) => hash,
+        Ok(Some(None)) => {
+            return HttpResponse::UnprocessableEntity().json(json!({
+                "status": "fail",
+                "message": format!("Document {} has no perceptual hash; only images have one", document_id)
+            }));
+        }
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Document {} not found", document_id)
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get document: {:?}", error)
+            }));
+        }
+    };
+
+    let found = sqlx::query_as!(
+        NearDuplicate,
+        r#"SELECT id, user_id, doc_type, original_filename, sha256, review_status, created_at,
+                  bit_count((perceptual_hash # $1)::bit(64)) AS "distance!"
+           FROM documents
+           WHERE perceptual_hash IS NOT NULL AND id <> $2 AND deleted_at IS NULL
+             AND bit_count((perceptual_hash # $1)::bit(64)) <= $3
+           ORDER BY "distance!", created_at
+           LIMIT $4"#,
+        hash,
+        document_id,
+        max_distance as i64,
+        limit
+    )
+    .fetch_all(&data.db)
+    .await;
+
+    match found {
+        Ok(documents) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "document_id": document_id,
+            "max_distance": max_distance,
+            "limit": limit,
+            "results": documents.len(),
+            "documents": documents
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to find near duplicates: {:?}", error)
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{codecs::jpeg::JpegEncoder, RgbImage};
+
+    use super::*;
+
+    // O mesmo que bit_count((a # b)::bit(64)) na busca
+    fn distance(a: i64, b: i64) -> u32 {
+        (a ^ b).count_ones()
+    }
+
+    // Blocos de tons pseudoaleatórios, únicos por semente
+    fn blocks(seed: u64, width: u32, height: u32) -> DynamicImage {
+        let mut state = seed | 1;
+        let shades: Vec<u8> = (0..16 * 12)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state % 256) as u8
+            })
+            .collect();
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let shade = shades[((y * 12 / height) * 16 + x * 16 / width) as usize];
+            image::Rgb([shade, shade / 2, 255 - shade])
+        }))
+    }
+
+    fn recompressed(image: &DynamicImage, quality: u8) -> DynamicImage {
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, quality).encode_image(image).unwrap();
+        image::load(Cursor::new(jpeg), image::ImageFormat::Jpeg).unwrap()
+    }
+
+    #[test]
+    fn identical_images_have_the_same_hash() {
+        let image = blocks(7, 320, 240);
+        assert_eq!(dhash(&image), dhash(&blocks(7, 320, 240)));
+        assert_eq!(distance(dhash(&image), dhash(&image)), 0);
+    }
+
+    #[test]
+    fn recompressed_and_resized_images_stay_close() {
+        let image = blocks(7, 320, 240);
+        let hash = dhash(&image);
+
+        assert!(distance(hash, dhash(&recompressed(&image, 40))) <= DEFAULT_MAX_DISTANCE as u32);
+        let resized = image.resize_exact(200, 150, FilterType::Triangle);
+        assert!(distance(hash, dhash(&resized)) <= DEFAULT_MAX_DISTANCE as u32);
+    }
+
+    #[test]
+    fn different_images_are_far_apart() {
+        let hash = dhash(&blocks(7, 320, 240));
+        for seed in [11, 42, 1234] {
+            assert!(distance(hash, dhash(&blocks(seed, 320, 240))) > DEFAULT_MAX_DISTANCE as u32);
+        }
+    }
+
+    #[test]
+    fn hash_keeps_all_64_bits() {
+        // Uma rampa que escurece para a direita liga todos os bits, inclusive o de sinal
+        let ramp = DynamicImage::ImageLuma8(image::GrayImage::from_fn(90, 80, |x, _| image::Luma([255 - x as u8 * 2])));
+        assert_eq!(dhash(&ramp), -1);
+        assert_eq!(distance(dhash(&ramp), 0), 64);
+    }
+}
@@ -0,0 +1,299 @@
+// Cotas de armazenamento por usuário. O consumo conta os documentos do usuário,
+// lixeira incluída, e os bytes dos conteúdos distintos das revisões deles; o
+// limite vem de user_quotas ou, na falta, de QUOTA_MAX_BYTES e QUOTA_MAX_DOCUMENTS.
+// A parte da lixeira aparece à parte (trash_bytes, trash_documents), já somada ao total.
+use std::io;
+
+use actix_web::{
+    delete, get, put,
+    web::{Data, Json, Path},
+    http::StatusCode,
+    HttpResponse, Responder,
+};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{model::UserQuotaModel, schema::QuotaSchema, AppState};
+
+/// Limites para quem não tem cota própria; `None` é sem limite.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuotaDefaults {
+    pub max_bytes: Option<i64>,
+    pub max_documents: Option<i64>,
+}
+
+impl QuotaDefaults {
+    /// Lê QUOTA_MAX_BYTES e QUOTA_MAX_DOCUMENTS; variável ausente é sem limite.
+    pub fn from_env() -> io::Result<Self> {
+        Ok(QuotaDefaults {
+            max_bytes: limit_from_env("QUOTA_MAX_BYTES")?,
+            max_documents: limit_from_env("QUOTA_MAX_DOCUMENTS")?,
+        })
+    }
+}
+
+fn limit_from_env(name: &str) -> io::Result<Option<i64>> {
+    match std::env::var(name) {
+        Ok(value) if value.trim().is_empty() => Ok(None),
+        Ok(value) => match value.trim().parse::<i64>() {
+            Ok(limit) if limit >= 0 => Ok(Some(limit)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid {}: {}", name, value),
+            )),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+/// Consumo atual de um usuário e os limites que valem para ele.
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    pub user_id: Uuid,
+    /// Inclui `trash_bytes`.
+    pub bytes_used: i64,
+    /// Inclui `trash_documents`.
+    pub documents: i64,
+    /// Bytes que só documentos na lixeira usam, liberados quando ela for esvaziada.
+    pub trash_bytes: i64,
+    pub trash_documents: i64,
+    pub max_bytes: Option<i64>,
+    pub max_documents: Option<i64>,
+}
+
+#[derive(Debug)]
+pub enum Limit {
+    Bytes,
+    Documents,
+}
+
+/// Um envio que passaria da cota do usuário.
+#[derive(Debug)]
+pub struct Exceeded {
+    pub limit: Limit,
+    pub requested_bytes: i64,
+    pub usage: Usage,
+}
+
+impl Exceeded {
+    /// 413 para espaço, 403 para número de documentos.
+    pub fn status(&self) -> StatusCode {
+        match self.limit {
+            Limit::Bytes => StatusCode::PAYLOAD_TOO_LARGE,
+            Limit::Documents => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl std::fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let usage = &self.usage;
+        match self.limit {
+            Limit::Bytes => write!(
+                f,
+                "Storage quota exceeded: {} of {} bytes in use, the upload needs {} more",
+                usage.bytes_used,
+                usage.max_bytes.unwrap_or_default(),
+                self.requested_bytes
+            ),
+            Limit::Documents => write!(
+                f,
+                "Document quota exceeded: {} of {} documents in use",
+                usage.documents,
+                usage.max_documents.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Soma o consumo de `user_id` e resolve os limites dele.
+pub async fn usage<'c, E>(executor: E, defaults: &QuotaDefaults, user_id: Uuid) -> Result<Usage, sqlx::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    // Conteúdo repetido entre documentos do mesmo usuário conta uma vez só
+    let row = sqlx::query!(
+        r#"SELECT
+               (SELECT COALESCE(SUM(size_bytes), 0)::BIGINT FROM (
+                    SELECT DISTINCT r.filename, r.size_bytes
+                    FROM document_revisions r JOIN documents d ON d.id = r.document_id
+                    WHERE d.user_id = $1
+               ) contents) AS "bytes_used!",
+               (SELECT COUNT(*) FROM documents WHERE user_id = $1) AS "documents!",
+               (SELECT COALESCE(SUM(size_bytes), 0)::BIGINT FROM (
+                    SELECT DISTINCT r.filename, r.size_bytes
+                    FROM document_revisions r JOIN documents d ON d.id = r.document_id
+                    WHERE d.user_id = $1 AND d.deleted_at IS NOT NULL
+                    EXCEPT
+                    SELECT r.filename, r.size_bytes
+                    FROM document_revisions r JOIN documents d ON d.id = r.document_id
+                    WHERE d.user_id = $1 AND d.deleted_at IS NULL
+               ) trashed) AS "trash_bytes!",
+               (SELECT COUNT(*) FROM documents WHERE user_id = $1 AND deleted_at IS NOT NULL) AS "trash_documents!",
+               q.max_bytes AS "max_bytes?",
+               q.max_documents AS "max_documents?"
+           FROM (SELECT 1) one LEFT JOIN user_quotas q ON q.user_id = $1"#,
+        user_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(Usage {
+        user_id,
+        bytes_used: row.bytes_used,
+        documents: row.documents,
+        trash_bytes: row.trash_bytes,
+        trash_documents: row.trash_documents,
+        max_bytes: row.max_bytes.or(defaults.max_bytes),
+        max_documents: row.max_documents.or(defaults.max_documents),
+    })
+}
+
+/// Confere se o conteúdo `key`, de `size_bytes`, e mais `new_documents`
+/// documentos cabem na cota de `user_id`.
+///
+/// Trava o usuário até o fim da transação, para que envios simultâneos não
+/// passem juntos pela mesma folga. Conteúdo que o usuário já tem não conta de novo.
+pub async fn check(
+    tx: &mut Transaction<'_, Postgres>,
+    defaults: &QuotaDefaults,
+    user_id: Uuid,
+    key: &str,
+    size_bytes: i64,
+    new_documents: i64,
+) -> Result<Option<Exceeded>, sqlx::Error> {
+    lock(tx, user_id).await?;
+    let usage = usage(&mut *tx, defaults, user_id).await?;
+    let known = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+               SELECT 1 FROM document_revisions r JOIN documents d ON d.id = r.document_id
+               WHERE d.user_id = $1 AND r.filename = $2
+           ) AS "known!""#,
+        user_id,
+        key
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    let requested_bytes = if known { 0 } else { size_bytes };
+    Ok(exceeded(usage, requested_bytes, new_documents))
+}
+
+/// Confere se o documento `document_id`, com todas as revisões, cabe na cota
+/// de `user_id`, que passaria a ser o dono dele. Mesma trava de [`check`].
+pub async fn check_transfer(
+    tx: &mut Transaction<'_, Postgres>,
+    defaults: &QuotaDefaults,
+    user_id: Uuid,
+    document_id: Uuid,
+) -> Result<Option<Exceeded>, sqlx::Error> {
+    lock(tx, user_id).await?;
+    let usage = usage(&mut *tx, defaults, user_id).await?;
+    // Só os conteúdos que o novo dono ainda não tem
+    let requested_bytes = sqlx::query_scalar!(
+        r#"SELECT COALESCE(SUM(size_bytes), 0)::BIGINT AS "requested!" FROM (
+               SELECT DISTINCT filename, size_bytes FROM document_revisions WHERE document_id = $2
+               EXCEPT
+               SELECT r.filename, r.size_bytes
+               FROM document_revisions r JOIN documents d ON d.id = r.document_id
+               WHERE d.user_id = $1
+           ) contents"#,
+        user_id,
+        document_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    Ok(exceeded(usage, requested_bytes, 1))
+}
+
+async fn lock(tx: &mut Transaction<'_, Postgres>, user_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"SELECT true AS "locked!" FROM pg_advisory_xact_lock(hashtextextended($1, 0))"#,
+        user_id.to_string()
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+/// Compara o consumo mais o pedido com os limites.
+pub fn exceeded(usage: Usage, requested_bytes: i64, new_documents: i64) -> Option<Exceeded> {
+    let limit = match (usage.max_bytes, usage.max_documents) {
+        (Some(max_bytes), _) if usage.bytes_used + requested_bytes > max_bytes => Limit::Bytes,
+        (_, Some(max_documents)) if usage.documents + new_documents > max_documents => Limit::Documents,
+        _ => return None,
+    };
+    Some(Exceeded { limit, requested_bytes, usage })
+}
+
+fn database_error(action: &str, error: sqlx::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(json!({
+        "status": "error",
+        "message": format!("Failed to {}: {:?}", action, error)
+    }))
+}
+
+#[get("/users/{user_id}/usage")]
+pub async fn get_user_usage(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    match usage(&data.db, &data.quota_defaults, path.into_inner()).await {
+        Ok(usage) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "usage": usage
+        })),
+        Err(error) => database_error("get usage", error),
+    }
+}
+
+// Campo ausente (ou null) volta ao padrão da configuração
+#[put("/users/{user_id}/quota")]
+pub async fn put_user_quota(
+    path: Path<Uuid>,
+    body: Json<QuotaSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    let user_id = path.into_inner();
+    if body.max_bytes.unwrap_or_default() < 0 || body.max_documents.unwrap_or_default() < 0 {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "fail",
+            "message": "max_bytes and max_documents must not be negative"
+        }));
+    }
+
+    match sqlx::query_as!(
+        UserQuotaModel,
+        r#"INSERT INTO user_quotas (user_id, max_bytes, max_documents) VALUES ($1, $2, $3)
+           ON CONFLICT (user_id) DO UPDATE
+           SET max_bytes = EXCLUDED.max_bytes, max_documents = EXCLUDED.max_documents, updated_at = now()
+           RETURNING *"#,
+        user_id,
+        body.max_bytes,
+        body.max_documents
+    )
+    .fetch_one(&data.db)
+    .await
+    {
+        Ok(quota) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "quota": quota
+        })),
+        Err(error) => database_error("set quota", error),
+    }
+}
+
+#[delete("/users/{user_id}/quota")]
+pub async fn delete_user_quota(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let user_id = path.into_inner();
+
+    match sqlx::query!("DELETE FROM user_quotas WHERE user_id = $1", user_id)
+        .execute(&data.db)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("User {} has no quota of its own", user_id)
+        })),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(error) => database_error("delete quota", error),
+    }
+}
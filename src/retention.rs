@@ -0,0 +1,468 @@
+// Retenção: cada doc_type pode ter um prazo em anos (retention_rules). Passado
+// o prazo, contado do upload, a limpeza destrói o documento, as revisões e o
+// conteúdo. Documentos com legal_hold, ou de usuários sob retenção legal, nunca
+// são destruídos; cada destruição, da limpeza ou da lixeira, fica em
+// document_destructions e no log.
+use std::{io, sync::Arc, time::Duration};
+
+use actix_web::{
+    delete, get, post, put,
+    web::{Data, Json, Path, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::{Pool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    blobs, doc_types,
+    model::{DocumentDestructionModel, DocumentModel, RetentionRuleModel, UserLegalHoldModel},
+    schema::{FilterOptions, LegalHoldSchema, RetentionRuleSchema},
+    storage::Storage,
+    AppState,
+};
+
+/// Intervalo entre as limpezas, em RETENTION_PURGE_INTERVAL_SECS (padrão 1 dia).
+pub fn purge_interval_from_env() -> io::Result<Duration> {
+    match std::env::var("RETENTION_PURGE_INTERVAL_SECS") {
+        Ok(value) => match value.parse::<u64>() {
+            Ok(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid RETENTION_PURGE_INTERVAL_SECS: {}", value),
+            )),
+        },
+        Err(_) => Ok(Duration::from_secs(24 * 60 * 60)),
+    }
+}
+
+/// Trava a linha do documento e diz se ele está sob retenção legal, própria
+/// ou do usuário; `None` se o documento não existe.
+pub async fn lock_hold(tx: &mut Transaction<'_, Postgres>, document_id: Uuid) -> Result<Option<bool>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT d.legal_hold OR EXISTS (SELECT 1 FROM user_legal_holds h WHERE h.user_id = d.user_id) AS "held!"
+           FROM documents d WHERE d.id = $1
+           FOR UPDATE OF d"#,
+        document_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+}
+
+/// Apaga o documento e as revisões, soltando as referências aos blobs.
+///
+/// Devolve também as chaves que ficaram sem uso, para `blobs::remove_unused`
+/// depois do commit.
+async fn delete_document(
+    tx: &mut Transaction<'_, Postgres>,
+    document_id: Uuid,
+) -> Result<(Option<DocumentModel>, Vec<String>), sqlx::Error> {
+    // Cada revisão guarda uma referência ao seu blob
+    let revisions = sqlx::query!(
+        "DELETE FROM document_revisions WHERE document_id = $1 RETURNING filename",
+        document_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let document = sqlx::query_as!(DocumentModel, "DELETE FROM documents WHERE id = $1 RETURNING *", document_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let mut unused = Vec::new();
+    for revision in revisions {
+        if blobs::release(tx, &revision.filename).await? {
+            unused.push(revision.filename);
+        }
+    }
+    Ok((document, unused))
+}
+
+/// Motivos gravados em `document_destructions.reason`.
+pub const RETENTION: &str = "retention";
+pub const TRASH: &str = "trash";
+
+/// Documento apagado por `destroy`, ainda sem o conteúdo removido e sem log.
+pub struct Destruction {
+    pub document: DocumentModel,
+    retention_years: Option<i32>,
+    reason: &'static str,
+    unused: Vec<String>,
+}
+
+/// Apaga o documento e registra a destruição em `document_destructions`;
+/// `None` se o documento não existe. Depois do commit, chame `finish`.
+pub async fn destroy(
+    tx: &mut Transaction<'_, Postgres>,
+    document_id: Uuid,
+    reason: &'static str,
+) -> Result<Option<Destruction>, sqlx::Error> {
+    let (Some(document), unused) = delete_document(tx, document_id).await? else {
+        return Ok(None);
+    };
+    let retention_years = sqlx::query_scalar!(
+        "SELECT retention_years FROM retention_rules WHERE doc_type = $1",
+        document.doc_type
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"INSERT INTO document_destructions
+               (document_id, user_id, doc_type, original_filename, sha256, uploaded_at, retention_years, reason)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+        document.id,
+        document.user_id,
+        document.doc_type,
+        document.original_filename,
+        document.sha256,
+        document.created_at,
+        retention_years,
+        reason
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(Some(Destruction { document, retention_years, reason, unused }))
+}
+
+impl Destruction {
+    /// Remove o conteúdo que ficou sem uso e registra a destruição no log.
+    pub async fn finish(self, db: &Pool<Postgres>, storage: &dyn Storage) {
+        blobs::remove_unused(db, storage, &self.unused).await;
+
+        let document = &self.document;
+        let why = match (self.reason, self.retention_years) {
+            (RETENTION, Some(years)) => format!("after {} year(s) of retention", years),
+            (TRASH, _) => "purged from the trash".to_string(),
+            (reason, _) => reason.to_string(),
+        };
+        log::warn!(
+            "Destroyed document {} (user {}, doc_type {}, sha256 {}) {}",
+            document.id,
+            document.user_id,
+            document.doc_type,
+            document.sha256,
+            why
+        );
+    }
+}
+
+/// Até quando o documento ainda precisa ser guardado, se o prazo de
+/// retenção do doc_type dele não passou.
+pub async fn kept_until(
+    tx: &mut Transaction<'_, Postgres>,
+    document_id: Uuid,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT d.created_at + make_interval(years => r.retention_years) AS "kept_until!"
+           FROM documents d JOIN retention_rules r ON r.doc_type = d.doc_type
+           WHERE d.id = $1 AND d.created_at + make_interval(years => r.retention_years) > now()"#,
+        document_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+}
+
+/// Quantos documentos vencidos foram destruídos, quantos ficaram por estar sob
+/// retenção legal e quantos falharam (e ficam para a próxima limpeza).
+pub struct PurgeReport {
+    pub destroyed: u64,
+    pub held: u64,
+    pub failed: u64,
+}
+
+enum Purged {
+    Destroyed,
+    Held,
+    Gone,
+}
+
+/// Destrói os documentos cujo prazo de retenção passou, um por transação; a
+/// falha em um documento não impede os seguintes.
+pub async fn purge(db: &Pool<Postgres>, storage: &dyn Storage) -> Result<PurgeReport, sqlx::Error> {
+    let due = sqlx::query!(
+        r#"SELECT d.id FROM documents d
+           JOIN retention_rules r ON r.doc_type = d.doc_type
+           WHERE d.created_at + make_interval(years => r.retention_years) <= now()
+           ORDER BY d.created_at"#
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut report = PurgeReport { destroyed: 0, held: 0, failed: 0 };
+    for row in due {
+        let purged = async {
+            let mut tx = db.begin().await?;
+            // O hold é conferido de novo com a linha travada, pois pode ter mudado desde a consulta
+            match lock_hold(&mut tx, row.id).await? {
+                Some(false) => {}
+                Some(true) => return Ok(Purged::Held),
+                None => return Ok(Purged::Gone),
+            }
+
+            let Some(destruction) = destroy(&mut tx, row.id, RETENTION).await? else {
+                return Ok(Purged::Gone);
+            };
+            tx.commit().await?;
+            destruction.finish(db, storage).await;
+            Ok::<_, sqlx::Error>(Purged::Destroyed)
+        }
+        .await;
+
+        match purged {
+            Ok(Purged::Destroyed) => report.destroyed += 1,
+            Ok(Purged::Held) => {
+                log::info!("Document {} is past its retention period but under legal hold; not destroyed", row.id);
+                report.held += 1;
+            }
+            Ok(Purged::Gone) => {}
+            Err(error) => {
+                log::warn!("Failed to destroy document {} past its retention period: {:?}", row.id, error);
+                report.failed += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Roda a limpeza ao subir o servidor e depois a cada `interval`.
+pub fn spawn_purger(db: Pool<Postgres>, storage: Arc<dyn Storage>, interval: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match purge(&db, storage.as_ref()).await {
+                Ok(report) if report.failed > 0 => log::warn!(
+                    "Retention purge destroyed {} document(s); {} failed and wait for the next run",
+                    report.destroyed,
+                    report.failed
+                ),
+                Ok(report) if report.destroyed > 0 => {
+                    log::info!("Retention purge destroyed {} document(s)", report.destroyed)
+                }
+                Ok(_) => {}
+                Err(error) => log::warn!("Failed to purge documents past retention: {:?}", error),
+            }
+        }
+    });
+}
+
+fn database_error(action: &str, error: sqlx::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(json!({
+        "status": "error",
+        "message": format!("Failed to {}: {:?}", action, error)
+    }))
+}
+
+#[get("/admin/retention-rules")]
+pub async fn get_retention_rules(data: Data<AppState>) -> impl Responder {
+    match sqlx::query_as!(RetentionRuleModel, "SELECT * FROM retention_rules ORDER BY doc_type")
+        .fetch_all(&data.db)
+        .await
+    {
+        Ok(rules) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "results": rules.len(),
+            "retention_rules": rules
+        })),
+        Err(error) => database_error("list retention rules", error),
+    }
+}
+
+// Cria ou troca o prazo do doc_type; vale também para os documentos já guardados
+#[put("/admin/retention-rules/{doc_type}")]
+pub async fn put_retention_rule(
+    path: Path<String>,
+    body: Json<RetentionRuleSchema>,
+    data: Data<AppState>
+) -> impl Responder {
+    let doc_type = path.into_inner();
+    if body.retention_years < 1 {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "fail",
+            "message": "retention_years must be at least 1"
+        }));
+    }
+
+    let document_type = match doc_types::resolve(&data.db, &doc_type).await {
+        Ok(Some(document_type)) => document_type,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Document type {} not found", doc_type)
+            }));
+        }
+        Err(error) => return database_error("get document type", error),
+    };
+
+    match sqlx::query_as!(
+        RetentionRuleModel,
+        r#"INSERT INTO retention_rules (doc_type, retention_years) VALUES ($1, $2)
+           ON CONFLICT (doc_type) DO UPDATE SET retention_years = EXCLUDED.retention_years, updated_at = now()
+           RETURNING *"#,
+        document_type.key,
+        body.retention_years
+    )
+    .fetch_one(&data.db)
+    .await
+    {
+        Ok(rule) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "retention_rule": rule
+        })),
+        Err(error) => database_error("save retention rule", error),
+    }
+}
+
+#[delete("/admin/retention-rules/{doc_type}")]
+pub async fn delete_retention_rule(path: Path<String>, data: Data<AppState>) -> impl Responder {
+    let doc_type = doc_types::normalize(&path.into_inner());
+
+    match sqlx::query!("DELETE FROM retention_rules WHERE doc_type = $1", doc_type)
+        .execute(&data.db)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("No retention rule for document type {}", doc_type)
+        })),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(error) => database_error("delete retention rule", error),
+    }
+}
+
+// Roda a limpeza agora, sem esperar o próximo intervalo
+#[post("/admin/retention-purge")]
+pub async fn run_retention_purge(data: Data<AppState>) -> impl Responder {
+    match purge(&data.db, data.storage.as_ref()).await {
+        Ok(report) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "destroyed": report.destroyed,
+            "held": report.held,
+            "failed": report.failed
+        })),
+        Err(error) => database_error("purge documents past retention", error),
+    }
+}
+
+#[get("/admin/destructions")]
+pub async fn get_destructions(opts: Query<FilterOptions>, data: Data<AppState>) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = (opts.page.unwrap_or(1) - 1) * limit;
+
+    match sqlx::query_as!(
+        DocumentDestructionModel,
+        "SELECT * FROM document_destructions ORDER BY destroyed_at DESC LIMIT $1 OFFSET $2",
+        limit as i32,
+        offset as i32
+    )
+    .fetch_all(&data.db)
+    .await
+    {
+        Ok(destructions) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "results": destructions.len(),
+            "destructions": destructions
+        })),
+        Err(error) => database_error("list destructions", error),
+    }
+}
+
+async fn set_document_hold(data: &AppState, document_id: Uuid, legal_hold: bool) -> HttpResponse {
+    match sqlx::query_as!(
+        DocumentModel,
+        "UPDATE documents SET legal_hold = $2 WHERE id = $1 RETURNING *",
+        document_id,
+        legal_hold
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(document)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "document": document
+        })),
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("Document {} not found", document_id)
+        })),
+        Err(error) => database_error("update legal hold", error),
+    }
+}
+
+#[put("/documents/{id}/legal-hold")]
+pub async fn place_document_hold(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    set_document_hold(&data, path.into_inner(), true).await
+}
+
+#[delete("/documents/{id}/legal-hold")]
+pub async fn release_document_hold(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    set_document_hold(&data, path.into_inner(), false).await
+}
+
+#[get("/users/{user_id}/legal-hold")]
+pub async fn get_user_hold(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let user_id = path.into_inner();
+
+    match sqlx::query_as!(UserLegalHoldModel, "SELECT * FROM user_legal_holds WHERE user_id = $1", user_id)
+        .fetch_optional(&data.db)
+        .await
+    {
+        Ok(Some(hold)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "legal_hold": hold
+        })),
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("User {} is not under legal hold", user_id)
+        })),
+        Err(error) => database_error("get legal hold", error),
+    }
+}
+
+// Vale para todos os documentos do usuário, inclusive os enviados depois
+#[put("/users/{user_id}/legal-hold")]
+pub async fn place_user_hold(
+    path: Path<Uuid>,
+    body: Option<Json<LegalHoldSchema>>,
+    data: Data<AppState>
+) -> impl Responder {
+    let user_id = path.into_inner();
+    let reason = body.and_then(|body| body.into_inner().reason);
+
+    match sqlx::query_as!(
+        UserLegalHoldModel,
+        r#"INSERT INTO user_legal_holds (user_id, reason) VALUES ($1, $2)
+           ON CONFLICT (user_id) DO UPDATE SET reason = COALESCE(EXCLUDED.reason, user_legal_holds.reason)
+           RETURNING *"#,
+        user_id,
+        reason
+    )
+    .fetch_one(&data.db)
+    .await
+    {
+        Ok(hold) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "legal_hold": hold
+        })),
+        Err(error) => database_error("place legal hold", error),
+    }
+}
+
+#[delete("/users/{user_id}/legal-hold")]
+pub async fn release_user_hold(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let user_id = path.into_inner();
+
+    match sqlx::query!("DELETE FROM user_legal_holds WHERE user_id = $1", user_id)
+        .execute(&data.db)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("User {} is not under legal hold", user_id)
+        })),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(error) => database_error("release legal hold", error),
+    }
+}
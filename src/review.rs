@@ -0,0 +1,282 @@
+// Fluxo de revisão dos documentos. As transições válidas são:
+//
+//     submitted --start--> in_review --approve--> approved
+//                              |
+//                              +------reject----> rejected --resubmit--> submitted
+//                                                  expired --resubmit--> submitted
+//
+// Um conteúdo novo (revisão ou restauração) também volta o documento para
+// submitted, já que a decisão anterior valia para outro arquivo. Documentos
+// vencidos vão para expired pela varredura de validade (ver expiry.rs) e só
+// voltam depois de ganhar uma validade futura. A aprovação assina o conteúdo
+// aprovado (ver signing.rs).
+use actix_web::{
+    http::StatusCode,
+    post,
+    web::{Data, Json, Path},
+    HttpResponse, Responder,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{model::DocumentModel, scanner, schema::ReviewSchema, signing, AppState};
+
+pub const SUBMITTED: &str = "submitted";
+pub const IN_REVIEW: &str = "in_review";
+pub const APPROVED: &str = "approved";
+pub const REJECTED: &str = "rejected";
+pub const EXPIRED: &str = "expired";
+
+pub const STATUSES: [&str; 5] = [SUBMITTED, IN_REVIEW, APPROVED, REJECTED, EXPIRED];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewAction {
+    Start,
+    Approve,
+    Reject,
+    Resubmit,
+}
+
+impl ReviewAction {
+    pub fn parse(action: &str) -> Option<Self> {
+        match action {
+            "start" => Some(ReviewAction::Start),
+            "approve" => Some(ReviewAction::Approve),
+            "reject" => Some(ReviewAction::Reject),
+            "resubmit" => Some(ReviewAction::Resubmit),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ReviewAction::Start => "start",
+            ReviewAction::Approve => "approve",
+            ReviewAction::Reject => "reject",
+            ReviewAction::Resubmit => "resubmit",
+        }
+    }
+
+    /// Status de onde a ação pode partir e para onde leva.
+    pub fn transition(self) -> (&'static [&'static str], &'static str) {
+        match self {
+            ReviewAction::Start => (&[SUBMITTED], IN_REVIEW),
+            ReviewAction::Approve => (&[IN_REVIEW], APPROVED),
+            ReviewAction::Reject => (&[IN_REVIEW], REJECTED),
+            ReviewAction::Resubmit => (&[REJECTED, EXPIRED], SUBMITTED),
+        }
+    }
+
+    /// Status depois da ação, ou `None` se ela não parte de `status`.
+    pub fn apply(self, status: &str) -> Option<&'static str> {
+        let (from, to) = self.transition();
+        from.contains(&status).then_some(to)
+    }
+}
+
+fn fail(status: StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "status": "fail",
+        "message": message
+    }))
+}
+
+// Muda o status da revisão; ações fora de ordem respondem 409
+#[post("/documents/{id}/review/{action}")]
+pub async fn review_document(
+    path: Path<(Uuid, String)>,
+    body: Option<Json<ReviewSchema>>,
+    data: Data<AppState>
+) -> impl Responder {
+    let (document_id, action_name) = path.into_inner();
+    let action = match ReviewAction::parse(&action_name) {
+        Some(action) => action,
+        None => {
+            return fail(
+                StatusCode::NOT_FOUND,
+                format!("Unknown review action {}; expected start, approve, reject or resubmit", action_name),
+            );
+        }
+    };
+
+    let body = body.map(Json::into_inner).unwrap_or(ReviewSchema { reviewer_id: None, reason: None });
+    let reviewer_id = match (action, body.reviewer_id) {
+        (ReviewAction::Resubmit, _) => None,
+        (_, Some(reviewer_id)) => Some(reviewer_id),
+        (_, None) => {
+            return fail(StatusCode::BAD_REQUEST, format!("reviewer_id is required to {} a review", action.name()));
+        }
+    };
+    let rejection_reason = match action {
+        ReviewAction::Reject => match body.reason.as_deref().map(str::trim) {
+            Some(reason) if !reason.is_empty() => Some(reason.to_string()),
+            _ => return fail(StatusCode::BAD_REQUEST, "reason is required to reject a document".to_string()),
+        },
+        _ => None,
+    };
+    let decided = matches!(action, ReviewAction::Approve | ReviewAction::Reject);
+    let (from, to) = action.transition();
+
+    let mut tx = match data.db.begin().await {
+        Ok(tx) => tx,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to update review: {:?}", error)
+            }));
+        }
+    };
+
+    // A condição no status atual torna a transição atômica; vencido só volta com validade futura
+    let updated = sqlx::query_as!(
+        DocumentModel,
+        r#"UPDATE documents
+           SET review_status = $3, reviewer_id = $4, rejection_reason = $5,
+               decided_at = CASE WHEN $6 THEN now() ELSE NULL END
+           WHERE id = $1 AND review_status = ANY($2) AND deleted_at IS NULL
+             AND ($3 <> 'approved' OR scan_status = 'clean')
+             AND (review_status <> 'expired' OR expires_at IS NULL OR expires_at >= CURRENT_DATE)
+           RETURNING *"#,
+        document_id,
+        &from.iter().map(|status| status.to_string()).collect::<Vec<_>>(),
+        to,
+        reviewer_id,
+        rejection_reason,
+        decided
+    )
+    .fetch_optional(&mut *tx)
+    .await;
+
+    let document = match updated {
+        Ok(Some(document)) => document,
+        Ok(None) => return rejected_transition(&data, document_id, action).await,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to update review: {:?}", error)
+            }));
+        }
+    };
+
+    // A aprovação só vale com a assinatura gravada junto
+    let mut response = json!({
+        "status": "success",
+        "document": document
+    });
+    if action == ReviewAction::Approve {
+        match signing::sign_document(&mut *tx, &data.signing_keys, &document).await {
+            Ok(signature) => response["signature"] = json!(signature),
+            Err(error) => {
+                return HttpResponse::InternalServerError().json(json!({
+                    "status": "error",
+                    "message": format!("Failed to sign document: {}", error)
+                }));
+            }
+        }
+    }
+
+    match tx.commit().await {
+        Ok(()) => HttpResponse::Ok().json(response),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to update review: {:?}", error)
+        })),
+    }
+}
+
+// Explica por que a transição não aconteceu
+async fn rejected_transition(data: &AppState, document_id: Uuid, action: ReviewAction) -> HttpResponse {
+    let current = sqlx::query!(
+        "SELECT review_status, scan_status, expires_at FROM documents WHERE id = $1 AND deleted_at IS NULL",
+        document_id
+    )
+    .fetch_optional(&data.db)
+    .await;
+
+    match current {
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("Document {} not found", document_id)
+        })),
+        Ok(Some(current))
+            if action == ReviewAction::Approve
+                && current.review_status == IN_REVIEW
+                && current.scan_status != scanner::CLEAN =>
+        {
+            fail(
+                StatusCode::CONFLICT,
+                format!(
+                    "Document cannot be approved before it passes the malware scan (scan_status: {})",
+                    current.scan_status
+                ),
+            )
+        }
+        Ok(Some(current)) if current.review_status == EXPIRED && action.apply(EXPIRED).is_some() => fail(
+            StatusCode::CONFLICT,
+            format!(
+                "Document expired on {}; set a future expires_at before resubmitting it",
+                current.expires_at.map(|date| date.to_string()).unwrap_or_default()
+            ),
+        ),
+        Ok(Some(current)) => fail(
+            StatusCode::CONFLICT,
+            format!(
+                "Cannot {} a document in review status {}; expected {}",
+                action.name(),
+                current.review_status,
+                action.transition().0.join(" or ")
+            ),
+        ),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to get document: {:?}", error)
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACTIONS: [ReviewAction; 4] =
+        [ReviewAction::Start, ReviewAction::Approve, ReviewAction::Reject, ReviewAction::Resubmit];
+
+    #[test]
+    fn action_names_round_trip() {
+        for action in ACTIONS {
+            assert_eq!(ReviewAction::parse(action.name()), Some(action));
+        }
+        assert_eq!(ReviewAction::parse("Approve"), None);
+        assert_eq!(ReviewAction::parse("expire"), None);
+    }
+
+    #[test]
+    fn follows_the_documented_transitions() {
+        let allowed = [
+            (SUBMITTED, ReviewAction::Start, IN_REVIEW),
+            (IN_REVIEW, ReviewAction::Approve, APPROVED),
+            (IN_REVIEW, ReviewAction::Reject, REJECTED),
+            (REJECTED, ReviewAction::Resubmit, SUBMITTED),
+            (EXPIRED, ReviewAction::Resubmit, SUBMITTED),
+        ];
+
+        for status in STATUSES {
+            for action in ACTIONS {
+                let expected = allowed
+                    .iter()
+                    .find(|(from, allowed_action, _)| *from == status && *allowed_action == action)
+                    .map(|(_, _, to)| *to);
+                assert_eq!(action.apply(status), expected, "{} from {}", action.name(), status);
+            }
+        }
+    }
+
+    #[test]
+    fn decisions_are_final_until_new_content() {
+        for action in ACTIONS {
+            assert_eq!(action.apply(APPROVED), None);
+        }
+        assert_eq!(ReviewAction::Start.apply(REJECTED), None);
+        assert_eq!(ReviewAction::Approve.apply(SUBMITTED), None);
+    }
+}
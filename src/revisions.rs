@@ -0,0 +1,207 @@
+use actix_multipart::Multipart;
+use actix_web::{
+    get, post,
+    web::{Data, Path, Query},
+    HttpRequest, HttpResponse, Responder,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    blobs, download,
+    model::{DocumentModel, DocumentRevisionModel},
+    schema::ContentQuery,
+    upload::{self, UploadError},
+    watermark, AppState,
+};
+
+async fn find_document(data: &AppState, document_id: Uuid) -> Result<DocumentModel, HttpResponse> {
+    match sqlx::query_as!(DocumentModel, "SELECT * FROM documents WHERE id = $1 AND deleted_at IS NULL", document_id)
+        .fetch_optional(&data.db)
+        .await
+    {
+        Ok(Some(document)) => Ok(document),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("Document {} not found", document_id)
+        }))),
+        Err(error) => Err(HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to get document: {:?}", error)
+        }))),
+    }
+}
+
+// Envia um novo conteúdo (multipart/form-data, campo file) como próxima revisão
+#[post("/documents/{id}/revisions")]
+pub async fn create_document_revision(
+    path: Path<Uuid>,
+    payload: Multipart,
+    data: Data<AppState>
+) -> impl Responder {
+    let document = match find_document(&data, path.into_inner()).await {
+        Ok(document) => document,
+        Err(response) => return response,
+    };
+
+    let mut file = match upload::read_form(payload, &data.staging_dir).await {
+        Ok(form) => match form.file {
+            Some(file) => file,
+            None => return UploadError::Invalid("Missing field: file".to_string()).error_response(),
+        },
+        Err(error) => return error.error_response(),
+    };
+
+    match upload::store_revision(&data, &document, &mut file).await {
+        Ok(document) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "document": document
+        })),
+        Err(error) => error.error_response(),
+    }
+}
+
+#[get("/documents/{id}/revisions")]
+pub async fn get_document_revisions(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let document = match find_document(&data, path.into_inner()).await {
+        Ok(document) => document,
+        Err(response) => return response,
+    };
+
+    match sqlx::query_as!(
+        DocumentRevisionModel,
+        "SELECT * FROM document_revisions WHERE document_id = $1 ORDER BY revision DESC",
+        document.id
+    )
+    .fetch_all(&data.db)
+    .await
+    {
+        Ok(revisions) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "current_revision": document.revision,
+            "revisions": revisions
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to get revisions: {:?}", error)
+        })),
+    }
+}
+
+async fn find_revision(
+    data: &AppState,
+    document_id: Uuid,
+    revision: i32,
+) -> Result<DocumentRevisionModel, HttpResponse> {
+    match sqlx::query_as!(
+        DocumentRevisionModel,
+        r#"SELECT r.* FROM document_revisions r
+           JOIN documents d ON d.id = r.document_id
+           WHERE r.document_id = $1 AND r.revision = $2 AND d.deleted_at IS NULL"#,
+        document_id,
+        revision
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(revision)) => Ok(revision),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("Revision {} of document {} not found", revision, document_id)
+        }))),
+        Err(error) => Err(HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to get revision: {:?}", error)
+        }))),
+    }
+}
+
+#[get("/documents/{id}/revisions/{revision}/content")]
+pub async fn get_document_revision_content(
+    path: Path<(Uuid, i32)>,
+    opts: Query<ContentQuery>,
+    req: HttpRequest,
+    data: Data<AppState>
+) -> impl Responder {
+    let (document_id, revision) = path.into_inner();
+
+    match find_revision(&data, document_id, revision).await {
+        Ok(revision) if opts.watermark == Some(true) => {
+            watermark::serve(&data, (&revision).into(), &opts, &document_id.to_string()).await
+        }
+        Ok(revision) => download::serve(&req, &data, (&revision).into()).await,
+        Err(response) => response,
+    }
+}
+
+// Torna uma revisão antiga a atual, registrando-a como uma nova revisão
+#[post("/documents/{id}/revisions/{revision}/restore")]
+pub async fn restore_document_revision(
+    path: Path<(Uuid, i32)>,
+    data: Data<AppState>
+) -> impl Responder {
+    let (document_id, revision) = path.into_inner();
+
+    let old = match find_revision(&data, document_id, revision).await {
+        Ok(old) => old,
+        Err(response) => return response,
+    };
+
+    let restored = async {
+        let mut tx = data.db.begin().await?;
+
+        let current = sqlx::query_scalar!("SELECT revision FROM documents WHERE id = $1 FOR UPDATE", document_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if current == old.revision {
+            let document = sqlx::query_as!(DocumentModel, "SELECT * FROM documents WHERE id = $1", document_id)
+                .fetch_one(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            return Ok(document);
+        }
+
+        blobs::acquire(&mut tx, &old.filename, &old.sha256, old.size_bytes).await?;
+
+        let document = sqlx::query_as!(
+            DocumentModel,
+            r#"UPDATE documents
+               SET filename = $1, original_filename = $2, size_bytes = $3, mime_type = $4,
+                   sha256 = $5, image_metadata = $6, revision = revision + 1,
+                   scan_status = $7, scan_detail = NULL, scanned_at = NULL,
+                   review_status = 'submitted', reviewer_id = NULL, decided_at = NULL, rejection_reason = NULL,
+                   master_key_id = $8, wrapped_key = $9, perceptual_hash = $10
+               WHERE id = $11
+               RETURNING *"#,
+            old.filename,
+            old.original_filename,
+            old.size_bytes,
+            old.mime_type,
+            old.sha256,
+            old.image_metadata,
+            old.scan_status,
+            old.master_key_id,
+            old.wrapped_key,
+            old.perceptual_hash,
+            document_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        upload::insert_revision(&mut tx, &document).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(document)
+    }
+    .await;
+
+    match restored {
+        Ok(document) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "document": document
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to restore revision: {:?}", error)
+        })),
+    }
+}
@@ -0,0 +1,214 @@
+// Antivírus: o conteúdo de cada documento passa pelo clamd (protocolo
+// INSTREAM) em segundo plano, e só conteúdo `clean` pode ser baixado.
+// Imagens, que são decodificadas já no envio, passam antes, ainda como chegaram.
+use std::{io, path::Path, sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+use sqlx::{Pool, Postgres};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    encryption::{self, Keyring},
+    storage::{ByteStream, Storage},
+    AppState,
+};
+
+pub const PENDING: &str = "pending";
+pub const CLEAN: &str = "clean";
+pub const INFECTED: &str = "infected";
+pub const ERROR: &str = "error";
+
+// O clamd recusa pedaços maiores que o seu StreamMaxLength; 64 KiB é seguro
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Clean,
+    Infected(String),
+    Error(String),
+}
+
+impl Verdict {
+    pub fn scan_status(&self) -> &'static str {
+        match self {
+            Verdict::Clean => CLEAN,
+            Verdict::Infected(_) => INFECTED,
+            Verdict::Error(_) => ERROR,
+        }
+    }
+}
+
+/// Cliente do clamd, configurado por CLAMD_ADDR (padrão 127.0.0.1:3310) e
+/// CLAMD_TIMEOUT_SECS.
+#[derive(Debug)]
+pub struct Scanner {
+    address: String,
+    timeout: Duration,
+}
+
+impl Scanner {
+    pub fn from_env() -> io::Result<Self> {
+        let address = std::env::var("CLAMD_ADDR").unwrap_or_else(|_| "127.0.0.1:3310".to_string());
+        let timeout_secs = match std::env::var("CLAMD_TIMEOUT_SECS") {
+            Ok(value) => value.parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid CLAMD_TIMEOUT_SECS: {}", value))
+            })?,
+            Err(_) => 60,
+        };
+
+        Ok(Scanner {
+            address,
+            timeout: Duration::from_secs(timeout_secs),
+        })
+    }
+
+    /// Envia um conteúdo ao clamd e interpreta a resposta.
+    pub async fn scan(&self, body: ByteStream) -> io::Result<Verdict> {
+        timeout(self.timeout, self.instream(body))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "clamd did not answer in time"))?
+    }
+
+    /// Verifica um arquivo local, como o envio ainda na área de staging.
+    pub async fn scan_file(&self, path: &Path) -> io::Result<Verdict> {
+        let file = tokio::fs::File::open(path).await?;
+        self.scan(Box::pin(ReaderStream::new(file))).await
+    }
+
+    async fn instream(&self, mut body: ByteStream) -> io::Result<Verdict> {
+        let mut connection = TcpStream::connect(&self.address).await?;
+
+        let sent = async {
+            connection.write_all(b"zINSTREAM\0").await?;
+            while let Some(chunk) = body.next().await {
+                for piece in chunk?.chunks(CHUNK_SIZE) {
+                    connection.write_all(&(piece.len() as u32).to_be_bytes()).await?;
+                    connection.write_all(piece).await?;
+                }
+            }
+            connection.write_all(&0u32.to_be_bytes()).await
+        }
+        .await;
+
+        // Ao passar do limite de tamanho o clamd responde e fecha a conexão
+        // no meio do envio; a resposta ainda diz o que houve
+        let mut reply = Vec::new();
+        let received = connection.read_to_end(&mut reply).await;
+        if reply.is_empty() {
+            sent?;
+            received?;
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "clamd closed the connection without a reply"));
+        }
+
+        Ok(parse_reply(&reply))
+    }
+}
+
+/// Interpreta respostas como `stream: OK` e `stream: Eicar-Signature FOUND`.
+pub fn parse_reply(reply: &[u8]) -> Verdict {
+    let reply = String::from_utf8_lossy(reply);
+    let reply = reply.trim_end_matches(['\0', '\n']);
+    let result = reply.strip_prefix("stream: ").unwrap_or(reply);
+
+    if result == "OK" {
+        Verdict::Clean
+    } else if let Some(signature) = result.strip_suffix(" FOUND") {
+        Verdict::Infected(signature.to_string())
+    } else {
+        Verdict::Error(result.to_string())
+    }
+}
+
+/// Agenda a verificação do conteúdo `key`.
+pub fn spawn_scan(data: &AppState, key: &str) {
+    let db = data.db.clone();
+    let storage = data.storage.clone();
+    let keyring = data.keyring.clone();
+    let scanner = data.scanner.clone();
+    let key = key.to_string();
+
+    actix_web::rt::spawn(async move {
+        scan_and_record(&db, storage.as_ref(), &keyring, &scanner, &key).await;
+    });
+}
+
+/// Retoma, um por vez, os conteúdos que ficaram `pending` (por exemplo,
+/// porque o servidor parou no meio da verificação).
+pub fn spawn_pending_scans(db: Pool<Postgres>, storage: Arc<dyn Storage>, keyring: Arc<Keyring>, scanner: Arc<Scanner>) {
+    actix_web::rt::spawn(async move {
+        let keys = sqlx::query_scalar!(
+            r#"SELECT filename AS "filename!" FROM documents WHERE scan_status = 'pending'
+               UNION
+               SELECT filename FROM document_revisions WHERE scan_status = 'pending'"#
+        )
+        .fetch_all(&db)
+        .await;
+
+        match keys {
+            Ok(keys) => {
+                for key in keys {
+                    scan_and_record(&db, storage.as_ref(), &keyring, &scanner, &key).await;
+                }
+            }
+            Err(error) => log::warn!("Failed to list pending scans: {:?}", error),
+        }
+    });
+}
+
+// O resultado vale para o conteúdo, então vai para todos os documentos e
+// revisões que apontam para ele. O clamd recebe o conteúdo já decifrado
+async fn scan_and_record(db: &Pool<Postgres>, storage: &dyn Storage, keyring: &Keyring, scanner: &Scanner, key: &str) {
+    let body = match encryption::content_key(db, keyring, key).await {
+        Ok(data_key) => encryption::get(storage, key, data_key.as_ref(), None).await,
+        Err(error) => Err(error),
+    };
+    let verdict = match body {
+        Ok(body) => scanner
+            .scan(body)
+            .await
+            .unwrap_or_else(|error| Verdict::Error(format!("Failed to reach clamd: {}", error))),
+        Err(error) => Verdict::Error(format!("Failed to read stored content: {}", error)),
+    };
+
+    let detail = match &verdict {
+        Verdict::Clean => None,
+        Verdict::Infected(signature) => {
+            log::warn!("Stored content {} is infected: {}", key, signature);
+            Some(signature.clone())
+        }
+        Verdict::Error(message) => {
+            log::warn!("Failed to scan stored content {}: {}", key, message);
+            Some(message.clone())
+        }
+    };
+
+    let recorded = async {
+        let mut tx = db.begin().await?;
+        sqlx::query!(
+            "UPDATE documents SET scan_status = $2, scan_detail = $3, scanned_at = now() WHERE filename = $1",
+            key,
+            verdict.scan_status(),
+            detail
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE document_revisions SET scan_status = $2 WHERE filename = $1",
+            key,
+            verdict.scan_status()
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+    .await;
+
+    if let Err(error) = recorded {
+        log::warn!("Failed to record scan result for {}: {:?}", key, error);
+    }
+}
@@ -1,4 +1,6 @@
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid; // Adicionado para o uso do tipo Uuid
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -11,6 +13,14 @@ pub struct CreateTaskSchema {
 pub struct CreateDocumentSchema {
     pub user_id: Uuid,
     pub doc_type: String,
+    #[serde(default = "empty_metadata")]
+    pub metadata: Value,
+    #[serde(default)]
+    pub expires_at: Option<NaiveDate>,
+}
+
+pub fn empty_metadata() -> Value {
+    Value::Object(Default::default())
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -19,6 +29,19 @@ pub struct FilterOptions {
     pub limit: Option<usize>
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocumentFilterOptions {
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+    pub status: Option<String>,
+    pub expiring_within: Option<u32>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThumbnailOptions {
+    pub size: Option<String>
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UpdateTaskSchema {
     pub title: Option<String>,
@@ -29,4 +52,89 @@ pub struct UpdateTaskSchema {
 pub struct UpdateDocumentSchema {
     pub user_id: Option<Uuid>, // Tipo deve corresponder ao esquema do banco de dados
     pub doc_type: Option<String>,
-}
\ No newline at end of file
+    pub metadata: Option<Value>,
+    pub expires_at: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewSchema {
+    pub reviewer_id: Option<Uuid>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDocumentTypeSchema {
+    pub key: String,
+    pub display_name: String,
+    pub metadata_schema: Option<Value>,
+    pub aliases: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDocumentTypeSchema {
+    pub display_name: Option<String>,
+    pub metadata_schema: Option<Value>,
+    pub aliases: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MrzSchema {
+    pub mrz: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidateIdentitySchema {
+    pub value: String,
+    pub state: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateShareLinkSchema {
+    pub expires_in_secs: Option<u64>,
+    pub max_downloads: Option<i32>,
+    pub allowed_ip: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShareLinkQuery {
+    pub expires: i64,
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RetentionRuleSchema {
+    pub retention_years: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LegalHoldSchema {
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuotaSchema {
+    pub max_bytes: Option<i64>,
+    pub max_documents: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NearDuplicateQuery {
+    pub max_distance: Option<i32>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeDocumentsSchema {
+    pub document_ids: Vec<Uuid>,
+    pub doc_type: Option<String>,
+    pub metadata: Option<Value>,
+    pub filename: Option<String>,
+}
+
+// watermark=true entrega uma cópia marcada com quem baixou, quando e para quê
+#[derive(Debug, Deserialize)]
+pub struct ContentQuery {
+    pub watermark: Option<bool>,
+    pub downloaded_by: Option<String>,
+    pub purpose: Option<String>,
+}
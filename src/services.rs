@@ -11,18 +11,41 @@ use actix_web::{
         Path,
         ServiceConfig
     },
+    HttpRequest,
     HttpResponse,
     Responder
 };
 
 use serde_json::json;
 
+use actix_multipart::Multipart;
+
 use crate::{
+    archive,
+    br_ids,
+    doc_types,
+    download,
+    encryption,
+    expiry,
     model::{TaskModel, DocumentModel},
-    schema::{CreateTaskSchema, CreateDocumentSchema, FilterOptions, UpdateTaskSchema, UpdateDocumentSchema},
+    merge,
+    mrz,
+    phash,
+    quotas,
+    schema::{ContentQuery, CreateTaskSchema, DocumentFilterOptions, FilterOptions, UpdateTaskSchema, UpdateDocumentSchema, ThumbnailOptions},
+    retention,
+    review,
+    revisions,
+    scanner,
+    share_links,
+    signing,
+    trash,
+    thumbnails,
+    tus,
+    upload::{self, UploadError},
+    watermark,
     AppState
 };
-use sqlx::PgPool;
 use uuid::Uuid;
 
 // Endpoint de verificação de saúde
@@ -45,7 +68,7 @@ async fn create_task(
     let query = r#"
         INSERT INTO tasks (title, content)
         VALUES ($1, $2)
-        RETURNING id, title, content, created_at
+        RETURNING id, title, content, created_at, deleted_at
     "#;
 
     match sqlx::query_as::<_, TaskModel>(query)
@@ -76,48 +99,26 @@ async fn create_task(
     }
 }
 
-// Endpoint para criar um documento
+// Endpoint para criar um documento (multipart/form-data: user_id, doc_type e file)
 #[post("/documents")]
 async fn create_document(
-    body: Json<CreateDocumentSchema>,
+    payload: Multipart,
     data: Data<AppState>
 ) -> impl Responder {
-    let query = r#"
-        INSERT INTO documents (user_id, doc_type, filename)
-        VALUES ($1, $2, $3)
-        RETURNING id, user_id, doc_type, filename, created_at
-    "#;
+    let (body, mut file) = match upload::read_document_upload(payload, &data.staging_dir).await {
+        Ok(upload) => upload,
+        Err(error) => return error.error_response(),
+    };
 
-    // O filename é gerado automaticamente; ajuste conforme necessário
-    let filename = format!("document_{}.jpg", Uuid::new_v4());
-
-    match sqlx::query_as::<_, DocumentModel>(query)
-        .bind(&body.user_id)
-        .bind(&body.doc_type)
-        .bind(&filename)
-        .fetch_one(&data.db)
-        .await
-    {
+    match upload::store_document(&data, &body, &mut file).await {
         Ok(document) => {
             let response = json!({
                 "status": "success",
-                "document": {
-                    "id": document.id,
-                    "user_id": document.user_id,
-                    "doc_type": document.doc_type,
-                    "filename": document.filename,
-                    "created_at": document.created_at
-                }
+                "document": document
             });
             HttpResponse::Ok().json(response)
         }
-        Err(error) => {
-            let response = json!({
-                "status": "error",
-                "message": format!("Failed to create document: {:?}", error)
-            });
-            HttpResponse::InternalServerError().json(response)
-        }
+        Err(error) => error.error_response(),
     }
 }
 
@@ -130,7 +131,7 @@ pub async fn get_all_tasks(opts: Query<FilterOptions>, data: Data<AppState>) ->
         sqlx
             ::query_as!(
                 TaskModel,
-                "SELECT * FROM tasks ORDER by id LIMIT $1 OFFSET $2",
+                "SELECT * FROM tasks WHERE deleted_at IS NULL ORDER by id LIMIT $1 OFFSET $2",
                 limit as i32,
                 offset as i32
             )
@@ -143,12 +144,12 @@ pub async fn get_all_tasks(opts: Query<FilterOptions>, data: Data<AppState>) ->
                     });
 
 
-                  return HttpResponse::Ok().json(task_note);
+                  HttpResponse::Ok().json(task_note)
                 }
 
                 Err(error) => {
 
-                    return HttpResponse::InternalServerError().json(
+                    HttpResponse::InternalServerError().json(
                         json!({
                             "status": "error",
                             "message": format!("{:?}", error)
@@ -164,23 +165,30 @@ async fn get_task_by_id(path: Path<uuid::Uuid>, data: Data<AppState>) -> impl Re
   let task_id = path.into_inner();
 
   let query_result = sqlx
-        ::query_as!(TaskModel, "SELECT * FROM tasks WHERE id = $1", task_id)
-        .fetch_one(&data.db).await;
+        ::query_as!(TaskModel, "SELECT * FROM tasks WHERE id = $1 AND deleted_at IS NULL", task_id)
+        .fetch_optional(&data.db).await;
 
     match query_result {
-        Ok(task) => {
+        Ok(Some(task)) => {
             let task_note = json!({
                 "status": "success",
                 "task": task
             });
 
 
-            return HttpResponse::Ok().json(task_note);
+            HttpResponse::Ok().json(task_note)
+        }
+
+        Ok(None) => {
+            HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Task {} not found", task_id)
+            }))
         }
 
         Err(error) => {
 
-            return HttpResponse::InternalServerError().json(
+            HttpResponse::InternalServerError().json(
                 json!({
                     "status": "error",
                     "message": format!("{:?}", error)
@@ -193,17 +201,39 @@ async fn get_task_by_id(path: Path<uuid::Uuid>, data: Data<AppState>) -> impl Re
 // get documents 
 #[get("/documents")]
 pub async fn get_all_documents(
-    opts: Query<FilterOptions>,
+    opts: Query<DocumentFilterOptions>,
     data: Data<AppState>
 ) -> impl Responder {
     let limit = opts.limit.unwrap_or(10);
     let offset = (opts.page.unwrap_or(1) - 1) * limit;
 
+    // Filtros opcionais pelo status da revisão e pela validade (vencendo em até N dias)
+    if opts.expiring_within.is_some_and(|days| days > expiry::MAX_EXPIRING_WITHIN_DAYS) {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "fail",
+            "message": format!("expiring_within must be at most {} days", expiry::MAX_EXPIRING_WITHIN_DAYS)
+        }));
+    }
+    if let Some(status) = &opts.status {
+        if !review::STATUSES.contains(&status.as_str()) {
+            return HttpResponse::BadRequest().json(json!({
+                "status": "fail",
+                "message": format!("Invalid status {}; expected one of: {}", status, review::STATUSES.join(", "))
+            }));
+        }
+    }
+
     match sqlx::query_as!(
         DocumentModel,
-        "SELECT * FROM documents ORDER BY id LIMIT $1 OFFSET $2",
+        r#"SELECT * FROM documents
+           WHERE deleted_at IS NULL
+             AND ($3::text IS NULL OR review_status = $3)
+             AND ($4::int IS NULL OR expires_at BETWEEN CURRENT_DATE AND CURRENT_DATE + $4)
+           ORDER BY id LIMIT $1 OFFSET $2"#,
         limit as i32,
-        offset as i32
+        offset as i32,
+        opts.status,
+        opts.expiring_within.map(|days| days as i32)
     )
     .fetch_all(&data.db)
     .await
@@ -225,6 +255,67 @@ pub async fn get_all_documents(
     }
 }
 
+// Documentos com conteúdo idêntico (mesmo SHA-256), agrupados
+#[get("/documents/duplicates")]
+pub async fn get_duplicate_documents(
+    opts: Query<FilterOptions>,
+    data: Data<AppState>
+) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = (opts.page.unwrap_or(1) - 1) * limit;
+
+    match sqlx::query_as!(
+        DocumentModel,
+        r#"SELECT * FROM documents
+           WHERE deleted_at IS NULL AND sha256 IN (
+               SELECT sha256 FROM documents
+               WHERE sha256 <> '' AND deleted_at IS NULL
+               GROUP BY sha256
+               HAVING count(*) > 1
+               ORDER BY sha256
+               LIMIT $1 OFFSET $2
+           )
+           ORDER BY sha256, created_at"#,
+        limit as i32,
+        offset as i32
+    )
+    .fetch_all(&data.db)
+    .await
+    {
+        Ok(documents) => {
+            let mut groups: Vec<(String, i64, Vec<DocumentModel>)> = Vec::new();
+            for document in documents {
+                match groups.last_mut() {
+                    Some((sha256, _, group)) if *sha256 == document.sha256 => group.push(document),
+                    _ => groups.push((document.sha256.clone(), document.size_bytes, vec![document])),
+                }
+            }
+
+            let duplicates: Vec<_> = groups
+                .into_iter()
+                .map(|(sha256, size_bytes, documents)| json!({
+                    "sha256": sha256,
+                    "size_bytes": size_bytes,
+                    "count": documents.len(),
+                    "documents": documents
+                }))
+                .collect();
+
+            HttpResponse::Ok().json(json!({
+                "status": "success",
+                "duplicates": duplicates
+            }))
+        }
+        Err(error) => {
+            let response = json!({
+                "status": "error",
+                "message": format!("Failed to get duplicate documents: {:?}", error)
+            });
+            HttpResponse::InternalServerError().json(response)
+        }
+    }
+}
+
 #[get("/documents/{id}")]
 pub async fn get_document_by_id(
     path: Path<Uuid>,
@@ -234,19 +325,23 @@ pub async fn get_document_by_id(
 
     match sqlx::query_as!(
         DocumentModel,
-        "SELECT * FROM documents WHERE id = $1",
+        "SELECT * FROM documents WHERE id = $1 AND deleted_at IS NULL",
         document_id
     )
-    .fetch_one(&data.db)
+    .fetch_optional(&data.db)
     .await
     {
-        Ok(document) => {
+        Ok(Some(document)) => {
             let response = json!({
                 "status": "success",
                 "document": document
             });
             HttpResponse::Ok().json(response)
         }
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("Document {} not found", document_id)
+        })),
         Err(error) => {
             let response = json!({
                 "status": "error",
@@ -257,17 +352,173 @@ pub async fn get_document_by_id(
     }
 }
 
+// Conteúdo de um documento, com suporte a Range e requisições condicionais
+#[get("/documents/{id}/content")]
+pub async fn get_document_content(
+    path: Path<Uuid>,
+    opts: Query<ContentQuery>,
+    req: HttpRequest,
+    data: Data<AppState>
+) -> impl Responder {
+    let document_id = path.into_inner();
+
+    let document = match sqlx::query_as!(
+        DocumentModel,
+        "SELECT * FROM documents WHERE id = $1 AND deleted_at IS NULL",
+        document_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(document)) => document,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Document {} not found", document_id)
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get document: {:?}", error)
+            }));
+        }
+    };
+
+    if opts.watermark == Some(true) {
+        return watermark::serve(&data, (&document).into(), &opts, &document_id.to_string()).await;
+    }
+    download::serve(&req, &data, (&document).into()).await
+}
+
+// Miniatura do conteúdo atual (size = small, medium ou large)
+#[get("/documents/{id}/thumbnail")]
+pub async fn get_document_thumbnail(
+    path: Path<Uuid>,
+    opts: Query<ThumbnailOptions>,
+    req: HttpRequest,
+    data: Data<AppState>
+) -> impl Responder {
+    let document_id = path.into_inner();
+    let size = opts.size.as_deref().unwrap_or(thumbnails::DEFAULT_SIZE);
+
+    if !thumbnails::is_size(size) {
+        let sizes: Vec<&str> = thumbnails::SIZES.iter().map(|(name, _)| *name).collect();
+        return HttpResponse::BadRequest().json(json!({
+            "status": "fail",
+            "message": format!("Invalid thumbnail size {}; expected one of: {}", size, sizes.join(", "))
+        }));
+    }
+
+    let document = match sqlx::query_as!(
+        DocumentModel,
+        "SELECT * FROM documents WHERE id = $1 AND deleted_at IS NULL",
+        document_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(document)) => document,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Document {} not found", document_id)
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get document: {:?}", error)
+            }));
+        }
+    };
+
+    let key = thumbnails::key(&document.filename, size);
+    // Cada tamanho precisa de uma ETag própria, diferente da do conteúdo
+    let etag = match document.sha256.as_str() {
+        "" => String::new(),
+        sha256 => format!("{}-{}", sha256, size),
+    };
+    let filename = format!("thumbnail-{}.jpg", size);
+    let content = download::StoredContent {
+        key: &key,
+        sha256: &etag,
+        mime_type: thumbnails::THUMBNAIL_MIME_TYPE,
+        original_filename: &filename,
+        scan_status: &document.scan_status,
+        master_key_id: document.master_key_id.as_deref(),
+        wrapped_key: document.wrapped_key.as_deref(),
+    };
+
+    download::serve(&req, &data, content).await
+}
+
+// Verifica de novo o conteúdo atual no antivírus (por exemplo, depois de um erro)
+#[post("/documents/{id}/scan")]
+pub async fn scan_document(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let document_id = path.into_inner();
+
+    // O resultado vale para todos que compartilham o conteúdo
+    let document = match sqlx::query_as!(
+        DocumentModel,
+        r#"WITH target AS (SELECT filename FROM documents WHERE id = $1 AND deleted_at IS NULL),
+                marked AS (
+                    UPDATE document_revisions SET scan_status = $2
+                    WHERE filename = (SELECT filename FROM target)
+                )
+           UPDATE documents SET scan_status = $2, scan_detail = NULL
+           WHERE filename = (SELECT filename FROM target)
+           RETURNING *"#,
+        document_id,
+        scanner::PENDING
+    )
+    .fetch_all(&data.db)
+    .await
+    {
+        Ok(documents) => match documents.into_iter().find(|document| document.id == document_id) {
+            Some(document) => document,
+            None => {
+                return HttpResponse::NotFound().json(json!({
+                    "status": "not found",
+                    "message": format!("Document {} not found", document_id)
+                }));
+            }
+        },
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to schedule scan: {:?}", error)
+            }));
+        }
+    };
+
+    scanner::spawn_scan(&data, &document.filename);
+
+    HttpResponse::Accepted().json(json!({
+        "status": "success",
+        "document": document
+    }))
+}
+
 #[delete("/tasks/{id}")]
 async fn delete_task_by_id(path: Path<uuid::Uuid>, data: Data<AppState>) -> impl Responder {
     let task_id = path.into_inner();
 
-    match sqlx::query!("DELETE FROM tasks WHERE id = $1", task_id).execute(&data.db).await {
+    // Vai para a lixeira; some de vez só em DELETE /trash/tasks/{id}
+    match sqlx::query!("UPDATE tasks SET deleted_at = now() WHERE id = $1 AND deleted_at IS NULL", task_id)
+        .execute(&data.db)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("Task {} not found", task_id)
+        })),
         Ok(_) => { HttpResponse::NoContent().finish() }
         Err(err) => {
             let message = format!("Internal server error: {:?}", err);
-            return HttpResponse::NotFound().json(
+            HttpResponse::NotFound().json(
                 serde_json::json!({"status": "fail","message": message})
-            );
+            )
         }
     }
 }
@@ -276,13 +527,25 @@ async fn delete_task_by_id(path: Path<uuid::Uuid>, data: Data<AppState>) -> impl
 async fn delete_documents_by_id(path: Path<uuid::Uuid>, data: Data<AppState>) -> impl Responder {
     let documents_id = path.into_inner();
 
-    match sqlx::query!("DELETE FROM documents WHERE id = $1", documents_id).execute(&data.db).await {
+    // Vai para a lixeira com o conteúdo; some de vez só em DELETE /trash/documents/{id}
+    let deleted = sqlx::query!(
+        "UPDATE documents SET deleted_at = now() WHERE id = $1 AND deleted_at IS NULL",
+        documents_id
+    )
+    .execute(&data.db)
+    .await;
+
+    match deleted {
+        Ok(result) if result.rows_affected() == 0 => HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("Document {} not found", documents_id)
+        })),
         Ok(_) => { HttpResponse::NoContent().finish() }
         Err(err) => {
             let message = format!("Internal server error: {:?}", err);
-            return HttpResponse::NotFound().json(
+            HttpResponse::NotFound().json(
                 serde_json::json!({"status": "fail","message": message})
-            );
+            )
         }
     }
 }
@@ -296,14 +559,14 @@ async fn update_task_by_id(
 ) -> impl Responder {
     let task_id = path.into_inner();
 
-    match sqlx::query_as!(TaskModel, "SELECT * FROM tasks WHERE id = $1", task_id)
+    match sqlx::query_as!(TaskModel, "SELECT * FROM tasks WHERE id = $1 AND deleted_at IS NULL", task_id)
         .fetch_one(&data.db)
         .await
     {
         Ok(task) => {
             let update_result = sqlx::query_as!(
                 TaskModel,
-                "UPDATE tasks SET title = $1, content = $2 WHERE id = $3 RETURNING *",
+                "UPDATE tasks SET title = $1, content = $2 WHERE id = $3 AND deleted_at IS NULL RETURNING *",
                 body.title.as_ref().unwrap_or(&task.title),
                 body.content.as_ref().unwrap_or(&task.content),
                 task_id
@@ -350,27 +613,77 @@ async fn update_document_by_id(
     // Recuperar o documento existente
     let document_result = sqlx::query_as!(
         DocumentModel,
-        "SELECT * FROM documents WHERE id = $1",
+        "SELECT * FROM documents WHERE id = $1 AND deleted_at IS NULL",
         document_id
     )
     .fetch_one(&data.db)
     .await;
 
     match document_result {
-        Ok(_document) => {
-            // Atualizar o documento
+        Ok(document) => {
+            // O metadata (novo ou o atual) precisa seguir o schema do doc_type final
+            let doc_type = body.doc_type.as_deref().unwrap_or(&document.doc_type);
+            let metadata = body.metadata.as_ref().unwrap_or(&document.metadata);
+            let (doc_type, metadata) = match doc_types::check(&data.db, doc_type, metadata).await {
+                Ok(checked) => checked,
+                Err(error) => return error.error_response(),
+            };
+
+            // Sem expires_at explícito, vale o expires_on do metadata novo
+            let from_metadata = match expiry::from_metadata(&metadata) {
+                Ok(expires_at) => expires_at.filter(|_| body.metadata.is_some()),
+                Err(error) => return UploadError::InvalidMetadata { doc_type, errors: vec![error] }.error_response(),
+            };
+            let expires_at = body.expires_at.or(from_metadata).or(document.expires_at);
+
+            let mut tx = match data.db.begin().await {
+                Ok(tx) => tx,
+                Err(error) => {
+                    return HttpResponse::InternalServerError().json(json!({
+                        "status": "error",
+                        "message": format!("Failed to update document: {:?}", error)
+                    }))
+                }
+            };
+
+            // Trocar de dono só se o documento couber na cota do novo dono
+            if let Some(user_id) = body.user_id.filter(|user_id| *user_id != document.user_id) {
+                match quotas::check_transfer(&mut tx, &data.quota_defaults, user_id, document_id).await {
+                    Ok(None) => {}
+                    Ok(Some(exceeded)) => return UploadError::QuotaExceeded(exceeded).error_response(),
+                    Err(error) => {
+                        return HttpResponse::InternalServerError().json(json!({
+                            "status": "error",
+                            "message": format!("Failed to check quota: {:?}", error)
+                        }))
+                    }
+                }
+            }
+
+            // Atualizar o documento; um documento vencido com nova validade volta pela revisão (resubmit)
             let update_result = sqlx::query_as!(
                 DocumentModel,
-                "UPDATE documents SET user_id = COALESCE($1, user_id), doc_type = COALESCE($2, doc_type) WHERE id = $3 RETURNING *",
+                r#"UPDATE documents
+                   SET user_id = COALESCE($1, user_id), doc_type = $2, metadata = $3, expires_at = $4
+                   WHERE id = $5
+                   RETURNING *"#,
                 body.user_id.as_ref(),
-                body.doc_type.as_ref(),
+                doc_type,
+                metadata,
+                expires_at,
                 document_id
             )
-            .fetch_one(&data.db)
+            .fetch_one(&mut *tx)
             .await;
 
             match update_result {
                 Ok(updated_document) => {
+                    if let Err(error) = tx.commit().await {
+                        return HttpResponse::InternalServerError().json(json!({
+                            "status": "error",
+                            "message": format!("Failed to update document: {:?}", error)
+                        }));
+                    }
                     let response = json!({
                         "status": "success",
                         "document": updated_document
@@ -405,15 +718,69 @@ pub fn config(conf: &mut ServiceConfig) {
     conf.service(
         scope("/api")
             .service(health_checker)
+            .service(br_ids::validate_identity)
             .service(create_task)
             .service(create_document) // Adiciona o serviço de documentos
             .service(get_all_tasks)
             .service(get_task_by_id)
             .service(get_all_documents)
+            .service(archive::get_user_documents_archive)
+            .service(expiry::run_expiry_sweep)
+            .service(encryption::rotate_master_key)
+            .service(encryption::encrypt_plaintext_content)
+            .service(retention::get_retention_rules)
+            .service(retention::put_retention_rule)
+            .service(retention::delete_retention_rule)
+            .service(retention::run_retention_purge)
+            .service(retention::get_destructions)
+            .service(retention::get_user_hold)
+            .service(retention::place_user_hold)
+            .service(retention::release_user_hold)
+            .service(quotas::get_user_usage)
+            .service(quotas::put_user_quota)
+            .service(quotas::delete_user_quota)
+            .service(get_duplicate_documents)
+            .service(merge::merge_documents)
+            .service(merge::get_document_sources)
+            .service(signing::verify_document)
+            .service(signing::get_document_signatures)
+            .service(signing::get_public_key)
+            .service(phash::get_near_duplicates)
             .service(get_document_by_id)
+            .service(get_document_content)
+            .service(get_document_thumbnail)
+            .service(scan_document)
+            .service(review::review_document)
+            .service(mrz::parse_document_mrz)
+            .service(retention::place_document_hold)
+            .service(retention::release_document_hold)
+            .service(share_links::create_share_link)
+            .service(share_links::get_share_links)
+            .service(share_links::get_share_link_accesses)
+            .service(share_links::download_shared_document)
             .service(delete_task_by_id)
             .service(delete_documents_by_id)
             .service(update_task_by_id)
             .service(update_document_by_id) // Adiciona o serviço de atualização
+            .service(trash::get_trashed_tasks)
+            .service(trash::restore_task)
+            .service(trash::purge_task)
+            .service(trash::get_trashed_documents)
+            .service(trash::restore_document)
+            .service(trash::purge_document)
+            .service(tus::upload_options)
+            .service(tus::create_upload)
+            .service(tus::get_upload_offset)
+            .service(tus::append_upload)
+            .service(tus::delete_upload)
+            .service(revisions::create_document_revision)
+            .service(revisions::get_document_revisions)
+            .service(revisions::get_document_revision_content)
+            .service(revisions::restore_document_revision)
+            .service(doc_types::get_document_types)
+            .service(doc_types::get_document_type)
+            .service(doc_types::create_document_type)
+            .service(doc_types::update_document_type)
+            .service(doc_types::delete_document_type)
     );
 }
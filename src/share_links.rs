@@ -0,0 +1,379 @@
+// Links de compartilhamento: URLs assinadas com HMAC-SHA256 que dão acesso ao
+// conteúdo de um documento sem conta. O link vale só para o conteúdo que o
+// documento tinha quando foi criado, até expirar, até esgotar o número de
+// downloads ou só para um IP/faixa; cada acesso fica registrado em
+// share_link_accesses.
+use std::{io, net::IpAddr};
+
+use actix_web::{
+    get, post,
+    http::{header, StatusCode},
+    web::{Data, Json, Path, Query},
+    HttpRequest, HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use ipnet::IpNet;
+use serde_json::json;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{
+    download,
+    model::{DocumentModel, ShareLinkAccessModel, ShareLinkModel},
+    scanner,
+    schema::{CreateShareLinkSchema, ShareLinkQuery},
+    AppState,
+};
+
+const DEFAULT_EXPIRES_IN_SECS: u64 = 24 * 60 * 60;
+const MAX_EXPIRES_IN_SECS: u64 = 30 * 24 * 60 * 60;
+
+// Segredos curtos tornariam a assinatura adivinhável
+const MIN_SECRET_BYTES: usize = 32;
+
+pub const GRANTED: &str = "granted";
+pub const EXPIRED: &str = "expired";
+pub const EXHAUSTED: &str = "exhausted";
+pub const IP_DENIED: &str = "ip_denied";
+pub const UNAVAILABLE: &str = "unavailable";
+
+/// Assina e confere os links com o segredo em SHARE_LINK_SECRET.
+pub struct ShareSigner {
+    secret: Vec<u8>,
+}
+
+impl ShareSigner {
+    pub fn from_env() -> io::Result<Self> {
+        let secret = std::env::var("SHARE_LINK_SECRET")
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "SHARE_LINK_SECRET must be set"))?;
+        if secret.len() < MIN_SECRET_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("SHARE_LINK_SECRET must have at least {} bytes", MIN_SECRET_BYTES),
+            ));
+        }
+        Ok(ShareSigner { secret: secret.into_bytes() })
+    }
+
+    fn mac(&self, link_id: Uuid, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(format!("{}.{}", link_id, expires).as_bytes());
+        mac
+    }
+
+    pub fn sign(&self, link_id: Uuid, expires: i64) -> String {
+        hex::encode(self.mac(link_id, expires).finalize().into_bytes())
+    }
+
+    /// Compara a assinatura em tempo constante.
+    pub fn verify(&self, link_id: Uuid, expires: i64, signature: &str) -> bool {
+        match hex::decode(signature) {
+            Ok(signature) => self.mac(link_id, expires).verify_slice(&signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+// Aceita um IP (203.0.113.7) ou uma faixa CIDR (203.0.113.0/24)
+fn parse_allowed_ip(value: &str) -> Option<String> {
+    let value = value.trim();
+    if let Ok(address) = value.parse::<IpAddr>() {
+        return Some(address.to_string());
+    }
+    value.parse::<IpNet>().ok().map(|network| network.trunc().to_string())
+}
+
+fn ip_allowed(allowed: &str, ip: Option<IpAddr>) -> bool {
+    let Some(ip) = ip else {
+        return false;
+    };
+    match allowed.parse::<IpAddr>() {
+        Ok(address) => address == ip,
+        Err(_) => allowed.parse::<IpNet>().map(|network| network.contains(&ip)).unwrap_or(false),
+    }
+}
+
+fn share_url(req: &HttpRequest, link: &ShareLinkModel, signature: &str) -> String {
+    let connection = req.connection_info();
+    format!(
+        "{}://{}/api/share/{}?expires={}&signature={}",
+        connection.scheme(),
+        connection.host(),
+        link.id,
+        link.expires_at.timestamp(),
+        signature
+    )
+}
+
+fn fail(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "status": "fail",
+        "message": message
+    }))
+}
+
+// Gera um link assinado para o conteúdo atual do documento
+#[post("/documents/{id}/share-links")]
+pub async fn create_share_link(
+    path: Path<Uuid>,
+    body: Option<Json<CreateShareLinkSchema>>,
+    req: HttpRequest,
+    data: Data<AppState>
+) -> impl Responder {
+    let document_id = path.into_inner();
+    let body = body.map(Json::into_inner).unwrap_or(CreateShareLinkSchema {
+        expires_in_secs: None,
+        max_downloads: None,
+        allowed_ip: None,
+    });
+
+    let expires_in_secs = body.expires_in_secs.unwrap_or(DEFAULT_EXPIRES_IN_SECS);
+    if expires_in_secs == 0 || expires_in_secs > MAX_EXPIRES_IN_SECS {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "fail",
+            "message": format!("expires_in_secs must be between 1 and {}", MAX_EXPIRES_IN_SECS)
+        }));
+    }
+    if body.max_downloads.is_some_and(|max| max < 1) {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "fail",
+            "message": "max_downloads must be at least 1"
+        }));
+    }
+    let allowed_ip = match body.allowed_ip.as_deref() {
+        Some(value) => match parse_allowed_ip(value) {
+            Some(allowed_ip) => Some(allowed_ip),
+            None => {
+                return HttpResponse::BadRequest().json(json!({
+                    "status": "fail",
+                    "message": format!("allowed_ip must be an IP address or CIDR range, not {}", value)
+                }));
+            }
+        },
+        None => None,
+    };
+
+    // A URL carrega a validade em segundos, então o banco guarda o mesmo valor
+    let expires = Utc::now().timestamp() + expires_in_secs as i64;
+    let expires_at = DateTime::from_timestamp(expires, 0).unwrap_or_default();
+
+    let created = sqlx::query_as!(
+        ShareLinkModel,
+        r#"INSERT INTO share_links (document_id, expires_at, max_downloads, allowed_ip, sha256)
+           SELECT id, $2, $3, $4, sha256 FROM documents WHERE id = $1 AND deleted_at IS NULL
+           RETURNING *"#,
+        document_id,
+        expires_at,
+        body.max_downloads,
+        allowed_ip
+    )
+    .fetch_optional(&data.db)
+    .await;
+
+    match created {
+        Ok(Some(link)) => {
+            let signature = data.share_signer.sign(link.id, expires);
+            HttpResponse::Ok().json(json!({
+                "status": "success",
+                "url": share_url(&req, &link, &signature),
+                "share_link": link
+            }))
+        }
+        Ok(None) => HttpResponse::NotFound().json(json!({
+            "status": "not found",
+            "message": format!("Document {} not found", document_id)
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to create share link: {:?}", error)
+        })),
+    }
+}
+
+#[get("/documents/{id}/share-links")]
+pub async fn get_share_links(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let document_id = path.into_inner();
+
+    match sqlx::query_as!(
+        ShareLinkModel,
+        "SELECT * FROM share_links WHERE document_id = $1 ORDER BY created_at DESC",
+        document_id
+    )
+    .fetch_all(&data.db)
+    .await
+    {
+        Ok(links) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "results": links.len(),
+            "share_links": links
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to get share links: {:?}", error)
+        })),
+    }
+}
+
+#[get("/documents/{id}/share-links/{link_id}/accesses")]
+pub async fn get_share_link_accesses(path: Path<(Uuid, Uuid)>, data: Data<AppState>) -> impl Responder {
+    let (document_id, link_id) = path.into_inner();
+
+    let accesses = sqlx::query_as!(
+        ShareLinkAccessModel,
+        r#"SELECT a.* FROM share_link_accesses a
+           JOIN share_links l ON l.id = a.share_link_id
+           WHERE l.id = $1 AND l.document_id = $2
+           ORDER BY a.accessed_at"#,
+        link_id,
+        document_id
+    )
+    .fetch_all(&data.db)
+    .await;
+
+    match accesses {
+        Ok(accesses) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "results": accesses.len(),
+            "accesses": accesses
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to get share link accesses: {:?}", error)
+        })),
+    }
+}
+
+async fn record_access(data: &AppState, link_id: Uuid, ip: Option<IpAddr>, user_agent: Option<&str>, outcome: &str) {
+    if let Err(error) = sqlx::query!(
+        "INSERT INTO share_link_accesses (share_link_id, ip, user_agent, outcome) VALUES ($1, $2, $3, $4)",
+        link_id,
+        ip.map(|ip| ip.to_string()),
+        user_agent,
+        outcome
+    )
+    .execute(&data.db)
+    .await
+    {
+        log::warn!("Failed to record access to share link {}: {:?}", link_id, error);
+    }
+}
+
+// Rota pública: confere a assinatura e as restrições do link antes de servir o conteúdo
+#[get("/share/{link_id}")]
+pub async fn download_shared_document(
+    path: Path<Uuid>,
+    query: Query<ShareLinkQuery>,
+    req: HttpRequest,
+    data: Data<AppState>
+) -> impl Responder {
+    let link_id = path.into_inner();
+    if !data.share_signer.verify(link_id, query.expires, &query.signature) {
+        return fail(StatusCode::FORBIDDEN, "Invalid share link signature");
+    }
+
+    let link = match sqlx::query_as!(ShareLinkModel, "SELECT * FROM share_links WHERE id = $1", link_id)
+        .fetch_optional(&data.db)
+        .await
+    {
+        Ok(Some(link)) if link.expires_at.timestamp() == query.expires => link,
+        Ok(_) => return fail(StatusCode::NOT_FOUND, "Share link not found"),
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get share link: {:?}", error)
+            }));
+        }
+    };
+
+    // O IP da conexão, e não o X-Forwarded-For, que o cliente pode forjar
+    let ip = req.peer_addr().map(|address| address.ip());
+    let user_agent = req.headers().get(header::USER_AGENT).and_then(|value| value.to_str().ok());
+
+    if Utc::now() >= link.expires_at {
+        record_access(&data, link.id, ip, user_agent, EXPIRED).await;
+        return fail(StatusCode::GONE, "Share link has expired");
+    }
+    if let Some(allowed_ip) = &link.allowed_ip {
+        if !ip_allowed(allowed_ip, ip) {
+            record_access(&data, link.id, ip, user_agent, IP_DENIED).await;
+            return fail(StatusCode::FORBIDDEN, "Share link is not valid from this address");
+        }
+    }
+
+    // Documento na lixeira não é servido, mas o link volta a valer se ele for restaurado
+    let document = match sqlx::query_as!(
+        DocumentModel,
+        "SELECT * FROM documents WHERE id = $1 AND deleted_at IS NULL",
+        link.document_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(document)) => document,
+        Ok(None) => return fail(StatusCode::NOT_FOUND, "Shared document not found"),
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get document: {:?}", error)
+            }));
+        }
+    };
+
+    // Uma nova revisão não passa a ser compartilhada pelo link antigo
+    if link.sha256.as_ref().is_some_and(|sha256| *sha256 != document.sha256) {
+        record_access(&data, link.id, ip, user_agent, UNAVAILABLE).await;
+        return fail(StatusCode::GONE, "Shared document has changed since the link was created");
+    }
+
+    // Conteúdo em quarentena ou ainda sem verificação não gasta downloads
+    if document.scan_status != scanner::CLEAN {
+        record_access(&data, link.id, ip, user_agent, UNAVAILABLE).await;
+        return download::serve(&req, &data, (&document).into()).await;
+    }
+
+    // Sem downloads restantes nada mais sai, nem um intervalo
+    if link.max_downloads.is_some_and(|max_downloads| link.download_count >= max_downloads) {
+        record_access(&data, link.id, ip, user_agent, EXHAUSTED).await;
+        return fail(StatusCode::GONE, "Share link has no downloads left");
+    }
+
+    // Toda resposta com conteúdo, inteiro ou um intervalo, gasta um download;
+    // um 304 ou uma falha de leitura não
+    let response = download::serve(&req, &data, (&document).into()).await;
+    if !matches!(response.status(), StatusCode::OK | StatusCode::PARTIAL_CONTENT) {
+        let outcome = if response.status().is_success() || response.status() == StatusCode::NOT_MODIFIED {
+            GRANTED
+        } else {
+            UNAVAILABLE
+        };
+        record_access(&data, link.id, ip, user_agent, outcome).await;
+        return response;
+    }
+
+    // A condição no contador garante o limite mesmo com downloads simultâneos;
+    // quem perde a corrida recebe 410 e a resposta montada é descartada
+    let counted = sqlx::query_scalar!(
+        r#"UPDATE share_links SET download_count = download_count + 1
+           WHERE id = $1 AND (max_downloads IS NULL OR download_count < max_downloads)
+           RETURNING download_count"#,
+        link.id
+    )
+    .fetch_optional(&data.db)
+    .await;
+
+    match counted {
+        Ok(Some(_)) => {
+            record_access(&data, link.id, ip, user_agent, GRANTED).await;
+            response
+        }
+        Ok(None) => {
+            record_access(&data, link.id, ip, user_agent, EXHAUSTED).await;
+            fail(StatusCode::GONE, "Share link has no downloads left")
+        }
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to update share link: {:?}", error)
+        })),
+    }
+}
@@ -0,0 +1,418 @@
+// Assinatura dos documentos aprovados. Na aprovação, o hash do conteúdo e os
+// dados principais do documento viram um payload JSON assinado com a chave
+// Ed25519 do servidor (SIGNING_KEY); payload e assinatura destacada ficam em
+// document_signatures. Com a chave pública, um parceiro confere sozinho que o
+// arquivo que recebeu é o mesmo que aprovamos, ou usa POST /documents/verify.
+use std::{collections::HashMap, io};
+
+use actix_multipart::Multipart;
+use actix_web::{
+    get, post,
+    web::{Data, Path},
+    HttpResponse, Responder,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use openssl::{
+    pkey::{Id, PKey, Private, Public},
+    sign::{Signer, Verifier},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+use crate::{
+    encryption,
+    model::{DocumentModel, DocumentSignatureModel},
+    upload::{self, UploadError},
+    AppState,
+};
+
+pub const ALGORITHM: &str = "Ed25519";
+
+const KEY_LEN: usize = 32;
+
+// Versão do formato do payload; muda se os campos assinados mudarem
+const PAYLOAD_VERSION: i32 = 1;
+
+fn parse_key(name: &str, value: &str) -> io::Result<[u8; KEY_LEN]> {
+    STANDARD
+        .decode(value.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} must be {} bytes encoded in base64", name, KEY_LEN),
+            )
+        })
+}
+
+/// Chave de assinatura atual e as chaves públicas das anteriores, que só
+/// servem para conferir o que foi assinado antes da troca.
+pub struct SigningKeys {
+    current_id: String,
+    current: PKey<Private>,
+    public_keys: HashMap<String, PKey<Public>>,
+}
+
+impl SigningKeys {
+    /// Lê a chave privada de SIGNING_KEY (semente Ed25519 de 32 bytes em
+    /// base64), com o identificador em SIGNING_KEY_ID, e as chaves públicas
+    /// anteriores de PREVIOUS_SIGNING_PUBLIC_KEYS (`id:chave,id:chave`).
+    pub fn from_env() -> io::Result<Self> {
+        let seed = std::env::var("SIGNING_KEY")
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "SIGNING_KEY must be set"))?;
+        let current_id = std::env::var("SIGNING_KEY_ID").unwrap_or_else(|_| "1".to_string());
+        let current = PKey::private_key_from_raw_bytes(&parse_key("SIGNING_KEY", &seed)?, Id::ED25519)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+        let mut public_keys = HashMap::new();
+        if let Ok(previous) = std::env::var("PREVIOUS_SIGNING_PUBLIC_KEYS") {
+            for entry in previous.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+                let (id, key) = entry.split_once(':').ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "PREVIOUS_SIGNING_PUBLIC_KEYS entries must look like id:key",
+                    )
+                })?;
+                let key = PKey::public_key_from_raw_bytes(&parse_key("PREVIOUS_SIGNING_PUBLIC_KEYS", key)?, Id::ED25519)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+                public_keys.insert(id.trim().to_string(), key);
+            }
+        }
+        let public = current.raw_public_key().map_err(io::Error::other)?;
+        let public = PKey::public_key_from_raw_bytes(&public, Id::ED25519).map_err(io::Error::other)?;
+        public_keys.insert(current_id.clone(), public);
+
+        Ok(SigningKeys { current_id, current, public_keys })
+    }
+
+    pub fn current_id(&self) -> &str {
+        &self.current_id
+    }
+
+    /// Assina com a chave atual; devolve a assinatura em base64.
+    pub fn sign(&self, payload: &[u8]) -> io::Result<String> {
+        let mut signer = Signer::new_without_digest(&self.current).map_err(io::Error::other)?;
+        let signature = signer.sign_oneshot_to_vec(payload).map_err(io::Error::other)?;
+        Ok(STANDARD.encode(signature))
+    }
+
+    /// Confere a assinatura com a chave pública `key_id`; chave desconhecida não confere.
+    pub fn verify(&self, key_id: &str, payload: &[u8], signature: &str) -> bool {
+        let (Some(key), Ok(signature)) = (self.public_keys.get(key_id), STANDARD.decode(signature)) else {
+            return false;
+        };
+        Verifier::new_without_digest(key)
+            .and_then(|mut verifier| verifier.verify_oneshot(&signature, payload))
+            .unwrap_or(false)
+    }
+
+    fn public_key(&self, key_id: &str) -> Option<serde_json::Value> {
+        let key = self.public_keys.get(key_id)?;
+        let raw = key.raw_public_key().ok()?;
+        let pem = key.public_key_to_pem().ok()?;
+        Some(json!({
+            "key_id": key_id,
+            "algorithm": ALGORITHM,
+            "public_key": STANDARD.encode(raw),
+            "pem": String::from_utf8_lossy(&pem)
+        }))
+    }
+}
+
+/// O que é assinado. A ordem dos campos é a da struct, então o JSON gerado é
+/// sempre o mesmo para os mesmos dados.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedPayload {
+    pub version: i32,
+    pub document_id: Uuid,
+    pub revision: i32,
+    pub user_id: Uuid,
+    pub doc_type: String,
+    pub original_filename: String,
+    pub mime_type: String,
+    pub size_bytes: i64,
+    pub sha256: String,
+    pub approved_by: Option<Uuid>,
+    pub approved_at: Option<DateTime<Utc>>,
+}
+
+/// Assina o documento recém-aprovado e grava a assinatura.
+pub async fn sign_document<'c, E>(
+    executor: E,
+    keys: &SigningKeys,
+    document: &DocumentModel,
+) -> io::Result<DocumentSignatureModel>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let payload = SignedPayload {
+        version: PAYLOAD_VERSION,
+        document_id: document.id,
+        revision: document.revision,
+        user_id: document.user_id,
+        doc_type: document.doc_type.clone(),
+        original_filename: document.original_filename.clone(),
+        mime_type: document.mime_type.clone(),
+        size_bytes: document.size_bytes,
+        sha256: document.sha256.clone(),
+        approved_by: document.reviewer_id,
+        approved_at: document.decided_at,
+    };
+    let payload = serde_json::to_string(&payload).map_err(io::Error::other)?;
+    let signature = keys.sign(payload.as_bytes())?;
+
+    sqlx::query_as!(
+        DocumentSignatureModel,
+        r#"INSERT INTO document_signatures (document_id, revision, sha256, key_id, payload, signature)
+           VALUES ($1, $2, $3, $4, $5, $6)
+           RETURNING *"#,
+        document.id,
+        document.revision,
+        document.sha256,
+        keys.current_id(),
+        payload,
+        signature
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(io::Error::other)
+}
+
+// Resultado da conferência de uma assinatura guardada
+fn check(keys: &SigningKeys, signature: &DocumentSignatureModel, sha256: &str) -> serde_json::Value {
+    let signature_valid = keys.verify(&signature.key_id, signature.payload.as_bytes(), &signature.signature);
+    // O hash vem do payload assinado, não da coluna, que poderia ter sido trocada
+    let signed_sha256 = serde_json::from_str::<SignedPayload>(&signature.payload)
+        .map(|payload| payload.sha256)
+        .unwrap_or_default();
+    let content_matches = signed_sha256 == sha256;
+    json!({
+        "valid": signature_valid && content_matches,
+        "signature_valid": signature_valid,
+        "content_matches": content_matches,
+        "signature": signature
+    })
+}
+
+#[get("/signing/public-key")]
+pub async fn get_public_key(data: Data<AppState>) -> impl Responder {
+    let keys = &data.signing_keys;
+    let mut previous: Vec<_> = keys
+        .public_keys
+        .keys()
+        .filter(|key_id| *key_id != keys.current_id())
+        .filter_map(|key_id| keys.public_key(key_id))
+        .collect();
+    previous.sort_by(|a, b| a["key_id"].as_str().cmp(&b["key_id"].as_str()));
+
+    match keys.public_key(keys.current_id()) {
+        Some(current) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "key": current,
+            "previous_keys": previous
+        })),
+        None => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": "Failed to export the signing public key"
+        })),
+    }
+}
+
+// Assinaturas do documento, da mais recente para a mais antiga
+#[get("/documents/{id}/signatures")]
+pub async fn get_document_signatures(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let document_id = path.into_inner();
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM documents WHERE id = $1 AND deleted_at IS NULL) AS "exists!""#,
+        document_id
+    )
+    .fetch_one(&data.db)
+    .await;
+    match exists {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Document {} not found", document_id)
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get document: {:?}", error)
+            }));
+        }
+    }
+
+    let signatures = sqlx::query_as!(
+        DocumentSignatureModel,
+        "SELECT * FROM document_signatures WHERE document_id = $1 ORDER BY signed_at DESC",
+        document_id
+    )
+    .fetch_all(&data.db)
+    .await;
+
+    match signatures {
+        Ok(signatures) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "document_id": document_id,
+            "algorithm": ALGORITHM,
+            "results": signatures.len(),
+            "signatures": signatures
+        })),
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to get document signatures: {:?}", error)
+        })),
+    }
+}
+
+// Confere um arquivo (campo `file`) contra as assinaturas do mesmo conteúdo, ou
+// o conteúdo guardado de um documento (campo `document_id`) contra a sua última assinatura
+#[post("/documents/verify")]
+pub async fn verify_document(payload: Multipart, data: Data<AppState>) -> impl Responder {
+    let form = match upload::read_form(payload, &data.staging_dir).await {
+        Ok(form) => form,
+        Err(error) => return error.error_response(),
+    };
+
+    match (form.file, form.fields.get("document_id")) {
+        (Some(file), None) => {
+            upload::discard(&file.path).await;
+            verify_file(&data, &file.sha256).await
+        }
+        (None, Some(document_id)) => match Uuid::parse_str(document_id.trim()) {
+            Ok(document_id) => verify_stored(&data, document_id).await,
+            Err(_) => UploadError::Invalid("document_id must be a UUID".to_string()).error_response(),
+        },
+        (file, _) => {
+            if let Some(file) = file {
+                upload::discard(&file.path).await;
+            }
+            UploadError::Invalid("Send either a file or a document_id".to_string()).error_response()
+        }
+    }
+}
+
+async fn verify_file(data: &AppState, sha256: &str) -> HttpResponse {
+    // A última assinatura de cada documento com esse conteúdo
+    let signatures = sqlx::query_as!(
+        DocumentSignatureModel,
+        r#"SELECT DISTINCT ON (s.document_id) s.*
+           FROM document_signatures s JOIN documents d ON d.id = s.document_id
+           WHERE s.sha256 = $1 AND d.deleted_at IS NULL
+           ORDER BY s.document_id, s.signed_at DESC"#,
+        sha256
+    )
+    .fetch_all(&data.db)
+    .await;
+
+    match signatures {
+        Ok(signatures) => {
+            let results: Vec<_> = signatures.iter().map(|signature| check(&data.signing_keys, signature, sha256)).collect();
+            let valid = results.iter().any(|result| result["valid"] == true);
+            let message = match (results.is_empty(), valid) {
+                (true, _) => "No signed document has this content",
+                (false, true) => "Signature is valid",
+                (false, false) => "Signature is not valid",
+            };
+            HttpResponse::Ok().json(json!({
+                "status": "success",
+                "valid": valid,
+                "sha256": sha256,
+                "message": message,
+                "results": results
+            }))
+        }
+        Err(error) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": format!("Failed to get signatures: {:?}", error)
+        })),
+    }
+}
+
+async fn verify_stored(data: &AppState, document_id: Uuid) -> HttpResponse {
+    let document = sqlx::query_as!(
+        DocumentModel,
+        "SELECT * FROM documents WHERE id = $1 AND deleted_at IS NULL",
+        document_id
+    )
+    .fetch_optional(&data.db)
+    .await;
+    let document = match document {
+        Ok(Some(document)) => document,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Document {} not found", document_id)
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get document: {:?}", error)
+            }));
+        }
+    };
+
+    let signature = sqlx::query_as!(
+        DocumentSignatureModel,
+        "SELECT * FROM document_signatures WHERE document_id = $1 ORDER BY signed_at DESC LIMIT 1",
+        document_id
+    )
+    .fetch_optional(&data.db)
+    .await;
+    let signature = match signature {
+        Ok(Some(signature)) => signature,
+        Ok(None) => {
+            return HttpResponse::Ok().json(json!({
+                "status": "success",
+                "valid": false,
+                "document_id": document_id,
+                "message": format!("Document {} has not been signed; documents are signed when approved", document_id)
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to get signature: {:?}", error)
+            }));
+        }
+    };
+
+    // Confere o que está de fato guardado, não o hash registrado no banco
+    let sha256 = match stored_sha256(data, &document).await {
+        Ok(sha256) => sha256,
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to read document content: {}", error)
+            }));
+        }
+    };
+
+    let mut result = check(&data.signing_keys, &signature, &sha256);
+    let valid = result["valid"] == true;
+    result["status"] = json!("success");
+    result["document_id"] = json!(document_id);
+    result["sha256"] = json!(sha256);
+    result["message"] = json!(if valid { "Signature is valid" } else { "Signature is not valid" });
+    HttpResponse::Ok().json(result)
+}
+
+async fn stored_sha256(data: &AppState, document: &DocumentModel) -> io::Result<String> {
+    let data_key = data.keyring.open(document.master_key_id.as_deref(), document.wrapped_key.as_deref())?;
+    let mut body = encryption::get(data.storage.as_ref(), &document.filename, data_key.as_ref(), None).await?;
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = body.next().await {
+        hasher.update(&chunk?);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
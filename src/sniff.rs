@@ -0,0 +1,139 @@
+use std::{collections::HashMap, io, path::Path};
+
+use tokio::{fs, io::AsyncReadExt};
+
+// Bytes do início do arquivo usados para reconhecer o formato
+const SNIFF_LEN: usize = 8192;
+
+// Chave do allowlist aplicada aos doc_types sem entrada própria
+const ANY_DOC_TYPE: &str = "*";
+
+const GENERIC_MIME_TYPES: [&str; 2] = ["application/octet-stream", "binary/octet-stream"];
+
+/// Tipos de arquivo aceitos por doc_type, verificados pelo conteúdo.
+///
+/// Lido do JSON em MIME_ALLOWLIST_FILE, no formato
+/// `{"passport": ["image/jpeg", "image/png", "application/pdf"], "*": [...]}`.
+/// A chave `*` vale para os doc_types não listados; sem ela, esses aceitam
+/// qualquer tipo reconhecível.
+#[derive(Debug)]
+pub struct MimePolicy {
+    allowlist: HashMap<String, Vec<String>>,
+}
+
+impl Default for MimePolicy {
+    fn default() -> Self {
+        let images_and_pdf = ["image/jpeg", "image/png", "application/pdf"];
+        let mut allowlist = HashMap::new();
+        for doc_type in ["passport", "id_card", "driver_license"] {
+            allowlist.insert(doc_type.to_string(), images_and_pdf.iter().map(|m| m.to_string()).collect());
+        }
+        allowlist.insert(
+            ANY_DOC_TYPE.to_string(),
+            ["image/jpeg", "image/png", "image/webp", "image/tiff", "image/heif", "application/pdf"]
+                .iter()
+                .map(|m| m.to_string())
+                .collect(),
+        );
+        MimePolicy { allowlist }
+    }
+}
+
+impl MimePolicy {
+    pub fn from_env() -> io::Result<Self> {
+        let path = match std::env::var("MIME_ALLOWLIST_FILE") {
+            Ok(path) => path,
+            Err(_) => return Ok(MimePolicy::default()),
+        };
+
+        let content = std::fs::read_to_string(&path)?;
+        let allowlist: HashMap<String, Vec<String>> = serde_json::from_str(&content).map_err(|error| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid MIME_ALLOWLIST_FILE {}: {}", path, error))
+        })?;
+        let allowlist = allowlist
+            .into_iter()
+            .map(|(doc_type, types)| {
+                (doc_type.to_lowercase(), types.iter().map(|m| normalize(m)).collect())
+            })
+            .collect();
+
+        Ok(MimePolicy { allowlist })
+    }
+
+    fn allowed_for(&self, doc_type: &str) -> Option<&Vec<String>> {
+        self.allowlist
+            .get(&doc_type.to_lowercase())
+            .or_else(|| self.allowlist.get(ANY_DOC_TYPE))
+    }
+
+    /// Confere o conteúdo do arquivo contra o Content-Type declarado, a
+    /// extensão do nome original e o allowlist do doc_type.
+    ///
+    /// Devolve o tipo detectado, que passa a ser o `mime_type` do documento.
+    pub fn check(&self, doc_type: &str, declared: &str, filename: &str, head: &[u8]) -> Result<String, String> {
+        let sniffed = infer::get(head)
+            .map(|kind| kind.mime_type().to_string())
+            .ok_or_else(|| "Could not determine the file type from its content".to_string())?;
+
+        let declared = normalize(declared);
+        if !GENERIC_MIME_TYPES.contains(&declared.as_str()) && declared != sniffed {
+            return Err(format!(
+                "Declared Content-Type {} does not match the file content ({})",
+                declared, sniffed
+            ));
+        }
+
+        if let Some(extension) = Path::new(filename).extension().and_then(|ext| ext.to_str()) {
+            let expected: Vec<String> = mime_guess::from_ext(extension)
+                .iter()
+                .map(|mime| normalize(mime.essence_str()))
+                .collect();
+            if !expected.contains(&sniffed) {
+                return Err(format!(
+                    "File extension .{} does not match the file content ({})",
+                    extension, sniffed
+                ));
+            }
+        }
+
+        if let Some(allowed) = self.allowed_for(doc_type) {
+            if !allowed.contains(&sniffed) {
+                return Err(format!(
+                    "File type {} is not allowed for doc_type {}; allowed: {}",
+                    sniffed,
+                    doc_type,
+                    allowed.join(", ")
+                ));
+            }
+        }
+
+        Ok(sniffed)
+    }
+}
+
+/// Lê o início de um arquivo em disco para a detecção de tipo.
+pub async fn read_head(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = fs::File::open(path).await?;
+    let mut head = vec![0u8; SNIFF_LEN];
+    let mut filled = 0;
+    while filled < SNIFF_LEN {
+        let read = file.read(&mut head[filled..]).await?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    head.truncate(filled);
+    Ok(head)
+}
+
+// Apelidos comuns que clientes ainda mandam
+fn normalize(mime: &str) -> String {
+    let mime = mime.trim().to_lowercase();
+    match mime.as_str() {
+        "image/jpg" | "image/pjpeg" => "image/jpeg".to_string(),
+        "image/x-png" => "image/png".to_string(),
+        "application/x-pdf" => "application/pdf".to_string(),
+        _ => mime,
+    }
+}
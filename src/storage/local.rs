@@ -0,0 +1,83 @@
+use std::{
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::io::ReaderStream;
+
+use super::{not_found, ByteStream, Storage};
+
+/// Arquivos em um diretório do disco local.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        Ok(LocalStorage { root })
+    }
+
+    fn path_for(&self, key: &str) -> io::Result<PathBuf> {
+        // As chaves são geradas pela API, mas nunca devem sair do diretório raiz
+        if key.is_empty() || key.contains(['/', '\\']) || key.starts_with('.') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid storage key: {}", key)));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put_file(&self, key: &str, src: &Path) -> io::Result<()> {
+        let dest = self.path_for(key)?;
+        // rename só funciona no mesmo sistema de arquivos; senão, copia
+        if fs::rename(src, &dest).await.is_err() {
+            fs::copy(src, &dest).await?;
+            fs::remove_file(src).await?;
+        }
+        Ok(())
+    }
+
+    async fn put_bytes(&self, key: &str, bytes: Bytes) -> io::Result<()> {
+        fs::write(self.path_for(key)?, bytes).await
+    }
+
+    async fn size(&self, key: &str) -> io::Result<u64> {
+        match fs::metadata(self.path_for(key)?).await {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Err(not_found(key)),
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn get(&self, key: &str, range: Option<(u64, u64)>) -> io::Result<ByteStream> {
+        let mut file = match fs::File::open(self.path_for(key)?).await {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Err(not_found(key)),
+            Err(error) => return Err(error),
+        };
+
+        match range {
+            Some((start, end)) => {
+                file.seek(SeekFrom::Start(start)).await?;
+                Ok(Box::pin(ReaderStream::new(file.take(end - start + 1))))
+            }
+            None => Ok(Box::pin(ReaderStream::new(file))),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path_for(key)?).await {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::{collections::HashMap, io, path::Path, sync::RwLock};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream;
+
+use super::{not_found, ByteStream, Storage};
+
+/// Tudo em memória; usado nos testes e em desenvolvimento.
+#[derive(Default)]
+pub struct MemoryStorage {
+    objects: RwLock<HashMap<String, Bytes>>,
+}
+
+impl MemoryStorage {
+    fn object(&self, key: &str) -> io::Result<Bytes> {
+        self.objects
+            .read()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| not_found(key))
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn put_file(&self, key: &str, src: &Path) -> io::Result<()> {
+        let bytes = tokio::fs::read(src).await?;
+        tokio::fs::remove_file(src).await?;
+        self.put_bytes(key, Bytes::from(bytes)).await
+    }
+
+    async fn put_bytes(&self, key: &str, bytes: Bytes) -> io::Result<()> {
+        self.objects.write().unwrap().insert(key.to_string(), bytes);
+        Ok(())
+    }
+
+    async fn size(&self, key: &str) -> io::Result<u64> {
+        Ok(self.object(key)?.len() as u64)
+    }
+
+    async fn get(&self, key: &str, range: Option<(u64, u64)>) -> io::Result<ByteStream> {
+        let object = self.object(key)?;
+        let chunk = match range {
+            Some((start, end)) => object.slice(start as usize..=end as usize),
+            None => object,
+        };
+        Ok(Box::pin(stream::once(async move { Ok(chunk) })))
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        self.objects.write().unwrap().remove(key);
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+mod local;
+mod memory;
+mod s3;
+
+use std::{io, path::Path, pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::Stream;
+
+pub use local::LocalStorage;
+pub use memory::MemoryStorage;
+pub use s3::S3Storage;
+
+/// Corpo de um arquivo lido do armazenamento, entregue em pedaços.
+pub type ByteStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/// Onde ficam os bytes dos documentos. A chave é o `filename` da tabela `documents`.
+///
+/// Chaves inexistentes devem resultar em erro com `io::ErrorKind::NotFound`.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Move para o armazenamento um arquivo já gravado em disco local.
+    async fn put_file(&self, key: &str, src: &Path) -> io::Result<()>;
+
+    async fn put_bytes(&self, key: &str, bytes: Bytes) -> io::Result<()>;
+
+    /// Tamanho em bytes do conteúdo guardado sob `key`.
+    async fn size(&self, key: &str) -> io::Result<u64>;
+
+    /// Lê o conteúdo, inteiro ou só o intervalo fechado `(início, fim)`.
+    async fn get(&self, key: &str, range: Option<(u64, u64)>) -> io::Result<ByteStream>;
+
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// Escolhe o backend pela variável STORAGE_BACKEND (local, memory ou s3).
+pub fn from_env() -> io::Result<Arc<dyn Storage>> {
+    let backend = std::env::var("STORAGE_BACKEND").unwrap_or_else(|_| "local".to_string());
+
+    match backend.as_str() {
+        "local" => {
+            let root = std::env::var("STORAGE_DIR").unwrap_or_else(|_| "storage".to_string());
+            Ok(Arc::new(LocalStorage::new(root)?))
+        }
+        "memory" => Ok(Arc::new(MemoryStorage::default())),
+        "s3" => Ok(Arc::new(S3Storage::from_env()?)),
+        other => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown STORAGE_BACKEND: {}", other),
+        )),
+    }
+}
+
+fn not_found(key: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("No stored content for key {}", key))
+}
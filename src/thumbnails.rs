@@ -0,0 +1,135 @@
+// Miniaturas do conteúdo dos documentos, para listagens sem baixar o arquivo
+// inteiro. Como o conteúdo, ficam no armazenamento com chave derivada da do
+// blob, cifradas com a mesma chave de dados, e são apagadas junto com ele.
+use std::{io::Cursor, path::Path};
+
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, ImageReader};
+use tokio::process::Command;
+
+use crate::{
+    encryption::{self, DataKey},
+    storage::Storage,
+};
+
+pub const THUMBNAIL_MIME_TYPE: &str = "image/jpeg";
+
+// Lado maior de cada tamanho, em pixels
+pub const SIZES: [(&str, u32); 3] = [("small", 128), ("medium", 256), ("large", 512)];
+
+pub const DEFAULT_SIZE: &str = "medium";
+
+const JPEG_QUALITY: u8 = 80;
+
+const RASTER_MIME_TYPES: [&str; 4] = ["image/jpeg", "image/png", "image/webp", "image/tiff"];
+
+/// Chave da miniatura `size` do conteúdo guardado sob `content_key`.
+pub fn key(content_key: &str, size: &str) -> String {
+    format!("{}.thumb-{}", content_key, size)
+}
+
+pub fn is_size(size: &str) -> bool {
+    SIZES.iter().any(|(name, _)| *name == size)
+}
+
+/// Gera e grava as miniaturas de um arquivo ainda em disco.
+///
+/// Imagens são reduzidas diretamente; PDFs têm a primeira página convertida
+/// pelo `pdftoppm` (PDF_RASTERIZER). Tipos sem prévia e falhas só são
+/// registrados no log: a falta de miniatura não impede o upload.
+pub async fn generate(storage: &dyn Storage, content_key: &str, path: &Path, mime_type: &str, data_key: &DataKey) {
+    let preview = if RASTER_MIME_TYPES.contains(&mime_type) {
+        decode(path.to_path_buf()).await
+    } else if mime_type == "application/pdf" {
+        rasterize_pdf(path).await
+    } else {
+        return;
+    };
+
+    let preview = match preview {
+        Ok(preview) => preview,
+        Err(error) => {
+            log::warn!("Failed to generate preview for {}: {}", content_key, error);
+            return;
+        }
+    };
+
+    let encoded = tokio::task::spawn_blocking(move || {
+        SIZES
+            .iter()
+            .map(|(size, pixels)| encode_jpeg(&preview.thumbnail(*pixels, *pixels)).map(|bytes| (*size, bytes)))
+            .collect::<Result<Vec<_>, String>>()
+    })
+    .await
+    .map_err(|error| error.to_string())
+    .and_then(|encoded| encoded);
+
+    let encoded = match encoded {
+        Ok(encoded) => encoded,
+        Err(error) => {
+            log::warn!("Failed to encode thumbnails for {}: {}", content_key, error);
+            return;
+        }
+    };
+
+    for (size, bytes) in encoded {
+        if let Err(error) = encryption::put_bytes(storage, &key(content_key, size), &bytes, data_key).await {
+            log::warn!("Failed to store {} thumbnail for {}: {}", size, content_key, error);
+        }
+    }
+}
+
+/// Apaga todas as miniaturas de um conteúdo.
+pub async fn remove(storage: &dyn Storage, content_key: &str) {
+    for (size, _) in SIZES {
+        if let Err(error) = storage.delete(&key(content_key, size)).await {
+            log::warn!("Failed to remove {} thumbnail for {}: {}", size, content_key, error);
+        }
+    }
+}
+
+async fn decode(path: std::path::PathBuf) -> Result<DynamicImage, String> {
+    tokio::task::spawn_blocking(move || {
+        ImageReader::open(&path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|error| error.to_string())?
+            .decode()
+            .map_err(|error| error.to_string())
+    })
+    .await
+    .map_err(|error| error.to_string())?
+}
+
+// Converte a primeira página em PNG, no tamanho da maior miniatura
+async fn rasterize_pdf(path: &Path) -> Result<DynamicImage, String> {
+    let rasterizer = std::env::var("PDF_RASTERIZER").unwrap_or_else(|_| "pdftoppm".to_string());
+    let largest = SIZES.iter().map(|(_, pixels)| *pixels).max().unwrap_or(512);
+    let prefix = path.with_extension("preview");
+    let output = prefix.with_extension("preview.png");
+
+    let status = Command::new(&rasterizer)
+        .args(["-f", "1", "-l", "1", "-singlefile", "-png", "-scale-to"])
+        .arg(largest.to_string())
+        .arg(path)
+        .arg(&prefix)
+        .kill_on_drop(true)
+        .status()
+        .await
+        .map_err(|error| format!("Failed to run {}: {}", rasterizer, error))?;
+
+    let preview = if status.success() {
+        decode(output.clone()).await
+    } else {
+        Err(format!("{} exited with {}", rasterizer, status))
+    };
+
+    let _ = tokio::fs::remove_file(&output).await;
+    preview
+}
+
+fn encode_jpeg(image: &DynamicImage) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    JpegEncoder::new_with_quality(Cursor::new(&mut bytes), JPEG_QUALITY)
+        .encode_image(&DynamicImage::ImageRgb8(image.to_rgb8()))
+        .map_err(|error| error.to_string())?;
+    Ok(bytes)
+}
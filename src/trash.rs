@@ -0,0 +1,191 @@
+// Lixeira: tarefas e documentos apagados ficam com deleted_at preenchido, fora
+// das listagens, até serem restaurados ou apagados de vez. O conteúdo de um
+// documento na lixeira continua no armazenamento.
+use actix_web::{
+    delete, get, post,
+    web::{Data, Path, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    model::{DocumentModel, TaskModel},
+    retention,
+    schema::FilterOptions,
+    AppState,
+};
+
+fn database_error(action: &str, error: sqlx::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(json!({
+        "status": "error",
+        "message": format!("Failed to {}: {:?}", action, error)
+    }))
+}
+
+fn not_in_trash(kind: &str, id: Uuid) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "status": "not found",
+        "message": format!("{} {} is not in the trash", kind, id)
+    }))
+}
+
+#[get("/trash/tasks")]
+pub async fn get_trashed_tasks(opts: Query<FilterOptions>, data: Data<AppState>) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = (opts.page.unwrap_or(1) - 1) * limit;
+
+    match sqlx::query_as!(
+        TaskModel,
+        "SELECT * FROM tasks WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC LIMIT $1 OFFSET $2",
+        limit as i32,
+        offset as i32
+    )
+    .fetch_all(&data.db)
+    .await
+    {
+        Ok(tasks) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "results": tasks.len(),
+            "tasks": tasks
+        })),
+        Err(error) => database_error("list trashed tasks", error),
+    }
+}
+
+#[post("/trash/tasks/{id}/restore")]
+pub async fn restore_task(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let task_id = path.into_inner();
+
+    match sqlx::query_as!(
+        TaskModel,
+        "UPDATE tasks SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL RETURNING *",
+        task_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(task)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "task": task
+        })),
+        Ok(None) => not_in_trash("Task", task_id),
+        Err(error) => database_error("restore task", error),
+    }
+}
+
+// Apaga de vez; só vale para o que já está na lixeira
+#[delete("/trash/tasks/{id}")]
+pub async fn purge_task(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let task_id = path.into_inner();
+
+    match sqlx::query!("DELETE FROM tasks WHERE id = $1 AND deleted_at IS NOT NULL", task_id)
+        .execute(&data.db)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => not_in_trash("Task", task_id),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(error) => database_error("purge task", error),
+    }
+}
+
+#[get("/trash/documents")]
+pub async fn get_trashed_documents(opts: Query<FilterOptions>, data: Data<AppState>) -> impl Responder {
+    let limit = opts.limit.unwrap_or(10);
+    let offset = (opts.page.unwrap_or(1) - 1) * limit;
+
+    match sqlx::query_as!(
+        DocumentModel,
+        "SELECT * FROM documents WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC LIMIT $1 OFFSET $2",
+        limit as i32,
+        offset as i32
+    )
+    .fetch_all(&data.db)
+    .await
+    {
+        Ok(documents) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "results": documents.len(),
+            "documents": documents
+        })),
+        Err(error) => database_error("list trashed documents", error),
+    }
+}
+
+#[post("/trash/documents/{id}/restore")]
+pub async fn restore_document(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let document_id = path.into_inner();
+
+    match sqlx::query_as!(
+        DocumentModel,
+        "UPDATE documents SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL RETURNING *",
+        document_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    {
+        Ok(Some(document)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "document": document
+        })),
+        Ok(None) => not_in_trash("Document", document_id),
+        Err(error) => database_error("restore document", error),
+    }
+}
+
+enum Purge {
+    Purged,
+    NotInTrash,
+    Held,
+    Retained(DateTime<Utc>),
+}
+
+// Apaga de vez o documento, as revisões e, se ninguém mais o usa, o conteúdo;
+// documentos sob retenção legal, ou ainda no prazo de retenção, ficam. A
+// destruição vai para document_destructions como as da limpeza
+#[delete("/trash/documents/{id}")]
+pub async fn purge_document(path: Path<Uuid>, data: Data<AppState>) -> impl Responder {
+    let document_id = path.into_inner();
+
+    let purged = async {
+        let mut tx = data.db.begin().await?;
+        let in_trash = sqlx::query_scalar!(
+            r#"SELECT deleted_at IS NOT NULL AS "in_trash!" FROM documents WHERE id = $1 FOR UPDATE"#,
+            document_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        if in_trash != Some(true) {
+            return Ok(Purge::NotInTrash);
+        }
+        if retention::lock_hold(&mut tx, document_id).await? == Some(true) {
+            return Ok(Purge::Held);
+        }
+        if let Some(kept_until) = retention::kept_until(&mut tx, document_id).await? {
+            return Ok(Purge::Retained(kept_until));
+        }
+
+        let destruction = retention::destroy(&mut tx, document_id, retention::TRASH).await?;
+        tx.commit().await?;
+        if let Some(destruction) = destruction {
+            destruction.finish(&data.db, data.storage.as_ref()).await;
+        }
+        Ok::<_, sqlx::Error>(Purge::Purged)
+    }
+    .await;
+
+    match purged {
+        Ok(Purge::Purged) => HttpResponse::NoContent().finish(),
+        Ok(Purge::NotInTrash) => not_in_trash("Document", document_id),
+        Ok(Purge::Held) => HttpResponse::Conflict().json(json!({
+            "status": "fail",
+            "message": format!("Document {} is under legal hold", document_id)
+        })),
+        Ok(Purge::Retained(kept_until)) => HttpResponse::Conflict().json(json!({
+            "status": "fail",
+            "message": format!("Document {} must be kept until {} by its retention rule", document_id, kept_until)
+        })),
+        Err(error) => database_error("purge document", error),
+    }
+}
@@ -0,0 +1,535 @@
+// Uploads resumíveis seguindo o protocolo tus 1.0.0 (https://tus.io/protocols/resumable-upload)
+// com as extensões creation e termination.
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use actix_web::{
+    delete, head, options, patch, post,
+    http::{header, StatusCode},
+    web::{Data, Path, Payload},
+    HttpRequest, HttpResponse, HttpResponseBuilder, Responder,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::StreamExt;
+use serde_json::json;
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+use uuid::Uuid;
+
+use crate::{
+    doc_types,
+    model::UploadModel,
+    quotas,
+    schema::CreateDocumentSchema,
+    upload::{self, StagedFile, UploadError},
+    AppState,
+};
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination";
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// Tamanho máximo aceito para um upload (Tus-Max-Size).
+pub const MAX_UPLOAD_LENGTH: i64 = 100 * 1024 * 1024;
+
+/// Uploads que estão recebendo um PATCH agora; um segundo PATCH simultâneo
+/// para o mesmo upload é recusado em vez de intercalar bytes no arquivo.
+#[derive(Clone, Default)]
+pub struct UploadLocks(Arc<Mutex<HashSet<Uuid>>>);
+
+struct UploadLock {
+    locks: UploadLocks,
+    id: Uuid,
+}
+
+impl UploadLocks {
+    fn try_lock(&self, id: Uuid) -> Option<UploadLock> {
+        if self.0.lock().unwrap().insert(id) {
+            Some(UploadLock { locks: self.clone(), id })
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        self.locks.0.lock().unwrap().remove(&self.id);
+    }
+}
+
+fn tus_response(status: StatusCode) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::build(status);
+    builder.insert_header(("Tus-Resumable", TUS_VERSION));
+    builder
+}
+
+fn tus_error(status: StatusCode, message: impl Into<String>) -> HttpResponse {
+    tus_response(status).json(json!({
+        "status": if status.is_server_error() { "error" } else { "fail" },
+        "message": message.into()
+    }))
+}
+
+// Erros de validação do documento, com o cabeçalho Tus-Resumable
+fn upload_error(error: UploadError) -> HttpResponse {
+    let mut response = error.error_response();
+    response.headers_mut().insert(
+        header::HeaderName::from_static("tus-resumable"),
+        header::HeaderValue::from_static(TUS_VERSION),
+    );
+    response
+}
+
+fn header_str<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+// Todas as requisições, exceto OPTIONS, precisam declarar a versão do protocolo
+fn check_version(req: &HttpRequest) -> Result<(), HttpResponse> {
+    if header_str(req, "Tus-Resumable") == Some(TUS_VERSION) {
+        Ok(())
+    } else {
+        Err(tus_response(StatusCode::PRECONDITION_FAILED)
+            .insert_header(("Tus-Version", TUS_VERSION))
+            .json(json!({
+                "status": "fail",
+                "message": format!("Unsupported tus version; expected Tus-Resumable: {}", TUS_VERSION)
+            })))
+    }
+}
+
+fn staged_path(staging_dir: &std::path::Path, id: Uuid) -> PathBuf {
+    staging_dir.join(format!("tus_{}", id))
+}
+
+/// Interpreta o cabeçalho Upload-Metadata: pares `chave valor-base64` separados por vírgula.
+fn parse_metadata(value: &str) -> Result<Vec<(String, Option<String>)>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, ' ');
+            let key = parts.next().unwrap_or_default().to_string();
+            match parts.next().map(str::trim).filter(|encoded| !encoded.is_empty()) {
+                None => Ok((key, None)),
+                Some(encoded) => {
+                    let decoded = STANDARD
+                        .decode(encoded)
+                        .map_err(|_| format!("Upload-Metadata value for {} is not valid base64", key))?;
+                    let decoded = String::from_utf8(decoded)
+                        .map_err(|_| format!("Upload-Metadata value for {} is not valid UTF-8", key))?;
+                    Ok((key, Some(decoded)))
+                }
+            }
+        })
+        .collect()
+}
+
+async fn find_upload(data: &AppState, id: Uuid) -> Result<UploadModel, HttpResponse> {
+    match sqlx::query_as!(UploadModel, "SELECT * FROM uploads WHERE id = $1", id)
+        .fetch_optional(&data.db)
+        .await
+    {
+        Ok(Some(upload)) => Ok(upload),
+        Ok(None) => Err(tus_error(StatusCode::NOT_FOUND, format!("Upload {} not found", id))),
+        Err(error) => Err(tus_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get upload: {:?}", error),
+        )),
+    }
+}
+
+// Descoberta de versão e extensões suportadas
+#[options("/uploads")]
+pub async fn upload_options() -> impl Responder {
+    tus_response(StatusCode::NO_CONTENT)
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", TUS_EXTENSIONS))
+        .insert_header(("Tus-Max-Size", MAX_UPLOAD_LENGTH.to_string()))
+        .finish()
+}
+
+// Extensão creation: reserva um upload com tamanho e metadados conhecidos
+#[post("/uploads")]
+pub async fn create_upload(req: HttpRequest, data: Data<AppState>) -> impl Responder {
+    if let Err(response) = check_version(&req) {
+        return response;
+    }
+
+    let upload_length = match header_str(&req, "Upload-Length").map(|value| value.parse::<i64>()) {
+        Some(Ok(length)) if length > 0 => length,
+        Some(Ok(0)) => return tus_error(StatusCode::BAD_REQUEST, "Uploaded file is empty"),
+        _ => return tus_error(StatusCode::BAD_REQUEST, "Upload-Length must be a positive integer"),
+    };
+    if upload_length > MAX_UPLOAD_LENGTH {
+        return tus_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Upload-Length exceeds Tus-Max-Size of {} bytes", MAX_UPLOAD_LENGTH),
+        );
+    }
+
+    let metadata = match parse_metadata(header_str(&req, "Upload-Metadata").unwrap_or_default()) {
+        Ok(metadata) => metadata,
+        Err(message) => return tus_error(StatusCode::BAD_REQUEST, message),
+    };
+    let value_of = |key: &str| {
+        metadata
+            .iter()
+            .find(|(name, _)| name == key)
+            .and_then(|(_, value)| value.clone())
+    };
+
+    // Mesma validação do POST /api/documents, feita antes de receber os bytes
+    let body = match upload::document_fields(
+        value_of("user_id"),
+        value_of("doc_type"),
+        value_of("metadata"),
+        value_of("expires_at"),
+    ) {
+        Ok(body) => body,
+        Err(error) => return tus_error(StatusCode::BAD_REQUEST, error.to_string()),
+    };
+    let (doc_type, metadata) = match doc_types::check(&data.db, &body.doc_type, &body.metadata).await {
+        Ok(checked) => checked,
+        Err(error) => return upload_error(error),
+    };
+    // A cota é conferida de novo ao final; aqui só evita receber o que não vai caber
+    match quotas::usage(&data.db, &data.quota_defaults, body.user_id).await {
+        Ok(usage) => {
+            if let Some(exceeded) = quotas::exceeded(usage, upload_length, 1) {
+                return upload_error(UploadError::QuotaExceeded(exceeded));
+            }
+        }
+        Err(error) => return upload_error(UploadError::Database(error)),
+    }
+    let original_filename = match value_of("filename")
+        .map(|name| upload::sanitize_filename(&name))
+        .filter(|name| !name.is_empty())
+    {
+        Some(name) => name,
+        None => return tus_error(StatusCode::BAD_REQUEST, "Upload-Metadata must include a filename"),
+    };
+    let mime_type = value_of("filetype").unwrap_or_else(|| DEFAULT_MIME_TYPE.to_string());
+
+    let created = sqlx::query_as!(
+        UploadModel,
+        r#"INSERT INTO uploads (user_id, doc_type, original_filename, mime_type, upload_length, metadata, expires_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7)
+           RETURNING *"#,
+        body.user_id,
+        doc_type,
+        original_filename,
+        mime_type,
+        upload_length,
+        metadata,
+        body.expires_at
+    )
+    .fetch_one(&data.db)
+    .await;
+
+    let upload = match created {
+        Ok(upload) => upload,
+        Err(error) => {
+            return tus_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to create upload: {:?}", error),
+            );
+        }
+    };
+
+    if let Err(error) = tokio::fs::File::create(staged_path(&data.staging_dir, upload.id)).await {
+        let _ = sqlx::query!("DELETE FROM uploads WHERE id = $1", upload.id)
+            .execute(&data.db)
+            .await;
+        return tus_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to create upload: {}", error),
+        );
+    }
+
+    tus_response(StatusCode::CREATED)
+        .insert_header((header::LOCATION, format!("/api/uploads/{}", upload.id)))
+        .finish()
+}
+
+// Quanto do upload o servidor já recebeu
+#[head("/uploads/{id}")]
+pub async fn get_upload_offset(path: Path<Uuid>, req: HttpRequest, data: Data<AppState>) -> impl Responder {
+    if let Err(response) = check_version(&req) {
+        return response;
+    }
+
+    let upload = match find_upload(&data, path.into_inner()).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+
+    let mut response = tus_response(StatusCode::OK);
+    response
+        .insert_header(("Upload-Offset", upload.upload_offset.to_string()))
+        .insert_header(("Upload-Length", upload.upload_length.to_string()))
+        .insert_header((header::CACHE_CONTROL, "no-store"));
+    if let Some(document_id) = upload.document_id {
+        response.insert_header(("Upload-Document-Id", document_id.to_string()));
+    }
+    response.finish()
+}
+
+// Anexa bytes a partir de Upload-Offset; ao completar, o upload vira um documento
+#[patch("/uploads/{id}")]
+pub async fn append_upload(
+    path: Path<Uuid>,
+    req: HttpRequest,
+    mut payload: Payload,
+    data: Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = check_version(&req) {
+        return response;
+    }
+    if header_str(&req, header::CONTENT_TYPE.as_str()) != Some(OFFSET_OCTET_STREAM) {
+        return tus_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Content-Type must be {}", OFFSET_OCTET_STREAM),
+        );
+    }
+    let client_offset = match header_str(&req, "Upload-Offset").map(|value| value.parse::<i64>()) {
+        Some(Ok(offset)) if offset >= 0 => offset,
+        _ => return tus_error(StatusCode::BAD_REQUEST, "Upload-Offset must be a non-negative integer"),
+    };
+
+    let upload_id = path.into_inner();
+    let _lock = match data.upload_locks.try_lock(upload_id) {
+        Some(lock) => lock,
+        None => return tus_error(StatusCode::LOCKED, "Upload is already receiving data"),
+    };
+
+    let upload = match find_upload(&data, upload_id).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+    if upload.upload_offset != client_offset {
+        return tus_response(StatusCode::CONFLICT)
+            .insert_header(("Upload-Offset", upload.upload_offset.to_string()))
+            .json(json!({
+                "status": "fail",
+                "message": format!("Upload-Offset {} does not match current offset {}", client_offset, upload.upload_offset)
+            }));
+    }
+
+    let staged = staged_path(&data.staging_dir, upload.id);
+    let mut offset = upload.upload_offset;
+
+    if offset < upload.upload_length {
+        let mut file = match OpenOptions::new().write(true).open(&staged).await {
+            Ok(file) => file,
+            Err(error) => {
+                return tus_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to open upload: {}", error),
+                );
+            }
+        };
+
+        // Descarta bytes de um PATCH anterior que não chegaram a ser confirmados
+        let prepared = async {
+            file.set_len(offset as u64).await?;
+            file.seek(std::io::SeekFrom::Start(offset as u64)).await
+        }
+        .await;
+        if let Err(error) = prepared {
+            return tus_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to prepare upload: {}", error),
+            );
+        }
+
+        let mut too_long = false;
+        while let Some(chunk) = payload.next().await {
+            // Conexão caiu: guarda o que chegou para o cliente retomar depois
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(error) => {
+                    log::info!("Upload {} interrupted at offset {}: {}", upload.id, offset, error);
+                    break;
+                }
+            };
+            if offset + chunk.len() as i64 > upload.upload_length {
+                too_long = true;
+                break;
+            }
+            if let Err(error) = file.write_all(&chunk).await {
+                return tus_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to write upload: {}", error),
+                );
+            }
+            offset += chunk.len() as i64;
+        }
+
+        if let Err(error) = file.flush().await {
+            return tus_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to write upload: {}", error),
+            );
+        }
+
+        if let Err(error) = sqlx::query!(
+            "UPDATE uploads SET upload_offset = $1 WHERE id = $2",
+            offset,
+            upload.id
+        )
+        .execute(&data.db)
+        .await
+        {
+            return tus_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to update upload: {:?}", error),
+            );
+        }
+
+        if too_long {
+            return tus_response(StatusCode::BAD_REQUEST)
+                .insert_header(("Upload-Offset", offset.to_string()))
+                .json(json!({
+                    "status": "fail",
+                    "message": "Request body exceeds Upload-Length"
+                }));
+        }
+    }
+
+    let mut response = tus_response(StatusCode::NO_CONTENT);
+    response.insert_header(("Upload-Offset", offset.to_string()));
+
+    if offset < upload.upload_length {
+        return response.finish();
+    }
+
+    if let Some(document_id) = upload.document_id {
+        return response
+            .insert_header(("Upload-Document-Id", document_id.to_string()))
+            .finish();
+    }
+
+    match finish_upload(&data, &upload, staged).await {
+        Ok(document_id) => response
+            .insert_header(("Upload-Document-Id", document_id.to_string()))
+            .finish(),
+        Err(response) => response,
+    }
+}
+
+/// Transforma um upload completo em documento, como o POST /api/documents faria.
+///
+/// Uploads recusados pela validação são descartados; se o armazenamento ou o
+/// banco falharem, o upload volta ao offset 0 para ser reenviado.
+async fn finish_upload(data: &AppState, upload: &UploadModel, staged: PathBuf) -> Result<Uuid, HttpResponse> {
+    let (size_bytes, sha256) = match upload::hash_file(&staged).await {
+        Ok(hashed) => hashed,
+        Err(error) => {
+            return Err(tus_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read upload: {}", error),
+            ));
+        }
+    };
+
+    // O schema do tipo pode ter mudado desde a criação; store_document valida de novo
+    let body = CreateDocumentSchema {
+        user_id: upload.user_id,
+        doc_type: upload.doc_type.clone(),
+        metadata: upload.metadata.clone(),
+        expires_at: upload.expires_at,
+    };
+    let mut file = StagedFile {
+        path: staged,
+        original_filename: upload.original_filename.clone(),
+        size_bytes,
+        mime_type: upload.mime_type.clone(),
+        sha256,
+        image_metadata: None,
+        perceptual_hash: None,
+    };
+
+    match upload::store_document(data, &body, &mut file).await {
+        Ok(document) => {
+            if let Err(error) = sqlx::query!(
+                "UPDATE uploads SET document_id = $1 WHERE id = $2",
+                document.id,
+                upload.id
+            )
+            .execute(&data.db)
+            .await
+            {
+                log::warn!("Failed to link upload {} to document {}: {:?}", upload.id, document.id, error);
+            }
+            Ok(document.id)
+        }
+        Err(error) if !error.status().is_server_error() => {
+            // store_document já removeu o arquivo de staging
+            let _ = sqlx::query!("DELETE FROM uploads WHERE id = $1", upload.id)
+                .execute(&data.db)
+                .await;
+            Err(upload_error(error))
+        }
+        Err(error) => {
+            // O arquivo de staging já foi descartado; o cliente recomeça do zero
+            let reset = async {
+                tokio::fs::File::create(staged_path(&data.staging_dir, upload.id)).await?;
+                sqlx::query!("UPDATE uploads SET upload_offset = 0 WHERE id = $1", upload.id)
+                    .execute(&data.db)
+                    .await
+                    .map_err(std::io::Error::other)
+            }
+            .await;
+            if let Err(reset_error) = reset {
+                log::warn!("Failed to reset upload {}: {}", upload.id, reset_error);
+            }
+            Err(tus_response(StatusCode::INTERNAL_SERVER_ERROR)
+                .insert_header(("Upload-Offset", "0"))
+                .json(json!({
+                    "status": "error",
+                    "message": error.to_string()
+                })))
+        }
+    }
+}
+
+// Extensão termination: o cliente desiste do upload
+#[delete("/uploads/{id}")]
+pub async fn delete_upload(path: Path<Uuid>, req: HttpRequest, data: Data<AppState>) -> impl Responder {
+    if let Err(response) = check_version(&req) {
+        return response;
+    }
+
+    let upload_id = path.into_inner();
+    let _lock = match data.upload_locks.try_lock(upload_id) {
+        Some(lock) => lock,
+        None => return tus_error(StatusCode::LOCKED, "Upload is receiving data"),
+    };
+
+    match sqlx::query!("DELETE FROM uploads WHERE id = $1 RETURNING id", upload_id)
+        .fetch_optional(&data.db)
+        .await
+    {
+        Ok(Some(_)) => {
+            let staged = staged_path(&data.staging_dir, upload_id);
+            if tokio::fs::try_exists(&staged).await.unwrap_or(false) {
+                upload::discard(&staged).await;
+            }
+            tus_response(StatusCode::NO_CONTENT).finish()
+        }
+        Ok(None) => tus_error(StatusCode::NOT_FOUND, format!("Upload {} not found", upload_id)),
+        Err(error) => tus_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to delete upload: {:?}", error),
+        ),
+    }
+}
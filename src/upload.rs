@@ -0,0 +1,687 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use actix_multipart::{Field, Multipart};
+use actix_web::{http::StatusCode, HttpResponse};
+use chrono::NaiveDate;
+use futures_util::StreamExt;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::{Postgres, Transaction};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+use uuid::Uuid;
+
+use crate::{
+    blobs, doc_types,
+    encryption::{self, DataKey, WrappedKey},
+    expiry, image_metadata, model::DocumentModel, phash, quotas, scanner, schema::CreateDocumentSchema, sniff, thumbnails,
+    AppState,
+};
+
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+// Limite para os campos de texto do formulário (user_id, doc_type, metadata)
+const MAX_TEXT_FIELD_BYTES: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum UploadError {
+    Invalid(String),
+    UnsupportedMediaType(String),
+    InvalidMetadata { doc_type: String, errors: Vec<String> },
+    QuotaExceeded(quotas::Exceeded),
+    Infected(String),
+    ScanFailed(String),
+    Io(std::io::Error),
+    Database(sqlx::Error),
+}
+
+impl UploadError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            UploadError::Invalid(_) | UploadError::InvalidMetadata { .. } => StatusCode::BAD_REQUEST,
+            UploadError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            UploadError::QuotaExceeded(exceeded) => exceeded.status(),
+            UploadError::Infected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            UploadError::ScanFailed(_) => StatusCode::SERVICE_UNAVAILABLE,
+            UploadError::Io(_) | UploadError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Resposta JSON no formato usado pelos demais endpoints.
+    pub fn error_response(&self) -> HttpResponse {
+        let status = if self.status().is_server_error() { "error" } else { "fail" };
+        if let UploadError::InvalidMetadata { errors, .. } = self {
+            return HttpResponse::build(self.status()).json(json!({
+                "status": status,
+                "message": self.to_string(),
+                "errors": errors
+            }));
+        }
+        if let UploadError::QuotaExceeded(exceeded) = self {
+            return HttpResponse::build(self.status()).json(json!({
+                "status": status,
+                "message": self.to_string(),
+                "usage": exceeded.usage
+            }));
+        }
+        HttpResponse::build(self.status()).json(json!({
+            "status": status,
+            "message": self.to_string()
+        }))
+    }
+}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadError::Invalid(message) => write!(f, "{}", message),
+            UploadError::UnsupportedMediaType(message) => write!(f, "{}", message),
+            UploadError::InvalidMetadata { doc_type, .. } => {
+                write!(f, "metadata does not match the schema of doc_type {}", doc_type)
+            }
+            UploadError::QuotaExceeded(exceeded) => write!(f, "{}", exceeded),
+            UploadError::Infected(signature) => write!(f, "Uploaded image is infected: {}", signature),
+            UploadError::ScanFailed(message) => write!(f, "Failed to scan uploaded image: {}", message),
+            UploadError::Io(error) => write!(f, "Failed to store document file: {}", error),
+            UploadError::Database(error) => write!(f, "Failed to create document: {:?}", error),
+        }
+    }
+}
+
+impl From<std::io::Error> for UploadError {
+    fn from(error: std::io::Error) -> Self {
+        UploadError::Io(error)
+    }
+}
+
+impl From<actix_multipart::MultipartError> for UploadError {
+    fn from(error: actix_multipart::MultipartError) -> Self {
+        UploadError::Invalid(format!("Invalid multipart body: {}", error))
+    }
+}
+
+/// Arquivo recebido e gravado na área de staging, ainda fora do armazenamento.
+#[derive(Debug)]
+pub struct StagedFile {
+    pub path: PathBuf,
+    pub original_filename: String,
+    pub size_bytes: i64,
+    pub mime_type: String,
+    /// SHA-256 do conteúdo que será guardado: nas imagens, o de depois da
+    /// remoção do EXIF, não o do arquivo enviado.
+    pub sha256: String,
+    /// Campos do EXIF, preenchidos na verificação do conteúdo.
+    pub image_metadata: Option<serde_json::Value>,
+    /// dHash das imagens, também calculado na verificação.
+    pub perceptual_hash: Option<i64>,
+}
+
+/// Campos de texto e o arquivo (campo `file`) de um corpo multipart/form-data.
+#[derive(Debug, Default)]
+pub struct UploadForm {
+    pub fields: HashMap<String, String>,
+    pub file: Option<StagedFile>,
+}
+
+/// Lê um corpo multipart/form-data com um campo `file` e campos de texto.
+///
+/// O campo `file` é gravado em `staging_dir` à medida que chega, calculando o
+/// SHA-256 no caminho, de modo que o arquivo nunca fica inteiro em memória.
+pub async fn read_form(mut payload: Multipart, staging_dir: &Path) -> Result<UploadForm, UploadError> {
+    let mut form = UploadForm::default();
+
+    let result = async {
+        while let Some(field) = payload.next().await {
+            let field = field?;
+            match field.name().map(str::to_string) {
+                Some(name) if name == "file" => {
+                    if form.file.is_some() {
+                        return Err(UploadError::Invalid("Only one file per document is allowed".to_string()));
+                    }
+                    form.file = Some(write_file_field(field, staging_dir).await?);
+                }
+                Some(name) => {
+                    let value = read_text_field(field).await?;
+                    form.fields.insert(name, value);
+                }
+                None => drain_field(field).await?,
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    match result {
+        Ok(()) => Ok(form),
+        Err(error) => {
+            if let Some(file) = form.file {
+                discard(&file.path).await;
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Lê um corpo multipart/form-data com os campos `user_id`, `doc_type`,
+/// `metadata` (opcional, um objeto JSON), `expires_at` (opcional,
+/// YYYY-MM-DD) e `file`.
+pub async fn read_document_upload(
+    payload: Multipart,
+    staging_dir: &Path,
+) -> Result<(CreateDocumentSchema, StagedFile), UploadError> {
+    let mut form = read_form(payload, staging_dir).await?;
+    let outcome = document_fields(
+        form.fields.remove("user_id"),
+        form.fields.remove("doc_type"),
+        form.fields.remove("metadata"),
+        form.fields.remove("expires_at"),
+    );
+
+    match (outcome, form.file) {
+        (Ok(body), Some(file)) => Ok((body, file)),
+        (Ok(_), None) => Err(UploadError::Invalid("Missing field: file".to_string())),
+        (Err(error), file) => {
+            if let Some(file) = file {
+                discard(&file.path).await;
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Valida os metadados obrigatórios de um novo documento.
+pub fn document_fields(
+    user_id: Option<String>,
+    doc_type: Option<String>,
+    metadata: Option<String>,
+    expires_at: Option<String>,
+) -> Result<CreateDocumentSchema, UploadError> {
+    let user_id = user_id
+        .ok_or_else(|| UploadError::Invalid("Missing field: user_id".to_string()))?;
+    let user_id = Uuid::parse_str(user_id.trim())
+        .map_err(|_| UploadError::Invalid("Field user_id must be a UUID".to_string()))?;
+    let doc_type = doc_type
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| UploadError::Invalid("Missing field: doc_type".to_string()))?;
+    let metadata = match metadata.as_deref().map(str::trim) {
+        Some(value) if !value.is_empty() => serde_json::from_str(value)
+            .map_err(|error| UploadError::Invalid(format!("Field metadata must be JSON: {}", error)))?,
+        _ => crate::schema::empty_metadata(),
+    };
+    let expires_at = match expires_at.as_deref().map(str::trim) {
+        Some(value) if !value.is_empty() => Some(
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map_err(|_| UploadError::Invalid("Field expires_at must be a date (YYYY-MM-DD)".to_string()))?,
+        ),
+        _ => None,
+    };
+    Ok(CreateDocumentSchema { user_id, doc_type, metadata, expires_at })
+}
+
+/// Move o arquivo de staging para o armazenamento e cria a linha em
+/// `documents`, junto com a revisão 1.
+///
+/// O doc_type é trocado pela chave canônica e o metadata precisa seguir o
+/// schema do tipo. O tipo do arquivo é detectado pelo conteúdo e precisa ser
+/// aceito para o doc_type e caber na cota do usuário. Em caso de erro nada fica
+/// para trás: nem o arquivo de staging, nem o conteúdo no armazenamento.
+pub async fn store_document(
+    data: &AppState,
+    body: &CreateDocumentSchema,
+    file: &mut StagedFile,
+) -> Result<DocumentModel, UploadError> {
+    store(data, body, file, &[]).await
+}
+
+/// Como `store_document`, para um documento gerado a partir de outros, que
+/// ficam ligados a ele em `document_sources`, na ordem dada.
+pub async fn store_derived_document(
+    data: &AppState,
+    body: &CreateDocumentSchema,
+    file: &mut StagedFile,
+    sources: &[Uuid],
+) -> Result<DocumentModel, UploadError> {
+    store(data, body, file, sources).await
+}
+
+async fn store(
+    data: &AppState,
+    body: &CreateDocumentSchema,
+    file: &mut StagedFile,
+    sources: &[Uuid],
+) -> Result<DocumentModel, UploadError> {
+    let (doc_type, metadata) = match doc_types::check(&data.db, &body.doc_type, &body.metadata).await {
+        Ok(checked) => checked,
+        Err(error) => {
+            discard(&file.path).await;
+            return Err(error);
+        }
+    };
+    let expires_at = match expiry::from_metadata(&metadata) {
+        Ok(from_metadata) => body.expires_at.or(from_metadata),
+        Err(error) => {
+            discard(&file.path).await;
+            return Err(UploadError::InvalidMetadata { doc_type, errors: vec![error] });
+        }
+    };
+    check_content(data, &doc_type, file).await?;
+
+    let stored = async {
+        let mut tx = data.db.begin().await.map_err(UploadError::Database)?;
+        check_quota(&mut tx, data, body.user_id, file, 1).await?;
+        let (key, written, wrapped) = store_content(&mut tx, data, file).await?;
+
+        let inserted = async {
+            let query = r#"
+                INSERT INTO documents (user_id, doc_type, filename, original_filename, size_bytes, mime_type, sha256, image_metadata, metadata, expires_at, master_key_id, wrapped_key, perceptual_hash)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+                RETURNING *
+            "#;
+
+            let document = sqlx::query_as::<_, DocumentModel>(query)
+                .bind(body.user_id)
+                .bind(&doc_type)
+                .bind(&key)
+                .bind(&file.original_filename)
+                .bind(file.size_bytes)
+                .bind(&file.mime_type)
+                .bind(&file.sha256)
+                .bind(&file.image_metadata)
+                .bind(&metadata)
+                .bind(expires_at)
+                .bind(&wrapped.master_key_id)
+                .bind(&wrapped.wrapped_key)
+                .bind(file.perceptual_hash)
+                .fetch_one(&mut *tx)
+                .await?;
+
+            insert_revision(&mut tx, &document).await?;
+            for (position, source) in sources.iter().enumerate() {
+                sqlx::query!(
+                    "INSERT INTO document_sources (document_id, position, source_document_id) VALUES ($1, $2, $3)",
+                    document.id,
+                    position as i32 + 1,
+                    source
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            Ok(document)
+        }
+        .await;
+
+        commit(data, tx, &key, written, file, inserted).await
+    }
+    .await;
+
+    discard_unused(file).await;
+    if let Ok(document) = &stored {
+        scanner::spawn_scan(data, &document.filename);
+    }
+    stored
+}
+
+/// Grava um novo conteúdo para um documento existente, como a próxima revisão.
+pub async fn store_revision(
+    data: &AppState,
+    document: &DocumentModel,
+    file: &mut StagedFile,
+) -> Result<DocumentModel, UploadError> {
+    check_content(data, &document.doc_type, file).await?;
+
+    let stored = async {
+        let mut tx = data.db.begin().await.map_err(UploadError::Database)?;
+        check_quota(&mut tx, data, document.user_id, file, 0).await?;
+        let (key, written, wrapped) = store_content(&mut tx, data, file).await?;
+
+        let updated = async {
+            // A trava na linha do documento serializa revisões concorrentes
+            let updated = sqlx::query_as!(
+                DocumentModel,
+                r#"UPDATE documents
+                   SET filename = $1, original_filename = $2, size_bytes = $3, mime_type = $4,
+                       sha256 = $5, image_metadata = $6, revision = revision + 1,
+                       scan_status = 'pending', scan_detail = NULL, scanned_at = NULL,
+                       review_status = 'submitted', reviewer_id = NULL, decided_at = NULL, rejection_reason = NULL,
+                       master_key_id = $7, wrapped_key = $8, perceptual_hash = $9
+                   WHERE id = $10
+                   RETURNING *"#,
+                key,
+                file.original_filename,
+                file.size_bytes,
+                file.mime_type,
+                file.sha256,
+                file.image_metadata,
+                Some(&wrapped.master_key_id),
+                Some(&wrapped.wrapped_key),
+                file.perceptual_hash,
+                document.id
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+
+            insert_revision(&mut tx, &updated).await?;
+            Ok(updated)
+        }
+        .await;
+
+        commit(data, tx, &key, written, file, updated).await
+    }
+    .await;
+
+    discard_unused(file).await;
+    if let Ok(document) = &stored {
+        scanner::spawn_scan(data, &document.filename);
+    }
+    stored
+}
+
+/// Registra o conteúdo atual de `document` em `document_revisions`.
+pub async fn insert_revision(
+    tx: &mut Transaction<'_, Postgres>,
+    document: &DocumentModel,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO document_revisions
+               (document_id, revision, filename, original_filename, size_bytes, mime_type, sha256, image_metadata, scan_status,
+                master_key_id, wrapped_key, perceptual_hash)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)"#,
+        document.id,
+        document.revision,
+        document.filename,
+        document.original_filename,
+        document.size_bytes,
+        document.mime_type,
+        document.sha256,
+        document.image_metadata,
+        document.scan_status,
+        document.master_key_id,
+        document.wrapped_key,
+        document.perceptual_hash
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+// Confere o tamanho e o tipo do arquivo; o tipo detectado substitui o declarado
+async fn check_content(data: &AppState, doc_type: &str, file: &mut StagedFile) -> Result<(), UploadError> {
+    if file.size_bytes == 0 {
+        discard(&file.path).await;
+        return Err(UploadError::Invalid("Uploaded file is empty".to_string()));
+    }
+
+    let head = match sniff::read_head(&file.path).await {
+        Ok(head) => head,
+        Err(error) => {
+            discard(&file.path).await;
+            return Err(UploadError::Io(error));
+        }
+    };
+    match data
+        .mime_policy
+        .check(doc_type, &file.mime_type, &file.original_filename, &head)
+    {
+        Ok(sniffed) => file.mime_type = sniffed,
+        Err(message) => {
+            discard(&file.path).await;
+            return Err(UploadError::UnsupportedMediaType(message));
+        }
+    }
+
+    // Nenhum decodificador abre uma imagem antes do antivírus; a que não passa
+    // não é guardada, já que a cópia guardada seria a regravada
+    if image_metadata::is_decoded(&file.mime_type) {
+        let rejected = match data.scanner.scan_file(&file.path).await {
+            Ok(scanner::Verdict::Clean) => None,
+            Ok(scanner::Verdict::Infected(signature)) => Some(UploadError::Infected(signature)),
+            Ok(scanner::Verdict::Error(message)) => Some(UploadError::ScanFailed(message)),
+            Err(error) => Some(UploadError::ScanFailed(format!("Failed to reach clamd: {}", error))),
+        };
+        if let Some(error) = rejected {
+            discard(&file.path).await;
+            return Err(error);
+        }
+    }
+
+    // Fotos saem sem EXIF (e sem GPS), o que muda o conteúdo e o hash
+    let sanitized = async {
+        let sanitized = image_metadata::sanitize(&file.path, &file.mime_type).await?;
+        if sanitized.rewritten {
+            (file.size_bytes, file.sha256) = hash_file(&file.path).await?;
+        }
+        file.image_metadata = sanitized.metadata;
+        // Já sem EXIF e na orientação certa, como a foto será vista
+        file.perceptual_hash = phash::compute(&file.path, &file.mime_type).await;
+        Ok::<_, std::io::Error>(())
+    }
+    .await;
+
+    if let Err(error) = sanitized {
+        discard(&file.path).await;
+        return Err(match error.kind() {
+            std::io::ErrorKind::InvalidData => UploadError::Invalid(format!("Invalid image file: {}", error)),
+            _ => UploadError::Io(error),
+        });
+    }
+    Ok(())
+}
+
+// Recusa o envio que passaria da cota do usuário, antes de gravar o conteúdo
+async fn check_quota(
+    tx: &mut Transaction<'_, Postgres>,
+    data: &AppState,
+    user_id: Uuid,
+    file: &StagedFile,
+    new_documents: i64,
+) -> Result<(), UploadError> {
+    match quotas::check(tx, &data.quota_defaults, user_id, &file.sha256, file.size_bytes, new_documents).await {
+        Ok(None) => Ok(()),
+        Ok(Some(exceeded)) => Err(UploadError::QuotaExceeded(exceeded)),
+        Err(error) => Err(UploadError::Database(error)),
+    }
+}
+
+// O que `store_content` gravou no armazenamento, para desfazer se a transação falhar
+enum Written {
+    Nothing,
+    New,
+    Encrypted,
+}
+
+// O conteúdo é guardado uma vez só, com o SHA-256 como chave, e cifrado com
+// uma chave de dados nova; devolve a chave, o que foi gravado e a chave de
+// dados embrulhada (a do conteúdo já existente, se for o caso). Um conteúdo
+// existente gravado em claro, de antes da criptografia, é cifrado agora.
+async fn store_content(
+    tx: &mut Transaction<'_, Postgres>,
+    data: &AppState,
+    file: &StagedFile,
+) -> Result<(String, Written, WrappedKey), UploadError> {
+    let key = file.sha256.clone();
+
+    let is_new = blobs::acquire(tx, &key, &file.sha256, file.size_bytes)
+        .await
+        .map_err(UploadError::Database)?;
+    if !is_new {
+        let wrapped = encryption::find_wrapped_key(&mut *tx, &key)
+            .await
+            .map_err(UploadError::Database)?;
+        return match wrapped {
+            Some(wrapped) => Ok((key, Written::Nothing, wrapped)),
+            None => {
+                let encrypted = encryption::encrypt_stored(
+                    tx,
+                    data.storage.as_ref(),
+                    &data.keyring,
+                    &key,
+                    &file.path,
+                    &file.mime_type,
+                )
+                .await?;
+                Ok((key, Written::Encrypted, encrypted))
+            }
+        };
+    }
+
+    let data_key = DataKey::generate()?;
+    let wrapped = data.keyring.wrap_key(&data_key)?;
+    thumbnails::generate(data.storage.as_ref(), &key, &file.path, &file.mime_type, &data_key).await;
+    if let Err(error) = encryption::put_file(data.storage.as_ref(), &key, &file.path, &data_key).await {
+        thumbnails::remove(data.storage.as_ref(), &key).await;
+        return Err(error.into());
+    }
+
+    Ok((key, Written::New, wrapped))
+}
+
+// Confirma a transação; se algo falhou, apaga o conteúdo que acabou de ser
+// gravado ou devolve em claro o que foi cifrado
+async fn commit<T>(
+    data: &AppState,
+    tx: Transaction<'_, Postgres>,
+    key: &str,
+    written: Written,
+    file: &StagedFile,
+    result: Result<T, sqlx::Error>,
+) -> Result<T, UploadError> {
+    let committed = match result {
+        Ok(value) => tx.commit().await.map(|_| value),
+        Err(error) => {
+            // Desfaz já, para soltar a trava do conteúdo antes de mexer nele
+            drop(tx);
+            Err(error)
+        }
+    };
+
+    if committed.is_err() {
+        match written {
+            Written::Nothing => {}
+            Written::New => blobs::remove_unused(&data.db, data.storage.as_ref(), &[key.to_string()]).await,
+            Written::Encrypted => encryption::restore_plaintext(&data.db, data.storage.as_ref(), key, &file.path).await,
+        }
+    }
+    committed.map_err(UploadError::Database)
+}
+
+// O armazenamento recebe só a cópia cifrada; o arquivo de staging, em claro, sai daqui
+async fn discard_unused(file: &StagedFile) {
+    if fs::try_exists(&file.path).await.unwrap_or(false) {
+        discard(&file.path).await;
+    }
+}
+
+/// Calcula tamanho e SHA-256 de um arquivo em disco, lendo-o em blocos.
+pub async fn hash_file(path: &Path) -> std::io::Result<(i64, String)> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut size_bytes: i64 = 0;
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size_bytes += read as i64;
+    }
+
+    Ok((size_bytes, hex::encode(hasher.finalize())))
+}
+
+/// Remove um arquivo de staging que não chegou ao armazenamento.
+pub async fn discard(path: &Path) {
+    if let Err(error) = fs::remove_file(path).await {
+        log::warn!("Failed to remove staged upload {}: {}", path.display(), error);
+    }
+}
+
+async fn read_text_field(mut field: Field) -> Result<String, UploadError> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk?;
+        if value.len() + chunk.len() > MAX_TEXT_FIELD_BYTES {
+            return Err(UploadError::Invalid("Form field is too large".to_string()));
+        }
+        value.extend_from_slice(&chunk);
+    }
+    String::from_utf8(value).map_err(|_| UploadError::Invalid("Form field is not valid UTF-8".to_string()))
+}
+
+async fn drain_field(mut field: Field) -> Result<(), UploadError> {
+    while let Some(chunk) = field.next().await {
+        chunk?;
+    }
+    Ok(())
+}
+
+async fn write_file_field(mut field: Field, staging_dir: &Path) -> Result<StagedFile, UploadError> {
+    let original_filename = field
+        .content_disposition()
+        .and_then(|cd| cd.get_filename())
+        .map(sanitize_filename)
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| UploadError::Invalid("Field file must include a filename".to_string()))?;
+    let mime_type = field
+        .content_type()
+        .map(|mime| mime.essence_str().to_string())
+        .unwrap_or_else(|| DEFAULT_MIME_TYPE.to_string());
+
+    let path = staging_dir.join(staged_filename(&original_filename));
+    let mut file = fs::File::create(&path).await?;
+
+    let mut hasher = Sha256::new();
+    let mut size_bytes: i64 = 0;
+
+    let written = async {
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk?;
+            hasher.update(&chunk);
+            size_bytes += chunk.len() as i64;
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok::<_, UploadError>(())
+    }
+    .await;
+
+    if let Err(error) = written {
+        drop(file);
+        discard(&path).await;
+        return Err(error);
+    }
+
+    Ok(StagedFile {
+        path,
+        original_filename,
+        size_bytes,
+        mime_type,
+        sha256: hex::encode(hasher.finalize()),
+        image_metadata: None,
+        perceptual_hash: None,
+    })
+}
+
+// Mantém só o nome base do arquivo enviado pelo cliente, sem caminhos
+pub fn sanitize_filename(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    base.chars().filter(|c| !c.is_control()).collect::<String>().trim().to_string()
+}
+
+/// Nome do arquivo na área de staging: document_{uuid} com a extensão original, se for simples.
+pub fn staged_filename(original_filename: &str) -> String {
+    let extension = Path::new(original_filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .filter(|ext| ext.len() <= 8 && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+        .map(|ext| format!(".{}", ext.to_ascii_lowercase()))
+        .unwrap_or_default();
+    format!("document_{}{}", Uuid::new_v4(), extension)
+}
@@ -0,0 +1,443 @@
+// Marca d'água aplicada na hora do download: a cópia entregue leva quem baixou,
+// quando e para quê, para que uma cópia vazada possa ser rastreada. O original
+// no armazenamento não muda. Imagens recebem o texto desenhado em faixas (com a
+// fonte de WATERMARK_FONT); PDFs, um texto na diagonal sobre cada página.
+use std::io;
+
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use actix_web::{http::header, HttpResponse};
+use chrono::{SecondsFormat, Utc};
+use futures_util::StreamExt;
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, ImageFormat, Rgba, RgbaImage};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use serde_json::json;
+
+use crate::{
+    download::{self, StoredContent},
+    encryption,
+    schema::ContentQuery,
+    AppState,
+};
+
+const DEFAULT_FONT: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+
+const DEFAULT_TEXT: &str = "{downloaded_by} | {timestamp} | {purpose}";
+
+// Conteúdo maior que isso não é marcado (tudo é processado em memória)
+const MAX_WATERMARK_BYTES: u64 = 50 * 1024 * 1024;
+
+const JPEG_QUALITY: u8 = 90;
+
+// Vermelho semitransparente, visível em fundo claro e escuro
+const INK: [u8; 3] = [200, 0, 0];
+const OPACITY: f32 = 0.4;
+
+/// Texto (modelo em WATERMARK_TEXT) e fonte (WATERMARK_FONT) das marcas d'água.
+pub struct Watermarker {
+    font: Option<FontArc>,
+    template: String,
+}
+
+impl Watermarker {
+    /// Sem WATERMARK_FONT, tenta a DejaVu Sans do sistema; se ela não existir,
+    /// só os PDFs podem ser marcados (com a Helvetica embutida nos leitores).
+    pub fn from_env() -> io::Result<Self> {
+        let font = match std::env::var("WATERMARK_FONT") {
+            Ok(path) => Some(load_font(&path)?),
+            Err(_) => match load_font(DEFAULT_FONT) {
+                Ok(font) => Some(font),
+                Err(error) => {
+                    log::warn!("Image watermarks are disabled: {}", error);
+                    None
+                }
+            },
+        };
+        let template = std::env::var("WATERMARK_TEXT")
+            .ok()
+            .filter(|template| !template.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_TEXT.to_string());
+        Ok(Watermarker { font, template })
+    }
+
+    /// Texto da marca: o modelo com {downloaded_by}, {purpose}, {timestamp} e {document_id}.
+    pub fn text(&self, downloaded_by: &str, purpose: &str, document_id: &str) -> String {
+        self.template
+            .replace("{downloaded_by}", downloaded_by)
+            .replace("{purpose}", purpose)
+            .replace("{timestamp}", &Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true))
+            .replace("{document_id}", document_id)
+    }
+}
+
+fn load_font(path: &str) -> io::Result<FontArc> {
+    let bytes = std::fs::read(path).map_err(|error| io::Error::new(error.kind(), format!("{}: {}", path, error)))?;
+    FontArc::try_from_vec(bytes)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, error)))
+}
+
+/// Serve uma cópia marcada do conteúdo, inteira (sem Range nem ETag, já que
+/// cada cópia é diferente) e fora de caches.
+///
+/// Exige `downloaded_by` e `purpose`; o download fica no log.
+pub async fn serve(data: &AppState, content: StoredContent<'_>, opts: &ContentQuery, document_id: &str) -> HttpResponse {
+    let (downloaded_by, purpose) = match (non_empty(&opts.downloaded_by), non_empty(&opts.purpose)) {
+        (Some(downloaded_by), Some(purpose)) => (downloaded_by, purpose),
+        _ => {
+            return HttpResponse::BadRequest().json(json!({
+                "status": "fail",
+                "message": "A watermarked download needs downloaded_by and purpose"
+            }));
+        }
+    };
+    if let Err(response) = download::check_scan(content.scan_status) {
+        return response;
+    }
+    let mime_type = content.mime_type.to_string();
+    if !is_supported(&mime_type) {
+        return HttpResponse::UnsupportedMediaType().json(json!({
+            "status": "fail",
+            "message": format!("Watermarks are not supported for {}", mime_type)
+        }));
+    }
+    if mime_type != "application/pdf" && data.watermarker.font.is_none() {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "status": "error",
+            "message": "Image watermarks need a font: set WATERMARK_FONT"
+        }));
+    }
+
+    let original = match read(data, &content).await {
+        Ok(original) => original,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return HttpResponse::NotFound().json(json!({
+                "status": "not found",
+                "message": format!("Document content not available: {}", error)
+            }));
+        }
+        Err(error) if error.kind() == io::ErrorKind::FileTooLarge => {
+            return HttpResponse::PayloadTooLarge().json(json!({
+                "status": "fail",
+                "message": error.to_string()
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to read document content: {}", error)
+            }));
+        }
+    };
+
+    let text = data.watermarker.text(downloaded_by, purpose, document_id);
+    let font = data.watermarker.font.clone();
+    let stamped = tokio::task::spawn_blocking(move || apply(&mime_type, &original, &text, font.as_ref()))
+        .await
+        .map_err(io::Error::other)
+        .and_then(|stamped| stamped);
+    let (bytes, mime_type) = match stamped {
+        Ok(stamped) => stamped,
+        Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+            return HttpResponse::UnprocessableEntity().json(json!({
+                "status": "fail",
+                "message": format!("Failed to watermark document content: {}", error)
+            }));
+        }
+        Err(error) => {
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": format!("Failed to watermark document content: {}", error)
+            }));
+        }
+    };
+
+    log::info!(
+        "Watermarked download of document {} by {} (purpose: {})",
+        document_id,
+        downloaded_by,
+        purpose
+    );
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, mime_type))
+        .insert_header(download::content_disposition(&filename_for(content.original_filename, mime_type)))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(bytes)
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|value| !value.is_empty())
+}
+
+fn is_supported(mime_type: &str) -> bool {
+    matches!(mime_type, "application/pdf" | "image/jpeg" | "image/png" | "image/webp" | "image/tiff")
+}
+
+async fn read(data: &AppState, content: &StoredContent<'_>) -> io::Result<Vec<u8>> {
+    let data_key = data.keyring.open(content.master_key_id, content.wrapped_key)?;
+    let storage = data.storage.as_ref();
+    let length = encryption::size(storage, content.key, data_key.as_ref()).await?;
+    if length > MAX_WATERMARK_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            format!("Document content is too large to watermark ({} bytes, at most {})", length, MAX_WATERMARK_BYTES),
+        ));
+    }
+
+    let mut body = encryption::get(storage, content.key, data_key.as_ref(), None).await?;
+    let mut bytes = Vec::with_capacity(length as usize);
+    while let Some(chunk) = body.next().await {
+        bytes.extend_from_slice(&chunk?);
+    }
+    Ok(bytes)
+}
+
+// JPEG continua JPEG; os demais formatos de imagem saem em PNG
+fn apply(mime_type: &str, original: &[u8], text: &str, font: Option<&FontArc>) -> io::Result<(Vec<u8>, &'static str)> {
+    let invalid = |error: String| io::Error::new(io::ErrorKind::InvalidData, error);
+    if mime_type == "application/pdf" {
+        return stamp_pdf(original, text).map(|bytes| (bytes, "application/pdf"));
+    }
+    let font = font.ok_or_else(|| io::Error::other("no font for image watermarks"))?;
+
+    let image = image::load_from_memory(original).map_err(|error| invalid(error.to_string()))?;
+    let has_alpha = image.color().has_alpha();
+    let mut canvas = image.to_rgba8();
+    stamp_image(&mut canvas, text, font);
+
+    let mut bytes = Vec::new();
+    if mime_type == "image/jpeg" {
+        let rgb = DynamicImage::ImageRgba8(canvas).to_rgb8();
+        JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY)
+            .encode_image(&rgb)
+            .map_err(io::Error::other)?;
+        return Ok((bytes, "image/jpeg"));
+    }
+    let output = if has_alpha {
+        DynamicImage::ImageRgba8(canvas)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8())
+    };
+    output
+        .write_to(&mut io::Cursor::new(&mut bytes), ImageFormat::Png)
+        .map_err(io::Error::other)?;
+    Ok((bytes, "image/png"))
+}
+
+// Mantém o nome original, trocando a extensão quando o formato muda
+fn filename_for(original_filename: &str, mime_type: &str) -> String {
+    let extension = match mime_type {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        _ => return original_filename.to_string(),
+    };
+    let path = std::path::Path::new(original_filename);
+    let current = path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+    match current.as_deref() {
+        Some("png") if extension == "png" => original_filename.to_string(),
+        Some("jpg" | "jpeg") if extension == "jpg" => original_filename.to_string(),
+        _ => format!(
+            "{}.{}",
+            path.file_stem().and_then(|stem| stem.to_str()).unwrap_or(original_filename),
+            extension
+        ),
+    }
+}
+
+// Quatro faixas com o texto, cada uma ocupando até 90% da largura
+fn stamp_image(canvas: &mut RgbaImage, text: &str, font: &FontArc) {
+    let (width, height) = canvas.dimensions();
+    let unscaled = PxScale::from(100.0);
+    let text_width = line_width(&font.as_scaled(unscaled), text).max(1.0);
+    let size = (100.0 * width as f32 * 0.9 / text_width).min(height as f32 / 8.0).max(8.0);
+    let scaled = font.as_scaled(PxScale::from(size));
+    let line = line_width(&scaled, text);
+    let left = ((width as f32 - line) / 2.0).max(0.0);
+
+    for band in 0..4 {
+        let baseline = height as f32 * (band as f32 * 2.0 + 1.0) / 8.0 + scaled.ascent() / 2.0;
+        let mut x = left;
+        let mut previous = None;
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                x += scaled.kern(previous, id);
+            }
+            previous = Some(id);
+            let glyph = id.with_scale_and_position(size, point(x, baseline));
+            x += scaled.h_advance(id);
+
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i64 + gx as i64;
+                let py = bounds.min.y as i64 + gy as i64;
+                if px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+                    return;
+                }
+                let pixel = canvas.get_pixel_mut(px as u32, py as u32);
+                *pixel = blend(*pixel, coverage * OPACITY);
+            });
+        }
+    }
+}
+
+fn line_width<F: Font, S: ScaleFont<F>>(font: &S, text: &str) -> f32 {
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+fn blend(pixel: Rgba<u8>, alpha: f32) -> Rgba<u8> {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let mix = |base: u8, ink: u8| (base as f32 * (1.0 - alpha) + ink as f32 * alpha).round() as u8;
+    Rgba([mix(pixel[0], INK[0]), mix(pixel[1], INK[1]), mix(pixel[2], INK[2]), pixel[3].max((alpha * 255.0) as u8)])
+}
+
+// Cada página ganha um fluxo de conteúdo no fim, com recursos próprios (fonte e
+// transparência). O conteúdo original fica entre q/Q, para que o estado
+// gráfico que ele deixar não desloque a marca.
+fn stamp_pdf(original: &[u8], text: &str) -> io::Result<Vec<u8>> {
+    let invalid = |error: String| io::Error::new(io::ErrorKind::InvalidData, error);
+    let mut document = Document::load_mem(original).map_err(|error| invalid(error.to_string()))?;
+    if document.is_encrypted() {
+        return Err(invalid("encrypted PDFs cannot be watermarked".to_string()));
+    }
+
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let state_id = document.add_object(dictionary! {
+        "Type" => "ExtGState",
+        "ca" => OPACITY,
+        "CA" => OPACITY,
+    });
+    let encoded = win_ansi(text);
+    let save_id = document.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+
+    for page_id in document.get_pages().into_values() {
+        let (width, height) = media_box(&document, page_id);
+        let mut resources = inherited_resources(&document, page_id);
+        add_resource(&document, &mut resources, b"Font", b"WatermarkFont", font_id);
+        add_resource(&document, &mut resources, b"ExtGState", b"WatermarkState", state_id);
+
+        // Helvetica tem em média meio em de largura por caractere
+        let diagonal = (width * width + height * height).sqrt();
+        let size = (diagonal * 0.8 / (encoded.len().max(1) as f32 * 0.5)).min(48.0);
+        // Do canto inferior esquerdo ao superior direito, centralizado
+        let (cos, sin) = (width / diagonal, height / diagonal);
+        let half = encoded.len() as f32 * size * 0.25;
+        let mut overlay = format!(
+            "Q\nq /WatermarkState gs {} {} {} rg BT /WatermarkFont {:.2} Tf {:.4} {:.4} {:.4} {:.4} {:.2} {:.2} Tm (",
+            INK[0] as f32 / 255.0,
+            INK[1] as f32 / 255.0,
+            INK[2] as f32 / 255.0,
+            size,
+            cos,
+            sin,
+            -sin,
+            cos,
+            width / 2.0 - half * cos,
+            height / 2.0 - half * sin
+        )
+        .into_bytes();
+        overlay.extend_from_slice(&encoded);
+        overlay.extend_from_slice(b") Tj ET Q\n");
+        let overlay_id = document.add_object(Stream::new(Dictionary::new(), overlay));
+
+        let page = document
+            .get_object(page_id)
+            .and_then(Object::as_dict)
+            .map_err(|error| invalid(error.to_string()))?;
+        let mut contents = vec![Object::Reference(save_id)];
+        match page.get(b"Contents") {
+            Ok(Object::Reference(id)) => contents.push(Object::Reference(*id)),
+            Ok(Object::Array(existing)) => contents.extend(existing.iter().cloned()),
+            _ => {}
+        }
+        contents.push(Object::Reference(overlay_id));
+
+        let page = document
+            .get_object_mut(page_id)
+            .and_then(Object::as_dict_mut)
+            .map_err(|error| invalid(error.to_string()))?;
+        page.set("Resources", resources);
+        page.set("Contents", contents);
+    }
+
+    let mut bytes = Vec::new();
+    document.save_to(&mut bytes).map_err(io::Error::other)?;
+    Ok(bytes)
+}
+
+// Largura e altura da página, herdadas da árvore de páginas se preciso (A4 na falta)
+fn media_box(document: &Document, page_id: ObjectId) -> (f32, f32) {
+    let found = inherited(document, page_id, b"MediaBox").and_then(|media_box| {
+        let values: Vec<f32> = media_box.as_array().ok()?.iter().filter_map(|value| value.as_float().ok()).collect();
+        match values[..] {
+            [x0, y0, x1, y1] => Some(((x1 - x0).abs(), (y1 - y0).abs())),
+            _ => None,
+        }
+    });
+    found.unwrap_or((595.0, 842.0))
+}
+
+// Os recursos da página (próprios ou herdados), copiados para poderem ganhar
+// entradas sem mexer nos compartilhados com outras páginas
+fn inherited_resources(document: &Document, page_id: ObjectId) -> Dictionary {
+    inherited(document, page_id, b"Resources")
+        .and_then(|resources| resources.as_dict().ok().cloned())
+        .unwrap_or_default()
+}
+
+fn inherited<'a>(document: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node = document.get_dictionary(page_id).ok()?;
+    // Limite contra ciclos na árvore de páginas
+    for _ in 0..32 {
+        if let Ok(value) = node.get(key) {
+            return document.dereference(value).ok().map(|(_, value)| value);
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = document.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+fn add_resource(document: &Document, resources: &mut Dictionary, kind: &[u8], name: &[u8], id: ObjectId) {
+    let mut entries = resources
+        .get(kind)
+        .ok()
+        .and_then(|entries| document.dereference(entries).ok())
+        .and_then(|(_, entries)| entries.as_dict().ok().cloned())
+        .unwrap_or_default();
+    entries.set(name.to_vec(), Object::Reference(id));
+    resources.set(kind.to_vec(), entries);
+}
+
+// Texto para a Helvetica padrão: Latin-1 passa, o resto vira '?'; parênteses e
+// barras invertidas são escapados para a string do PDF
+fn win_ansi(text: &str) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        let byte = match c as u32 {
+            0x20..=0x7e | 0xa0..=0xff => c as u32 as u8,
+            _ => b'?',
+        };
+        if matches!(byte, b'(' | b')' | b'\\') {
+            encoded.push(b'\\');
+        }
+        encoded.push(byte);
+    }
+    encoded
+}
@@ -1,6 +1,6 @@
 // tests/integration_test.rs
-use reqwest::Client;
-use serde_json::{json, Value};
+use reqwest::{multipart, Client};
+use serde_json::Value;
 use std::time::Duration;
 use tokio::time::timeout;
 
@@ -9,17 +9,22 @@ async fn test_create_document() {
     let client = Client::new();
     let url = "http://localhost:8080/api/documents";
 
-    let new_document = json!({
-        "user_id": "123e4567-e89b-12d3-a456-426614174000",
-        "doc_type": "passport",
-        "filename": "test_document.jpg"
-    });
+    let file_bytes = b"%PDF-1.4 test document".to_vec();
+    let file_part = multipart::Part::bytes(file_bytes.clone())
+        .file_name("test_document.pdf")
+        .mime_str("application/pdf")
+        .unwrap();
+    // Um usuário novo por execução, para não acumular documentos na cota de ninguém
+    let user_id = uuid::Uuid::new_v4().to_string();
+    let new_document = multipart::Form::new()
+        .text("user_id", user_id.clone())
+        .text("doc_type", "passport")
+        .part("file", file_part);
 
     let response_result = timeout(Duration::from_secs(10), async {
         client
             .post(url)
-            .header("Content-Type", "application/json")
-            .body(new_document.to_string())
+            .multipart(new_document)
             .send()
             .await
     })
@@ -42,5 +47,1980 @@ async fn test_create_document() {
 
     // Verifique o conteúdo do JSON
     assert_eq!(response_body["status"], "success");
-    assert_eq!(response_body["document"]["user_id"], "123e4567-e89b-12d3-a456-426614174000");
+    assert_eq!(response_body["document"]["user_id"], user_id);
+    assert_eq!(response_body["document"]["original_filename"], "test_document.pdf");
+    assert_eq!(response_body["document"]["size_bytes"], file_bytes.len());
+    assert_eq!(response_body["document"]["mime_type"], "application/pdf");
+    // sha256 de "%PDF-1.4 test document"
+    assert_eq!(
+        response_body["document"]["sha256"],
+        "437ce29b9898285d628e3e3819a45e2bd56472e2ee48290aaf41f04354a7d929"
+    );
+}
+
+#[tokio::test]
+async fn test_create_document_requires_file() {
+    let client = Client::new();
+    let url = "http://localhost:8080/api/documents";
+
+    let form = multipart::Form::new()
+        .text("user_id", uuid::Uuid::new_v4().to_string())
+        .text("doc_type", "passport");
+
+    let response = timeout(Duration::from_secs(10), client.post(url).multipart(form).send())
+        .await
+        .expect("Request timed out")
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), 400);
+}
+
+async fn upload_document(client: &Client, file_name: &str, mime: &str, bytes: Vec<u8>) -> Value {
+    let file_part = multipart::Part::bytes(bytes)
+        .file_name(file_name.to_string())
+        .mime_str(mime)
+        .unwrap();
+    let form = multipart::Form::new()
+        .text("user_id", uuid::Uuid::new_v4().to_string())
+        .text("doc_type", "passport")
+        .part("file", file_part);
+
+    let response = timeout(
+        Duration::from_secs(10),
+        client.post("http://localhost:8080/api/documents").multipart(form).send(),
+    )
+    .await
+    .expect("Request timed out")
+    .expect("Failed to send request");
+    assert!(response.status().is_success());
+
+    let body: Value = response.json().await.expect("Failed to parse response JSON");
+    wait_for_scan(client, body["document"]["id"].as_str().unwrap()).await
+}
+
+// O antivírus roda em segundo plano; devolve o documento já verificado
+async fn wait_for_scan(client: &Client, document_id: &str) -> Value {
+    let url = format!("http://localhost:8080/api/documents/{}", document_id);
+    for _ in 0..50 {
+        let body: Value = client.get(&url).send().await.unwrap().json().await.unwrap();
+        if body["document"]["scan_status"] != "pending" {
+            return body["document"].clone();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Document {} was not scanned in time", document_id);
+}
+
+#[tokio::test]
+async fn test_get_document_content_with_range() {
+    let client = Client::new();
+    // Conteúdo único: de outra execução pode ter sobrado a linha do blob, mas não o arquivo
+    let bytes = format!("%PDF-1.4 0123456789 {}", uuid::Uuid::new_v4()).into_bytes();
+    let document = upload_document(&client, "relatório.pdf", "application/pdf", bytes.clone()).await;
+    let url = format!("http://localhost:8080/api/documents/{}/content", document["id"].as_str().unwrap());
+
+    let full = client.get(&url).send().await.expect("Failed to send request");
+    assert_eq!(full.status(), 200);
+    assert_eq!(full.headers()["content-type"], "application/pdf");
+    assert!(full.headers()["content-disposition"].to_str().unwrap().contains("filename*=UTF-8''relat%C3%B3rio.pdf"));
+    let etag = full.headers()["etag"].to_str().unwrap().to_string();
+    assert_eq!(etag, format!("\"{}\"", document["sha256"].as_str().unwrap()));
+    assert_eq!(full.bytes().await.unwrap().to_vec(), bytes);
+
+    let partial = client.get(&url).header("Range", "bytes=9-12").send().await.unwrap();
+    assert_eq!(partial.status(), 206);
+    assert_eq!(partial.headers()["content-range"], format!("bytes 9-12/{}", bytes.len()));
+    assert_eq!(partial.bytes().await.unwrap().as_ref(), b"0123");
+
+    let not_modified = client.get(&url).header("If-None-Match", etag.as_str()).send().await.unwrap();
+    assert_eq!(not_modified.status(), 304);
+
+    let stale_range = client
+        .get(&url)
+        .header("Range", "bytes=0-3")
+        .header("If-Range", "\"outdated\"")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stale_range.status(), 200);
+
+    let unsatisfiable = client.get(&url).header("Range", "bytes=500-").send().await.unwrap();
+    assert_eq!(unsatisfiable.status(), 416);
+}
+
+#[tokio::test]
+async fn test_delete_document_removes_content() {
+    let client = Client::new();
+    let document = upload_document(&client, "scan.pdf", "application/pdf", b"%PDF-1.4 delete me".to_vec()).await;
+    let url = format!("http://localhost:8080/api/documents/{}", document["id"].as_str().unwrap());
+
+    let deleted = client.delete(&url).send().await.expect("Failed to send request");
+    assert_eq!(deleted.status(), 204);
+
+    let content = client.get(format!("{}/content", url)).send().await.unwrap();
+    assert_eq!(content.status(), 404);
+    assert_eq!(client.delete(&url).send().await.unwrap().status(), 404);
+}
+
+#[tokio::test]
+async fn test_trash_restore_and_purge() {
+    let client = Client::new();
+    let document = upload_document(&client, "trash.pdf", "application/pdf", b"%PDF-1.4 trash me".to_vec()).await;
+    let id = document["id"].as_str().unwrap();
+    let url = format!("http://localhost:8080/api/documents/{}", id);
+    let trash_url = format!("http://localhost:8080/api/trash/documents/{}", id);
+
+    // Só o que está na lixeira pode ser restaurado ou apagado de vez
+    assert_eq!(client.post(format!("{}/restore", trash_url)).send().await.unwrap().status(), 404);
+    assert_eq!(client.delete(&trash_url).send().await.unwrap().status(), 404);
+
+    assert_eq!(client.delete(&url).send().await.unwrap().status(), 204);
+    assert_eq!(client.get(&url).send().await.unwrap().status(), 404);
+    let trash: Value = client
+        .get("http://localhost:8080/api/trash/documents?limit=100")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(trash["documents"].as_array().unwrap().iter().any(|document| document["id"] == id));
+
+    let restored: Value = client
+        .post(format!("{}/restore", trash_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(restored["document"]["id"], id);
+    assert!(restored["document"]["deleted_at"].is_null());
+    let content = client.get(format!("{}/content", url)).send().await.unwrap();
+    assert_eq!(content.bytes().await.unwrap().as_ref(), b"%PDF-1.4 trash me");
+
+    assert_eq!(client.delete(&url).send().await.unwrap().status(), 204);
+    assert_eq!(client.delete(&trash_url).send().await.unwrap().status(), 204);
+    assert_eq!(client.post(format!("{}/restore", trash_url)).send().await.unwrap().status(), 404);
+
+    // Tarefas seguem o mesmo caminho
+    let task: Value = client
+        .post("http://localhost:8080/api/task")
+        .json(&serde_json::json!({"title": "Trash task", "content": "soft delete"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let task_id = task["task"]["id"].as_str().unwrap();
+    let task_url = format!("http://localhost:8080/api/tasks/{}", task_id);
+    let task_trash_url = format!("http://localhost:8080/api/trash/tasks/{}", task_id);
+
+    assert_eq!(client.delete(&task_url).send().await.unwrap().status(), 204);
+    assert_eq!(client.delete(&task_url).send().await.unwrap().status(), 404);
+    assert_eq!(client.get(&task_url).send().await.unwrap().status(), 404);
+    let trash: Value = client
+        .get("http://localhost:8080/api/trash/tasks?limit=100")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(trash["tasks"].as_array().unwrap().iter().any(|task| task["id"] == task_id));
+
+    assert_eq!(client.post(format!("{}/restore", task_trash_url)).send().await.unwrap().status(), 200);
+    assert_eq!(client.get(&task_url).send().await.unwrap().status(), 200);
+    assert_eq!(client.delete(&task_url).send().await.unwrap().status(), 204);
+    assert_eq!(client.delete(&task_trash_url).send().await.unwrap().status(), 204);
+    assert_eq!(client.delete(&task_trash_url).send().await.unwrap().status(), 404);
+}
+
+#[tokio::test]
+async fn test_tus_resumable_upload() {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let client = Client::new();
+    let content = format!("%PDF-1.4 uploaded in two chunks {}", uuid::Uuid::new_v4()).into_bytes();
+    let metadata = format!(
+        "filename {},filetype {},user_id {},doc_type {}",
+        STANDARD.encode("scan.pdf"),
+        STANDARD.encode("application/pdf"),
+        STANDARD.encode(uuid::Uuid::new_v4().to_string()),
+        STANDARD.encode("passport")
+    );
+
+    let created = client
+        .post("http://localhost:8080/api/uploads")
+        .header("Tus-Resumable", "1.0.0")
+        .header("Upload-Length", content.len().to_string())
+        .header("Upload-Metadata", metadata)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(created.status(), 201);
+    let location = created.headers()["location"].to_str().unwrap().to_string();
+    let url = format!("http://localhost:8080{}", location);
+
+    let first = client
+        .patch(&url)
+        .header("Tus-Resumable", "1.0.0")
+        .header("Content-Type", "application/offset+octet-stream")
+        .header("Upload-Offset", "0")
+        .body(content[..10].to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(first.status(), 204);
+    assert_eq!(first.headers()["upload-offset"], "10");
+
+    let stale = client
+        .patch(&url)
+        .header("Tus-Resumable", "1.0.0")
+        .header("Content-Type", "application/offset+octet-stream")
+        .header("Upload-Offset", "0")
+        .body(content.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stale.status(), 409);
+
+    let offset = client.head(&url).header("Tus-Resumable", "1.0.0").send().await.unwrap();
+    assert_eq!(offset.status(), 200);
+    assert_eq!(offset.headers()["upload-offset"], "10");
+
+    let last = client
+        .patch(&url)
+        .header("Tus-Resumable", "1.0.0")
+        .header("Content-Type", "application/offset+octet-stream")
+        .header("Upload-Offset", "10")
+        .body(content[10..].to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(last.status(), 204);
+    let document_id = last.headers()["upload-document-id"].to_str().unwrap().to_string();
+    assert_eq!(wait_for_scan(&client, &document_id).await["scan_status"], "clean");
+
+    let stored = client
+        .get(format!("http://localhost:8080/api/documents/{}/content", document_id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stored.status(), 200);
+    assert_eq!(stored.bytes().await.unwrap().to_vec(), content);
+
+    let terminated = client.delete(&url).header("Tus-Resumable", "1.0.0").send().await.unwrap();
+    assert_eq!(terminated.status(), 204);
+    let gone = client.head(&url).header("Tus-Resumable", "1.0.0").send().await.unwrap();
+    assert_eq!(gone.status(), 404);
+}
+
+async fn post_document_file(client: &Client, doc_type: &str, file_name: &str, mime: &str, bytes: Vec<u8>) -> reqwest::Response {
+    let file_part = multipart::Part::bytes(bytes)
+        .file_name(file_name.to_string())
+        .mime_str(mime)
+        .unwrap();
+    let form = multipart::Form::new()
+        .text("user_id", uuid::Uuid::new_v4().to_string())
+        .text("doc_type", doc_type.to_string())
+        .part("file", file_part);
+
+    timeout(
+        Duration::from_secs(10),
+        client.post("http://localhost:8080/api/documents").multipart(form).send(),
+    )
+    .await
+    .expect("Request timed out")
+    .expect("Failed to send request")
+}
+
+#[tokio::test]
+async fn test_create_document_rejects_mismatched_content() {
+    let client = Client::new();
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+
+    // Conteúdo PNG declarado como PDF
+    let response = post_document_file(&client, "passport", "scan.pdf", "application/pdf", png.clone()).await;
+    assert_eq!(response.status(), 415);
+
+    // Extensão que não bate com o conteúdo
+    let response = post_document_file(&client, "passport", "scan.pdf", "image/png", png.clone()).await;
+    assert_eq!(response.status(), 415);
+
+    // Texto puro não está no allowlist de passport
+    let response = post_document_file(&client, "passport", "notes.txt", "text/plain", b"hello".to_vec()).await;
+    assert_eq!(response.status(), 415);
+
+    let response = post_document_file(&client, "passport", "scan.png", "image/png", png).await;
+    assert!(response.status().is_success());
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["document"]["mime_type"], "image/png");
+}
+
+#[tokio::test]
+async fn test_identical_uploads_share_content() {
+    let client = Client::new();
+    let content = format!("%PDF-1.4 shared {}", uuid::Uuid::new_v4()).into_bytes();
+
+    let first = upload_document(&client, "front.pdf", "application/pdf", content.clone()).await;
+    let second = upload_document(&client, "copy.pdf", "application/pdf", content.clone()).await;
+    assert_eq!(first["filename"], second["filename"]);
+    assert_eq!(first["filename"], first["sha256"]);
+
+    let duplicates: Value = client
+        .get("http://localhost:8080/api/documents/duplicates?limit=1000")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let group = duplicates["duplicates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|group| group["sha256"] == first["sha256"])
+        .expect("Duplicate group not reported");
+    assert_eq!(group["count"], 2);
+
+    let first_url = format!("http://localhost:8080/api/documents/{}", first["id"].as_str().unwrap());
+    let second_url = format!("http://localhost:8080/api/documents/{}", second["id"].as_str().unwrap());
+
+    assert_eq!(client.delete(&first_url).send().await.unwrap().status(), 204);
+    let remaining = client.get(format!("{}/content", second_url)).send().await.unwrap();
+    assert_eq!(remaining.status(), 200);
+    assert_eq!(remaining.bytes().await.unwrap().to_vec(), content);
+
+    assert_eq!(client.delete(&second_url).send().await.unwrap().status(), 204);
+}
+
+#[tokio::test]
+async fn test_document_revisions_and_restore() {
+    let client = Client::new();
+    let original = format!("%PDF-1.4 original {}", uuid::Uuid::new_v4()).into_bytes();
+    let replacement = format!("%PDF-1.4 replacement {}", uuid::Uuid::new_v4()).into_bytes();
+
+    let document = upload_document(&client, "contract.pdf", "application/pdf", original.clone()).await;
+    assert_eq!(document["revision"], 1);
+    let document_url = format!("http://localhost:8080/api/documents/{}", document["id"].as_str().unwrap());
+
+    let form = multipart::Form::new().part(
+        "file",
+        multipart::Part::bytes(replacement.clone())
+            .file_name("contract-v2.pdf")
+            .mime_str("application/pdf")
+            .unwrap(),
+    );
+    let updated: Value = client
+        .post(format!("{}/revisions", document_url))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(updated["document"]["revision"], 2);
+    assert_eq!(updated["document"]["original_filename"], "contract-v2.pdf");
+    wait_for_scan(&client, document["id"].as_str().unwrap()).await;
+
+    let current = client.get(format!("{}/content", document_url)).send().await.unwrap();
+    assert_eq!(current.bytes().await.unwrap().to_vec(), replacement);
+
+    let history: Value = client
+        .get(format!("{}/revisions", document_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(history["current_revision"], 2);
+    assert_eq!(history["revisions"].as_array().unwrap().len(), 2);
+
+    let first = client.get(format!("{}/revisions/1/content", document_url)).send().await.unwrap();
+    assert_eq!(first.status(), 200);
+    assert_eq!(first.bytes().await.unwrap().to_vec(), original);
+
+    let restored: Value = client
+        .post(format!("{}/revisions/1/restore", document_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(restored["document"]["revision"], 3);
+    assert_eq!(restored["document"]["sha256"], document["sha256"]);
+
+    let current = client.get(format!("{}/content", document_url)).send().await.unwrap();
+    assert_eq!(current.bytes().await.unwrap().to_vec(), original);
+
+    let missing = client.get(format!("{}/revisions/9/content", document_url)).send().await.unwrap();
+    assert_eq!(missing.status(), 404);
+
+    assert_eq!(client.delete(&document_url).send().await.unwrap().status(), 204);
+}
+
+#[tokio::test]
+async fn test_image_document_thumbnails() {
+    let client = Client::new();
+
+    // Cor aleatória para não reaproveitar o blob de outra execução
+    let seed = uuid::Uuid::new_v4().as_bytes()[0];
+    let scan = image::RgbImage::from_pixel(600, 400, image::Rgb([seed, 120, 200]));
+    let mut png = Vec::new();
+    scan.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+
+    let document = upload_document(&client, "scan.png", "image/png", png).await;
+    let document_url = format!("http://localhost:8080/api/documents/{}", document["id"].as_str().unwrap());
+
+    for (size, pixels) in [("small", 128), ("medium", 256), ("large", 512)] {
+        let response = client
+            .get(format!("{}/thumbnail?size={}", document_url, size))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "image/jpeg");
+
+        let thumbnail = image::load_from_memory(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!(thumbnail.width().max(thumbnail.height()), pixels);
+    }
+
+    let invalid = client.get(format!("{}/thumbnail?size=huge", document_url)).send().await.unwrap();
+    assert_eq!(invalid.status(), 400);
+
+    assert_eq!(client.delete(&document_url).send().await.unwrap().status(), 204);
+    let deleted = client.get(format!("{}/thumbnail", document_url)).send().await.unwrap();
+    assert_eq!(deleted.status(), 404);
+}
+
+// JPEG com EXIF: câmera, data, orientação e GPS
+fn jpeg_with_exif(width: u32, height: u32, orientation: u32) -> Vec<u8> {
+    fn entry(tiff: &mut Vec<u8>, tag: u16, kind: u16, count: u32, value: u32) {
+        tiff.extend_from_slice(&tag.to_le_bytes());
+        tiff.extend_from_slice(&kind.to_le_bytes());
+        tiff.extend_from_slice(&count.to_le_bytes());
+        tiff.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend_from_slice(&8u32.to_le_bytes());
+    // IFD0 em 8 (4 entradas, termina em 62), IFD do GPS em 62 (termina em 80), dados em 80
+    tiff.extend_from_slice(&4u16.to_le_bytes());
+    entry(&mut tiff, 0x010F, 2, 8, 80);
+    entry(&mut tiff, 0x0112, 3, 1, orientation);
+    entry(&mut tiff, 0x0132, 2, 20, 88);
+    entry(&mut tiff, 0x8825, 4, 1, 62);
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    tiff.extend_from_slice(&1u16.to_le_bytes());
+    entry(&mut tiff, 0x0001, 2, 2, u32::from_le_bytes(*b"N\0\0\0"));
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    tiff.extend_from_slice(b"TestCam\0");
+    tiff.extend_from_slice(b"2024:05:01 10:20:30\0");
+
+    let seed = uuid::Uuid::new_v4().as_bytes()[0];
+    let photo = image::RgbImage::from_pixel(width, height, image::Rgb([seed, 60, 90]));
+    let mut jpeg = Vec::new();
+    photo.write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg).unwrap();
+
+    let mut app1 = vec![0xFF, 0xE1];
+    app1.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+    app1.extend_from_slice(b"Exif\0\0");
+    app1.extend_from_slice(&tiff);
+    jpeg.splice(2..2, app1);
+    jpeg
+}
+
+#[tokio::test]
+async fn test_image_upload_strips_exif_and_rotates() {
+    let client = Client::new();
+
+    // Orientação 6: girar 90° no sentido horário
+    let document = upload_document(&client, "photo.jpg", "image/jpeg", jpeg_with_exif(60, 40, 6)).await;
+    let metadata = &document["image_metadata"];
+    assert_eq!(metadata["camera_make"], "TestCam");
+    assert_eq!(metadata["orientation"], 6);
+    assert_eq!(metadata["captured_at"], "2024-05-01T10:20:30");
+    assert_eq!(metadata["location_removed"], true);
+
+    let document_url = format!("http://localhost:8080/api/documents/{}", document["id"].as_str().unwrap());
+    let stored = client
+        .get(format!("{}/content", document_url))
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    assert_eq!(stored.len() as u64, document["size_bytes"].as_u64().unwrap());
+
+    let stored_exif = exif::Reader::new().read_from_container(&mut std::io::Cursor::new(&stored));
+    assert!(matches!(stored_exif, Err(exif::Error::NotFound(_))));
+
+    let upright = image::load_from_memory(&stored).unwrap();
+    assert_eq!((upright.width(), upright.height()), (40, 60));
+
+    assert_eq!(client.delete(&document_url).send().await.unwrap().status(), 204);
+
+    // Sem rotação o EXIF sai sem recodificar a imagem
+    let original = jpeg_with_exif(60, 40, 1);
+    let document = upload_document(&client, "photo.jpg", "image/jpeg", original.clone()).await;
+    assert_eq!(document["image_metadata"]["location_removed"], true);
+    assert!(document["size_bytes"].as_u64().unwrap() < original.len() as u64);
+
+    let document_url = format!("http://localhost:8080/api/documents/{}", document["id"].as_str().unwrap());
+    let stored = client
+        .get(format!("{}/content", document_url))
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    assert!(stored.ends_with(&original[original.len() - 64..]));
+    assert!(!stored.windows(7).any(|window| window == b"TestCam"));
+
+    assert_eq!(client.delete(&document_url).send().await.unwrap().status(), 204);
+}
+
+#[tokio::test]
+async fn test_infected_document_is_quarantined() {
+    let client = Client::new();
+    let content = format!(
+        "%PDF-1.4 {} X5O!P%@AP[4\\PZX54(P^)7CC)7}}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*",
+        uuid::Uuid::new_v4()
+    )
+    .into_bytes();
+
+    let document = upload_document(&client, "invoice.pdf", "application/pdf", content).await;
+    assert_eq!(document["scan_status"], "infected");
+    assert_eq!(document["scan_detail"], "Eicar-Test-Signature");
+
+    let document_url = format!("http://localhost:8080/api/documents/{}", document["id"].as_str().unwrap());
+    let blocked = client.get(format!("{}/content", document_url)).send().await.unwrap();
+    assert_eq!(blocked.status(), 403);
+
+    let rescan = client.post(format!("{}/scan", document_url)).send().await.unwrap();
+    assert_eq!(rescan.status(), 202);
+    let rescanned = wait_for_scan(&client, document["id"].as_str().unwrap()).await;
+    assert_eq!(rescanned["scan_status"], "infected");
+
+    assert_eq!(client.delete(&document_url).send().await.unwrap().status(), 204);
+}
+
+#[tokio::test]
+async fn test_infected_image_is_rejected_before_decoding() {
+    let client = Client::new();
+    let mut content = jpeg_with_exif(60, 40, 1);
+    content.extend_from_slice(b"X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*");
+
+    let response = post_document_file(&client, "passport", "photo.jpg", "image/jpeg", content).await;
+    assert_eq!(response.status(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "fail");
+    assert!(body["message"].as_str().unwrap().contains("Eicar-Test-Signature"));
+}
+
+#[tokio::test]
+async fn test_document_review_workflow() {
+    let client = Client::new();
+    let reviewer = "0b9f3c4e-7a51-4c1d-9c3a-5d2e8f6a1b70";
+    let content = format!("%PDF-1.4 review {}", uuid::Uuid::new_v4()).into_bytes();
+
+    let document = upload_document(&client, "passport.pdf", "application/pdf", content).await;
+    assert_eq!(document["review_status"], "submitted");
+    let document_url = format!("http://localhost:8080/api/documents/{}", document["id"].as_str().unwrap());
+
+    let review = |action: &str, body: Value| {
+        client
+            .post(format!("{}/review/{}", document_url, action))
+            .json(&body)
+            .send()
+    };
+
+    // Não dá para aprovar sem iniciar a revisão
+    let early = review("approve", serde_json::json!({"reviewer_id": reviewer})).await.unwrap();
+    assert_eq!(early.status(), 409);
+
+    let anonymous = review("start", serde_json::json!({})).await.unwrap();
+    assert_eq!(anonymous.status(), 400);
+
+    let started: Value = review("start", serde_json::json!({"reviewer_id": reviewer}))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(started["document"]["review_status"], "in_review");
+    assert_eq!(started["document"]["reviewer_id"], reviewer);
+
+    let no_reason = review("reject", serde_json::json!({"reviewer_id": reviewer})).await.unwrap();
+    assert_eq!(no_reason.status(), 400);
+
+    let rejected: Value = review("reject", serde_json::json!({"reviewer_id": reviewer, "reason": "Photo is blurry"}))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(rejected["document"]["review_status"], "rejected");
+    assert_eq!(rejected["document"]["rejection_reason"], "Photo is blurry");
+    assert!(rejected["document"]["decided_at"].is_string());
+
+    let resubmitted: Value = review("resubmit", serde_json::json!({})).await.unwrap().json().await.unwrap();
+    assert_eq!(resubmitted["document"]["review_status"], "submitted");
+    assert!(resubmitted["document"]["rejection_reason"].is_null());
+
+    review("start", serde_json::json!({"reviewer_id": reviewer})).await.unwrap();
+    let approved: Value = review("approve", serde_json::json!({"reviewer_id": reviewer}))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(approved["document"]["review_status"], "approved");
+
+    let again = review("approve", serde_json::json!({"reviewer_id": reviewer})).await.unwrap();
+    assert_eq!(again.status(), 409);
+
+    let listed: Value = client
+        .get("http://localhost:8080/api/documents?status=approved&limit=1000")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let documents = listed["documents"].as_array().unwrap();
+    assert!(documents.iter().all(|listed| listed["review_status"] == "approved"));
+    assert!(documents.iter().any(|listed| listed["id"] == document["id"]));
+
+    let invalid = client.get("http://localhost:8080/api/documents?status=done").send().await.unwrap();
+    assert_eq!(invalid.status(), 400);
+
+    assert_eq!(client.delete(&document_url).send().await.unwrap().status(), 204);
+}
+
+async fn post_document_metadata(client: &Client, doc_type: &str, metadata: Value) -> reqwest::Response {
+    let content = format!("%PDF-1.4 metadata {}", uuid::Uuid::new_v4()).into_bytes();
+    let file_part = multipart::Part::bytes(content)
+        .file_name("document.pdf")
+        .mime_str("application/pdf")
+        .unwrap();
+    let form = multipart::Form::new()
+        .text("user_id", uuid::Uuid::new_v4().to_string())
+        .text("doc_type", doc_type.to_string())
+        .text("metadata", metadata.to_string())
+        .part("file", file_part);
+
+    client.post("http://localhost:8080/api/documents").multipart(form).send().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_document_types_validate_metadata() {
+    let client = Client::new();
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    let key = format!("visa_{}", &suffix[..8]);
+    let type_url = format!("http://localhost:8080/api/admin/document-types/{}", key);
+
+    let created = client
+        .post("http://localhost:8080/api/admin/document-types")
+        .json(&serde_json::json!({
+            "key": key,
+            "display_name": "Visa",
+            "aliases": [format!("visto_{}", &suffix[..8])],
+            "metadata_schema": {
+                "type": "object",
+                "required": ["visa_number"],
+                "properties": {
+                    "visa_number": {"type": "string", "pattern": "^[A-Z0-9]{8}$"}
+                }
+            }
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert!(created.status().is_success());
+
+    let duplicate = client
+        .post("http://localhost:8080/api/admin/document-types")
+        .json(&serde_json::json!({"key": key, "display_name": "Visa"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(duplicate.status(), 409);
+
+    let broken_schema = client
+        .post("http://localhost:8080/api/admin/document-types")
+        .json(&serde_json::json!({"key": "broken_schema", "display_name": "Broken", "metadata_schema": {"type": 12}}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(broken_schema.status(), 400);
+
+    // Campo obrigatório ausente e campo fora do padrão
+    let missing = post_document_metadata(&client, &key, serde_json::json!({})).await;
+    assert_eq!(missing.status(), 400);
+    let missing: Value = missing.json().await.unwrap();
+    assert_eq!(missing["errors"].as_array().unwrap().len(), 1);
+
+    let malformed = post_document_metadata(&client, &key, serde_json::json!({"visa_number": "abc"})).await;
+    assert_eq!(malformed.status(), 400);
+    let malformed: Value = malformed.json().await.unwrap();
+    assert!(malformed["errors"][0].as_str().unwrap().starts_with("/visa_number"));
+
+    let unknown = post_document_metadata(&client, "library_card", serde_json::json!({})).await;
+    assert_eq!(unknown.status(), 400);
+
+    // O alias é aceito e trocado pela chave canônica
+    let alias = format!("Visto {}", &suffix[..8].to_uppercase());
+    let stored = post_document_metadata(&client, &alias, serde_json::json!({"visa_number": "AB12CD34"})).await;
+    assert!(stored.status().is_success());
+    let stored: Value = stored.json().await.unwrap();
+    assert_eq!(stored["document"]["doc_type"], key);
+    assert_eq!(stored["document"]["metadata"]["visa_number"], "AB12CD34");
+
+    let passport = post_document_metadata(&client, "Passaporte", serde_json::json!({})).await;
+    let passport: Value = passport.json().await.unwrap();
+    assert_eq!(passport["document"]["doc_type"], "passport");
+
+    // "format": "date" também é conferido
+    let bad_date = post_document_metadata(&client, "passport", serde_json::json!({"expires_on": "not-a-date"})).await;
+    assert_eq!(bad_date.status(), 400);
+    let bad_date: Value = bad_date.json().await.unwrap();
+    assert!(bad_date["errors"][0].as_str().unwrap().starts_with("/expires_on"));
+
+    let document_url = format!("http://localhost:8080/api/documents/{}", stored["document"]["id"].as_str().unwrap());
+    let invalid_update = client
+        .patch(&document_url)
+        .json(&serde_json::json!({"metadata": {"visa_number": 42}}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid_update.status(), 400);
+
+    let updated: Value = client
+        .patch(&document_url)
+        .json(&serde_json::json!({"metadata": {"visa_number": "ZZ99ZZ99"}}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(updated["document"]["metadata"]["visa_number"], "ZZ99ZZ99");
+
+    // Tipo em uso não pode ser removido
+    assert_eq!(client.delete(&type_url).send().await.unwrap().status(), 409);
+    assert_eq!(client.delete(&document_url).send().await.unwrap().status(), 204);
+    // Documento na lixeira ainda usa o tipo
+    assert_eq!(client.delete(&type_url).send().await.unwrap().status(), 409);
+    let purge_url = document_url.replace("/api/documents/", "/api/trash/documents/");
+    assert_eq!(client.delete(&purge_url).send().await.unwrap().status(), 204);
+    assert_eq!(client.delete(&type_url).send().await.unwrap().status(), 204);
+    assert_eq!(client.get(&type_url).send().await.unwrap().status(), 404);
+}
+
+#[tokio::test]
+async fn test_passport_mrz_parsing() {
+    let client = Client::new();
+    let content = format!("%PDF-1.4 mrz {}", uuid::Uuid::new_v4()).into_bytes();
+    let document = upload_document(&client, "passport.pdf", "application/pdf", content).await;
+    let mrz_url = format!("http://localhost:8080/api/documents/{}/mrz", document["id"].as_str().unwrap());
+
+    // Exemplo TD3 do ICAO 9303
+    let td3 = "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<\nL898902C36UTO7408122F1204159ZE184226B<<<<<10";
+    let parsed: Value = client
+        .post(&mrz_url)
+        .json(&serde_json::json!({"mrz": td3}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(parsed["status"], "success");
+    let metadata = &parsed["document"]["metadata"];
+    assert_eq!(metadata["surname"], "ERIKSSON");
+    assert_eq!(metadata["given_names"], "ANNA MARIA");
+    assert_eq!(metadata["document_number"], "L898902C3");
+    assert_eq!(metadata["nationality"], "UTO");
+    assert_eq!(metadata["birth_date"], "1974-08-12");
+    assert_eq!(metadata["expires_on"], "2012-04-15");
+
+    // Dígitos do número e da validade trocados
+    let broken = "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<\nL898902C35UTO7408122F1204158ZE184226B<<<<<10";
+    let rejected = client.post(&mrz_url).json(&serde_json::json!({"mrz": broken})).send().await.unwrap();
+    assert_eq!(rejected.status(), 400);
+    let rejected: Value = rejected.json().await.unwrap();
+    let fields: Vec<&str> = rejected["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert!(fields.contains(&"document_number"));
+    assert!(fields.contains(&"expires_on"));
+    assert!(fields.contains(&"composite"));
+
+    let td1 = "I<UTOD231458907<<<<<<<<<<<<<<<\n7408122F1204159UTO<<<<<<<<<<<6\nERIKSSON<<ANNA<MARIA<<<<<<<<<<";
+    let parsed: Value = client
+        .post(&mrz_url)
+        .json(&serde_json::json!({"mrz": td1}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(parsed["mrz"]["format"], "TD1");
+    assert_eq!(parsed["document"]["metadata"]["document_number"], "D23145890");
+
+    let short = client.post(&mrz_url).json(&serde_json::json!({"mrz": "P<UTO"})).send().await.unwrap();
+    assert_eq!(short.status(), 400);
+}
+
+#[tokio::test]
+async fn test_brazilian_identity_numbers() {
+    let client = Client::new();
+    let validate = |kind: &str, body: Value| {
+        client
+            .post(format!("http://localhost:8080/api/validate/{}", kind))
+            .json(&body)
+            .send()
+    };
+
+    let cpf: Value = validate("cpf", serde_json::json!({"value": "52998224725"})).await.unwrap().json().await.unwrap();
+    assert_eq!(cpf["valid"], true);
+    assert_eq!(cpf["normalized"], "529.982.247-25");
+
+    let cpf: Value = validate("cpf", serde_json::json!({"value": "529.982.247-24"})).await.unwrap().json().await.unwrap();
+    assert_eq!(cpf["valid"], false);
+
+    let cpf: Value = validate("cpf", serde_json::json!({"value": "111.111.111-11"})).await.unwrap().json().await.unwrap();
+    assert_eq!(cpf["valid"], false);
+
+    let cnpj: Value = validate("cnpj", serde_json::json!({"value": "11222333000181"})).await.unwrap().json().await.unwrap();
+    assert_eq!(cnpj["normalized"], "11.222.333/0001-81");
+
+    // CNPJ alfanumérico
+    let cnpj: Value = validate("cnpj", serde_json::json!({"value": "12.abc.345/01de-35"})).await.unwrap().json().await.unwrap();
+    assert_eq!(cnpj["valid"], true);
+    assert_eq!(cnpj["normalized"], "12.ABC.345/01DE-35");
+
+    let rg: Value = validate("rg", serde_json::json!({"value": "12345678x", "state": "sp"})).await.unwrap().json().await.unwrap();
+    assert_eq!(rg["normalized"], "12.345.678-X");
+
+    let rg: Value = validate("rg", serde_json::json!({"value": "MG 12.345.678", "state": "MG"})).await.unwrap().json().await.unwrap();
+    assert_eq!(rg["normalized"], "MG-12.345.678");
+
+    let stateless = validate("rg", serde_json::json!({"value": "1234567"})).await.unwrap();
+    assert_eq!(stateless.status(), 400);
+
+    let unknown = validate("passport", serde_json::json!({"value": "1"})).await.unwrap();
+    assert_eq!(unknown.status(), 404);
+
+    // Documentos cpf e rg têm o número conferido e formatado
+    let invalid = post_document_metadata(&client, "cpf", serde_json::json!({"document_number": "52998224700"})).await;
+    assert_eq!(invalid.status(), 400);
+    let invalid: Value = invalid.json().await.unwrap();
+    assert!(invalid["errors"][0].as_str().unwrap().starts_with("/document_number"));
+
+    let stored = post_document_metadata(&client, "cpf", serde_json::json!({"document_number": "52998224725"})).await;
+    let stored: Value = stored.json().await.unwrap();
+    assert_eq!(stored["document"]["metadata"]["document_number"], "529.982.247-25");
+
+    let document_url = format!("http://localhost:8080/api/documents/{}", stored["document"]["id"].as_str().unwrap());
+    let updated: Value = client
+        .patch(&document_url)
+        .json(&serde_json::json!({"doc_type": "rg", "metadata": {"document_number": "123456789", "issuing_state": "rj"}}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(updated["document"]["doc_type"], "rg");
+    assert_eq!(updated["document"]["metadata"]["document_number"], "12.345.678-9");
+    assert_eq!(updated["document"]["metadata"]["issuing_state"], "RJ");
+}
+
+#[tokio::test]
+async fn test_document_expiry() {
+    let client = Client::new();
+    let today = chrono::Utc::now().date_naive();
+    let in_five_days = (today + chrono::Duration::days(5)).to_string();
+    let yesterday = (today - chrono::Duration::days(1)).to_string();
+
+    // Sem expires_at explícito, vale o metadata.expires_on
+    let soon: Value = post_document_metadata(&client, "passport", serde_json::json!({"expires_on": in_five_days}))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(soon["document"]["expires_at"], in_five_days);
+    let soon_id = soon["document"]["id"].clone();
+
+    let listed = |query: &str| {
+        let url = format!("http://localhost:8080/api/documents?limit=1000&{}", query);
+        let client = client.clone();
+        async move {
+            let body: Value = client.get(url).send().await.unwrap().json().await.unwrap();
+            body["documents"].as_array().unwrap().clone()
+        }
+    };
+    assert!(listed("expiring_within=10").await.iter().any(|document| document["id"] == soon_id));
+    assert!(!listed("expiring_within=2").await.iter().any(|document| document["id"] == soon_id));
+
+    let expired: Value = post_document_metadata(&client, "passport", serde_json::json!({"expires_on": yesterday}))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let expired_id = expired["document"]["id"].clone();
+    assert_eq!(expired["document"]["review_status"], "submitted");
+
+    let swept: Value = client
+        .post("http://localhost:8080/api/admin/expiry-sweep")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(swept["expired"].as_u64().unwrap() >= 1);
+
+    let expired_list = listed("status=expired").await;
+    assert!(expired_list.iter().any(|document| document["id"] == expired_id));
+    assert!(!expired_list.iter().any(|document| document["id"] == soon_id));
+
+    assert!(listed("expiring_within=3650").await.iter().any(|document| document["id"] == soon_id));
+    let too_far = client
+        .get("http://localhost:8080/api/documents?expiring_within=4294967295")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(too_far.status(), 400);
+
+    let not_a_date = post_document_metadata(&client, "driver_license", serde_json::json!({"expires_on": "soon"})).await;
+    assert_eq!(not_a_date.status(), 400);
+
+    // Vencido só volta para submitted pela revisão, e com uma validade futura
+    let document_url = format!("http://localhost:8080/api/documents/{}", expired_id.as_str().unwrap());
+    let resubmit = || client.post(format!("{}/review/resubmit", document_url)).send();
+    assert_eq!(resubmit().await.unwrap().status(), 409);
+
+    let renewed: Value = client
+        .patch(&document_url)
+        .json(&serde_json::json!({"expires_at": in_five_days}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(renewed["document"]["review_status"], "expired");
+    assert_eq!(renewed["document"]["expires_at"], in_five_days);
+
+    let resubmitted: Value = resubmit().await.unwrap().json().await.unwrap();
+    assert_eq!(resubmitted["document"]["review_status"], "submitted");
+}
+
+#[tokio::test]
+async fn test_expiry_clears_approval() {
+    let client = Client::new();
+    let reviewer = "0b9f3c4e-7a51-4c1d-9c3a-5d2e8f6a1b70";
+    let content = format!("%PDF-1.4 expiring {}", uuid::Uuid::new_v4()).into_bytes();
+    let document = upload_document(&client, "expiring.pdf", "application/pdf", content).await;
+    let document_url = format!("http://localhost:8080/api/documents/{}", document["id"].as_str().unwrap());
+    for action in ["start", "approve"] {
+        let reviewed = client
+            .post(format!("{}/review/{}", document_url, action))
+            .json(&serde_json::json!({ "reviewer_id": reviewer }))
+            .send()
+            .await
+            .unwrap();
+        assert!(reviewed.status().is_success());
+    }
+
+    let yesterday = (chrono::Utc::now().date_naive() - chrono::Duration::days(1)).to_string();
+    client
+        .patch(&document_url)
+        .json(&serde_json::json!({ "expires_at": yesterday }))
+        .send()
+        .await
+        .unwrap();
+    client.post("http://localhost:8080/api/admin/expiry-sweep").send().await.unwrap();
+
+    let expired: Value = client.get(&document_url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(expired["document"]["review_status"], "expired");
+    assert!(expired["document"]["reviewer_id"].is_null());
+    assert!(expired["document"]["decided_at"].is_null());
+}
+
+#[tokio::test]
+async fn test_share_links() {
+    let client = Client::new();
+    let content = format!("%PDF-1.4 shared {}", uuid::Uuid::new_v4()).into_bytes();
+    let document = upload_document(&client, "audit.pdf", "application/pdf", content.clone()).await;
+    let links_url = format!("http://localhost:8080/api/documents/{}/share-links", document["id"].as_str().unwrap());
+
+    let create = |body: Value| {
+        let client = client.clone();
+        let links_url = links_url.clone();
+        async move {
+            let created: Value = client.post(links_url).json(&body).send().await.unwrap().json().await.unwrap();
+            created
+        }
+    };
+
+    let limited = create(serde_json::json!({"max_downloads": 2})).await;
+    let url = limited["url"].as_str().unwrap().to_string();
+    for _ in 0..2 {
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.bytes().await.unwrap().to_vec(), content);
+    }
+    assert_eq!(client.get(&url).send().await.unwrap().status(), 410);
+
+    // Revalidações não gastam downloads; cada intervalo entregue gasta um
+    let ranged = create(serde_json::json!({"max_downloads": 2})).await;
+    let ranged_url = ranged["url"].as_str().unwrap().to_string();
+    let get_range = |range: &'static str| client.get(&ranged_url).header("Range", range).send();
+    let etag = document["sha256"].as_str().unwrap();
+    for _ in 0..3 {
+        let revalidated = client.get(&ranged_url).header("If-None-Match", format!("\"{}\"", etag)).send().await.unwrap();
+        assert_eq!(revalidated.status(), 304);
+    }
+    let head = get_range("bytes=0-3").await.unwrap();
+    assert_eq!(head.status(), 206);
+    assert_eq!(head.bytes().await.unwrap().to_vec(), content[..4].to_vec());
+    let tail = get_range("bytes=4-").await.unwrap();
+    assert_eq!(tail.status(), 206);
+    assert_eq!(tail.bytes().await.unwrap().to_vec(), content[4..].to_vec());
+    assert_eq!(get_range("bytes=0-3").await.unwrap().status(), 410);
+    assert_eq!(client.get(&ranged_url).send().await.unwrap().status(), 410);
+
+    // Assinatura ou validade adulteradas
+    let tampered = format!("{}0", &url[..url.len() - 1]);
+    assert_eq!(client.get(&tampered).send().await.unwrap().status(), 403);
+    let expires = limited["share_link"]["expires_at"].as_str().unwrap();
+    let later = chrono::DateTime::parse_from_rfc3339(expires).unwrap().timestamp() + 3600;
+    let extended = url.replace(&format!("expires={}", later - 3600), &format!("expires={}", later));
+    assert_eq!(client.get(&extended).send().await.unwrap().status(), 403);
+
+    let elsewhere = create(serde_json::json!({"allowed_ip": "10.0.0.0/8"})).await;
+    assert_eq!(client.get(elsewhere["url"].as_str().unwrap()).send().await.unwrap().status(), 403);
+    let local = create(serde_json::json!({"allowed_ip": "127.0.0.1"})).await;
+    assert_eq!(client.get(local["url"].as_str().unwrap()).send().await.unwrap().status(), 200);
+
+    let invalid = client.post(&links_url).json(&serde_json::json!({"allowed_ip": "nowhere"})).send().await.unwrap();
+    assert_eq!(invalid.status(), 400);
+
+    let short = create(serde_json::json!({"expires_in_secs": 1})).await;
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    assert_eq!(client.get(short["url"].as_str().unwrap()).send().await.unwrap().status(), 410);
+
+    let accesses: Value = client
+        .get(format!("{}/{}/accesses", links_url, limited["share_link"]["id"].as_str().unwrap()))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let outcomes: Vec<&str> = accesses["accesses"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|access| access["outcome"].as_str().unwrap())
+        .collect();
+    assert_eq!(outcomes, ["granted", "granted", "exhausted"]);
+
+    // O link vale para o conteúdo de quando foi criado, não para uma revisão nova
+    let pinned = create(serde_json::json!({})).await;
+    let form = multipart::Form::new().part(
+        "file",
+        multipart::Part::bytes(format!("%PDF-1.4 revised {}", uuid::Uuid::new_v4()).into_bytes())
+            .file_name("audit-v2.pdf")
+            .mime_str("application/pdf")
+            .unwrap(),
+    );
+    let revised = client
+        .post(format!("http://localhost:8080/api/documents/{}/revisions", document["id"].as_str().unwrap()))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert!(revised.status().is_success());
+    wait_for_scan(&client, document["id"].as_str().unwrap()).await;
+    assert_eq!(client.get(pinned["url"].as_str().unwrap()).send().await.unwrap().status(), 410);
+}
+
+#[tokio::test]
+async fn test_user_documents_archive() {
+    use std::io::Read;
+
+    let client = Client::new();
+    let user_id = uuid::Uuid::new_v4().to_string();
+    let mut uploaded = Vec::new();
+    for index in 0..3 {
+        let content = format!("%PDF-1.4 archive {} {}", index, uuid::Uuid::new_v4()).into_bytes();
+        let file_part = multipart::Part::bytes(content.clone())
+            .file_name("contract.pdf")
+            .mime_str("application/pdf")
+            .unwrap();
+        let form = multipart::Form::new()
+            .text("user_id", user_id.clone())
+            .text("doc_type", "passport")
+            .part("file", file_part);
+        let created: Value = client
+            .post("http://localhost:8080/api/documents")
+            .multipart(form)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let document = wait_for_scan(&client, created["document"]["id"].as_str().unwrap()).await;
+        uploaded.push((document["id"].as_str().unwrap().to_string(), content));
+    }
+
+    // Uma chave de dados que não abre não pode interromper o ZIP já em envio
+    let (unreadable_id, _) = uploaded.pop().unwrap();
+    dotenv::dotenv().ok();
+    let pool = sqlx::PgPool::connect(&std::env::var("DATABASE_URL").unwrap()).await.unwrap();
+    sqlx::query("UPDATE documents SET wrapped_key = 'AAAA' WHERE id = $1::uuid")
+        .bind(&unreadable_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let response = client
+        .get(format!("http://localhost:8080/api/users/{}/documents/archive", user_id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/zip");
+    let bytes = response.bytes().await.unwrap();
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes.to_vec())).unwrap();
+    assert_eq!(archive.len(), 3);
+
+    let mut manifest = String::new();
+    archive.by_name("manifest.json").unwrap().read_to_string(&mut manifest).unwrap();
+    let manifest: Value = serde_json::from_str(&manifest).unwrap();
+    assert_eq!(manifest["user_id"], user_id);
+    assert_eq!(manifest["documents"].as_array().unwrap().len(), 3);
+    assert_eq!(manifest["files"].as_array().unwrap().len(), 2);
+    assert_eq!(manifest["skipped"][0]["document_id"], unreadable_id);
+
+    // Mesmo nome original, caminhos diferentes
+    for (id, content) in &uploaded {
+        let mut stored = Vec::new();
+        archive
+            .by_name(&format!("documents/{}/contract.pdf", id))
+            .unwrap()
+            .read_to_end(&mut stored)
+            .unwrap();
+        assert_eq!(&stored, content);
+    }
+}
+
+#[tokio::test]
+async fn test_encrypted_content_across_segments() {
+    let client = Client::new();
+    let mut bytes = format!("%PDF-1.4 {}\n", uuid::Uuid::new_v4()).into_bytes();
+    bytes.extend((0..200_000u32).map(|i| (i % 251) as u8));
+    let document = upload_document(&client, "scan.pdf", "application/pdf", bytes.clone()).await;
+    assert!(document["master_key_id"].is_string());
+    assert!(document.get("wrapped_key").is_none());
+    let url = format!("http://localhost:8080/api/documents/{}/content", document["id"].as_str().unwrap());
+
+    let full = client.get(&url).send().await.unwrap();
+    assert_eq!(full.status(), 200);
+    assert_eq!(full.bytes().await.unwrap().to_vec(), bytes);
+
+    // Intervalos que atravessam a fronteira dos segmentos de 64 KiB e o fim do arquivo
+    for (start, end) in [(65_530, 65_545), (131_000, 131_100), (bytes.len() - 10, bytes.len() - 1)] {
+        let partial = client
+            .get(&url)
+            .header("Range", format!("bytes={}-{}", start, end))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(partial.status(), 206);
+        assert_eq!(partial.bytes().await.unwrap().as_ref(), &bytes[start..=end]);
+    }
+
+    let rotated = client.post("http://localhost:8080/api/admin/key-rotation").send().await.unwrap();
+    assert_eq!(rotated.status(), 200);
+    let rotated: Value = rotated.json().await.unwrap();
+    assert_eq!(rotated["master_key_id"], document["master_key_id"]);
+}
+
+// Conteúdo gravado em claro, de antes da criptografia em repouso: as linhas ficam sem chave
+async fn forget_data_key(pool: &sqlx::PgPool, sha256: &str) {
+    for table in ["documents", "document_revisions"] {
+        sqlx::query(&format!("UPDATE {} SET master_key_id = NULL, wrapped_key = NULL WHERE filename = $1", table))
+            .bind(sha256)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_plaintext_content_gets_encrypted() {
+    let client = Client::new();
+    dotenv::dotenv().ok();
+    let pool = sqlx::PgPool::connect(&std::env::var("DATABASE_URL").unwrap()).await.unwrap();
+
+    // Um novo upload do mesmo conteúdo cifra o que estava em claro, para todos
+    let bytes = format!("%PDF-1.4 {}\n", uuid::Uuid::new_v4()).into_bytes();
+    let legacy = upload_document(&client, "legacy.pdf", "application/pdf", bytes.clone()).await;
+    forget_data_key(&pool, legacy["sha256"].as_str().unwrap()).await;
+    let url = format!("http://localhost:8080/api/documents/{}/content", legacy["id"].as_str().unwrap());
+    let stored = client.get(&url).send().await.unwrap().bytes().await.unwrap();
+    assert_ne!(stored.to_vec(), bytes);
+
+    let again = upload_document(&client, "again.pdf", "application/pdf", bytes.clone()).await;
+    assert!(again["master_key_id"].is_string());
+    let legacy = wait_for_scan(&client, legacy["id"].as_str().unwrap()).await;
+    assert_eq!(legacy["master_key_id"], again["master_key_id"]);
+    let content = client.get(&url).send().await.unwrap().bytes().await.unwrap();
+    assert_eq!(content.to_vec(), bytes);
+
+    // O job de administração cifra o resto sem mudar o que é servido
+    let bytes = format!("%PDF-1.4 {}\n", uuid::Uuid::new_v4()).into_bytes();
+    let legacy = upload_document(&client, "legacy.pdf", "application/pdf", bytes).await;
+    forget_data_key(&pool, legacy["sha256"].as_str().unwrap()).await;
+    let url = format!("http://localhost:8080/api/documents/{}/content", legacy["id"].as_str().unwrap());
+    let plaintext = client.get(&url).send().await.unwrap().bytes().await.unwrap();
+
+    let response = client
+        .post("http://localhost:8080/api/admin/plaintext-encryption")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["encrypted"].as_u64().unwrap() >= 1);
+    assert_eq!(body["failed"], 0);
+
+    let legacy = wait_for_scan(&client, legacy["id"].as_str().unwrap()).await;
+    assert!(legacy["master_key_id"].is_string());
+    let content = client.get(&url).send().await.unwrap().bytes().await.unwrap();
+    assert_eq!(content, plaintext);
+}
+
+#[tokio::test]
+async fn test_retention_purge_respects_legal_holds() {
+    let client = Client::new();
+    let doc_type = format!("retention_{}", uuid::Uuid::new_v4().simple());
+    let created = client
+        .post("http://localhost:8080/api/admin/document-types")
+        .json(&serde_json::json!({"key": doc_type, "display_name": "Retention test"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(created.status(), 200);
+
+    let rule = client
+        .put(format!("http://localhost:8080/api/admin/retention-rules/{}", doc_type))
+        .json(&serde_json::json!({"retention_years": 2}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rule.status(), 200);
+
+    // Três documentos: um livre, um com hold próprio e um de usuário com hold
+    let held_user = uuid::Uuid::new_v4().to_string();
+    let mut ids = Vec::new();
+    for user_id in [uuid::Uuid::new_v4().to_string(), uuid::Uuid::new_v4().to_string(), held_user.clone()] {
+        let file_part = multipart::Part::bytes(format!("%PDF-1.4 {}", uuid::Uuid::new_v4()).into_bytes())
+            .file_name("old.pdf")
+            .mime_str("application/pdf")
+            .unwrap();
+        let form = multipart::Form::new()
+            .text("user_id", user_id)
+            .text("doc_type", doc_type.clone())
+            .part("file", file_part);
+        let body: Value = client
+            .post("http://localhost:8080/api/documents")
+            .multipart(form)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        ids.push(body["document"]["id"].as_str().unwrap().to_string());
+    }
+
+    // O prazo conta do upload; a API não muda created_at, então o teste recua direto no banco
+    dotenv::dotenv().ok();
+    let pool = sqlx::PgPool::connect(&std::env::var("DATABASE_URL").unwrap()).await.unwrap();
+    sqlx::query("UPDATE documents SET created_at = now() - interval '3 years' WHERE doc_type = $1")
+        .bind(&doc_type)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let hold = client
+        .put(format!("http://localhost:8080/api/documents/{}/legal-hold", ids[1]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(hold.status(), 200);
+    let user_hold = client
+        .put(format!("http://localhost:8080/api/users/{}/legal-hold", held_user))
+        .json(&serde_json::json!({"reason": "case 2026-001"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(user_hold.status(), 200);
+
+    // Vai para a lixeira, mas não sai dela enquanto durar o hold
+    let trashed = client
+        .delete(format!("http://localhost:8080/api/documents/{}", ids[1]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(trashed.status(), 204);
+    let refused = client
+        .delete(format!("http://localhost:8080/api/trash/documents/{}", ids[1]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(refused.status(), 409);
+    let restored = client
+        .post(format!("http://localhost:8080/api/trash/documents/{}/restore", ids[1]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(restored.status(), 200);
+
+    let purged: Value = client
+        .post("http://localhost:8080/api/admin/retention-purge")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(purged["destroyed"].as_u64().unwrap() >= 1);
+    assert!(purged["held"].as_u64().unwrap() >= 2);
+    assert_eq!(purged["failed"], 0);
+
+    // O conteúdo dá 404 só quando o documento não existe mais
+    let status = |id: &str| {
+        let request = client.get(format!("http://localhost:8080/api/documents/{}/content", id)).send();
+        async move { request.await.unwrap().status() }
+    };
+    assert_eq!(status(&ids[0]).await, 404);
+    assert_ne!(status(&ids[1]).await, 404);
+    assert_ne!(status(&ids[2]).await, 404);
+
+    let destructions: Value = client
+        .get("http://localhost:8080/api/admin/destructions?limit=100")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let destroyed: Vec<&str> = destructions["destructions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|destruction| destruction["document_id"].as_str().unwrap())
+        .collect();
+    assert!(destroyed.contains(&ids[0].as_str()));
+    assert!(!destroyed.contains(&ids[1].as_str()));
+
+    // Sem o hold do usuário, o documento dele vai na próxima limpeza
+    let released = client
+        .delete(format!("http://localhost:8080/api/users/{}/legal-hold", held_user))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(released.status(), 204);
+    client.post("http://localhost:8080/api/admin/retention-purge").send().await.unwrap();
+    assert_eq!(status(&ids[2]).await, 404);
+    assert_ne!(status(&ids[1]).await, 404);
+}
+
+#[tokio::test]
+async fn test_trash_purge_respects_retention() {
+    let client = Client::new();
+    let doc_type = format!("retention_{}", uuid::Uuid::new_v4().simple());
+    let created = client
+        .post("http://localhost:8080/api/admin/document-types")
+        .json(&serde_json::json!({"key": doc_type, "display_name": "Trash retention test"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(created.status(), 200);
+    let rule_url = format!("http://localhost:8080/api/admin/retention-rules/{}", doc_type);
+    let rule = client.put(&rule_url).json(&serde_json::json!({"retention_years": 5})).send().await.unwrap();
+    assert_eq!(rule.status(), 200);
+
+    let file_part = multipart::Part::bytes(format!("%PDF-1.4 {}", uuid::Uuid::new_v4()).into_bytes())
+        .file_name("kept.pdf")
+        .mime_str("application/pdf")
+        .unwrap();
+    let form = multipart::Form::new()
+        .text("user_id", uuid::Uuid::new_v4().to_string())
+        .text("doc_type", doc_type.clone())
+        .part("file", file_part);
+    let body: Value = client
+        .post("http://localhost:8080/api/documents")
+        .multipart(form)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = body["document"]["id"].as_str().unwrap().to_string();
+    let trash_url = format!("http://localhost:8080/api/trash/documents/{}", id);
+
+    // Na lixeira, mas ainda dentro do prazo: não sai
+    let trashed = client.delete(format!("http://localhost:8080/api/documents/{}", id)).send().await.unwrap();
+    assert_eq!(trashed.status(), 204);
+    let refused = client.delete(&trash_url).send().await.unwrap();
+    assert_eq!(refused.status(), 409);
+    let body: Value = refused.json().await.unwrap();
+    assert!(body["message"].as_str().unwrap().contains("retention rule"));
+
+    // Sem a regra, sai e fica no registro de destruições
+    assert_eq!(client.delete(&rule_url).send().await.unwrap().status(), 204);
+    assert_eq!(client.delete(&trash_url).send().await.unwrap().status(), 204);
+    let destructions: Value = client
+        .get("http://localhost:8080/api/admin/destructions?limit=100")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let destruction = destructions["destructions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|destruction| destruction["document_id"] == id.as_str())
+        .expect("trash purge is logged");
+    assert_eq!(destruction["reason"], "trash");
+    assert!(destruction["retention_years"].is_null());
+}
+
+#[tokio::test]
+async fn test_user_storage_quota() {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let client = Client::new();
+    let user_id = uuid::Uuid::new_v4().to_string();
+    let upload = |content: &'static [u8]| {
+        let file_part = multipart::Part::bytes(content).file_name("quota.pdf").mime_str("application/pdf").unwrap();
+        let form = multipart::Form::new()
+            .text("user_id", user_id.clone())
+            .text("doc_type", "passport")
+            .part("file", file_part);
+        client.post("http://localhost:8080/api/documents").multipart(form).send()
+    };
+    let quota_url = format!("http://localhost:8080/api/users/{}/quota", user_id);
+    let usage_url = format!("http://localhost:8080/api/users/{}/usage", user_id);
+
+    let quota = client
+        .put(&quota_url)
+        .json(&serde_json::json!({"max_bytes": 40, "max_documents": 1}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(quota.status(), 200);
+
+    let first: &[u8] = b"%PDF-1.4 quota 25 bytes..";
+    assert_eq!(upload(first).await.unwrap().status(), 200);
+    let usage: Value = client.get(&usage_url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(usage["usage"]["bytes_used"], first.len());
+    assert_eq!(usage["usage"]["documents"], 1);
+    assert_eq!(usage["usage"]["max_bytes"], 40);
+
+    // Sem espaço: 413, com o consumo no corpo
+    let too_big = upload(b"%PDF-1.4 this one does not fit").await.unwrap();
+    assert_eq!(too_big.status(), 413);
+    let body: Value = too_big.json().await.unwrap();
+    assert_eq!(body["usage"]["bytes_used"], first.len());
+    assert!(body["message"].as_str().unwrap().contains("quota"));
+
+    // Conteúdo repetido não ocupa espaço, mas ainda é mais um documento
+    let too_many = upload(first).await.unwrap();
+    assert_eq!(too_many.status(), 403);
+    let body: Value = too_many.json().await.unwrap();
+    assert_eq!(body["usage"]["max_documents"], 1);
+
+    // O tus recusa antes de receber os bytes
+    let metadata = format!(
+        "filename {},filetype {},user_id {},doc_type {}",
+        STANDARD.encode("quota.pdf"),
+        STANDARD.encode("application/pdf"),
+        STANDARD.encode(&user_id),
+        STANDARD.encode("passport")
+    );
+    let created = client
+        .post("http://localhost:8080/api/uploads")
+        .header("Tus-Resumable", "1.0.0")
+        .header("Upload-Length", "1000")
+        .header("Upload-Metadata", metadata)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(created.status(), 413);
+
+    // Sem cota própria vale o padrão da configuração
+    assert_eq!(client.delete(&quota_url).send().await.unwrap().status(), 204);
+    assert_eq!(client.delete(&quota_url).send().await.unwrap().status(), 404);
+    assert_eq!(upload(b"%PDF-1.4 this one does not fit").await.unwrap().status(), 200);
+    let usage: Value = client.get(&usage_url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(usage["usage"]["documents"], 2);
+}
+
+#[tokio::test]
+async fn test_quota_on_owner_change_and_trash() {
+    let client = Client::new();
+    let owner = uuid::Uuid::new_v4().to_string();
+    let new_owner = uuid::Uuid::new_v4().to_string();
+    let content = format!("%PDF-1.4 transfer {}", owner).into_bytes();
+    let size = content.len();
+
+    let file_part = multipart::Part::bytes(content).file_name("transfer.pdf").mime_str("application/pdf").unwrap();
+    let form = multipart::Form::new()
+        .text("user_id", owner.clone())
+        .text("doc_type", "passport")
+        .part("file", file_part);
+    let document: Value = client
+        .post("http://localhost:8080/api/documents")
+        .multipart(form)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = document["document"]["id"].as_str().unwrap();
+    let document_url = format!("http://localhost:8080/api/documents/{}", id);
+    let quota_url = format!("http://localhost:8080/api/users/{}/quota", new_owner);
+    let usage = |user_id: &str| {
+        let url = format!("http://localhost:8080/api/users/{}/usage", user_id);
+        let request = client.get(url).send();
+        async move { request.await.unwrap().json::<Value>().await.unwrap()["usage"].clone() }
+    };
+
+    // O novo dono não tem espaço: 413 e o documento fica com quem estava
+    let quota = client.put(&quota_url).json(&serde_json::json!({"max_bytes": 10})).send().await.unwrap();
+    assert_eq!(quota.status(), 200);
+    let patched = client.patch(&document_url).json(&serde_json::json!({"user_id": new_owner})).send().await.unwrap();
+    assert_eq!(patched.status(), 413);
+    let fetched: Value = client.get(&document_url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(fetched["document"]["user_id"], owner.as_str());
+
+    // O de documentos também vale na troca
+    let quota = client.put(&quota_url).json(&serde_json::json!({"max_documents": 0})).send().await.unwrap();
+    assert_eq!(quota.status(), 200);
+    let patched = client.patch(&document_url).json(&serde_json::json!({"user_id": new_owner})).send().await.unwrap();
+    assert_eq!(patched.status(), 403);
+
+    // A lixeira conta no total e aparece à parte
+    assert_eq!(client.delete(&document_url).send().await.unwrap().status(), 204);
+    let trashed = usage(&owner).await;
+    assert_eq!(trashed["bytes_used"], size);
+    assert_eq!(trashed["documents"], 1);
+    assert_eq!(trashed["trash_bytes"], size);
+    assert_eq!(trashed["trash_documents"], 1);
+    assert_eq!(client.post(format!("http://localhost:8080/api/trash/documents/{}/restore", id)).send().await.unwrap().status(), 200);
+    let restored = usage(&owner).await;
+    assert_eq!(restored["trash_bytes"], 0);
+    assert_eq!(restored["trash_documents"], 0);
+
+    // Com folga, a troca passa e o consumo muda de dono
+    assert_eq!(client.delete(&quota_url).send().await.unwrap().status(), 204);
+    let patched = client.patch(&document_url).json(&serde_json::json!({"user_id": new_owner})).send().await.unwrap();
+    assert_eq!(patched.status(), 200);
+    assert_eq!(usage(&owner).await["bytes_used"], 0);
+    assert_eq!(usage(&new_owner).await["bytes_used"], size);
+}
+
+// Imagem em blocos de tons aleatórios, única por semente
+fn block_pattern(seed: u64, width: u32, height: u32) -> image::RgbImage {
+    let mut state = seed | 1;
+    let shades: Vec<u8> = (0..16 * 12)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 256) as u8
+        })
+        .collect();
+    image::RgbImage::from_fn(width, height, |x, y| {
+        let shade = shades[((y * 12 / height) * 16 + x * 16 / width) as usize];
+        image::Rgb([shade, shade / 2, 255 - shade])
+    })
+}
+
+#[tokio::test]
+async fn test_near_duplicate_images() {
+    let client = Client::new();
+    let upload = |user_id: String, file_name: &'static str, mime: &'static str, bytes: Vec<u8>| {
+        let file_part = multipart::Part::bytes(bytes).file_name(file_name).mime_str(mime).unwrap();
+        let form = multipart::Form::new()
+            .text("user_id", user_id)
+            .text("doc_type", "passport")
+            .part("file", file_part);
+        let request = client.post("http://localhost:8080/api/documents").multipart(form).send();
+        async move {
+            let body: Value = request.await.unwrap().json().await.unwrap();
+            body["document"]["id"].as_str().unwrap().to_string()
+        }
+    };
+    let seed = u64::from_le_bytes(uuid::Uuid::new_v4().as_bytes()[..8].try_into().unwrap());
+
+    let photo = block_pattern(seed, 640, 480);
+    let mut png = Vec::new();
+    photo.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+    let original = upload(uuid::Uuid::new_v4().to_string(), "id.png", "image/png", png).await;
+
+    // A mesma foto, levemente cortada e recomprimida, por outro usuário
+    let cropped = image::imageops::crop_imm(&photo, 10, 8, 620, 464).to_image();
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 60).encode_image(&cropped).unwrap();
+    let resubmitted = upload(uuid::Uuid::new_v4().to_string(), "id.jpg", "image/jpeg", jpeg).await;
+
+    let mut other = Vec::new();
+    block_pattern(!seed, 640, 480)
+        .write_to(&mut std::io::Cursor::new(&mut other), image::ImageFormat::Png)
+        .unwrap();
+    let unrelated = upload(uuid::Uuid::new_v4().to_string(), "other.png", "image/png", other).await;
+
+    let found: Value = client
+        .get(format!("http://localhost:8080/api/documents/{}/near-duplicates?limit=100", original))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ids: Vec<&str> = found["documents"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|document| document["id"].as_str().unwrap())
+        .collect();
+    assert!(ids.contains(&resubmitted.as_str()));
+    assert!(!ids.contains(&unrelated.as_str()));
+    assert!(!ids.contains(&original.as_str()));
+    assert!(found["documents"][0]["distance"].as_i64().unwrap() <= 10);
+
+    let invalid = client
+        .get(format!("http://localhost:8080/api/documents/{}/near-duplicates?max_distance=65", original))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), 400);
+    let negative = client
+        .get(format!("http://localhost:8080/api/documents/{}/near-duplicates?limit=-1", original))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(negative.status(), 400);
+    let capped: Value = client
+        .get(format!("http://localhost:8080/api/documents/{}/near-duplicates?limit=9223372036854775807", original))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(capped["limit"], 100);
+
+    // Só imagens têm hash
+    let pdf = upload(uuid::Uuid::new_v4().to_string(), "scan.pdf", "application/pdf", b"%PDF-1.4 no pixels".to_vec()).await;
+    let not_image = client
+        .get(format!("http://localhost:8080/api/documents/{}/near-duplicates", pdf))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(not_image.status(), 422);
+}
+
+#[tokio::test]
+async fn test_merge_images_into_pdf() {
+    let client = Client::new();
+    let user_id = uuid::Uuid::new_v4().to_string();
+    let upload = |user_id: String, file_name: &'static str, mime: &'static str, bytes: Vec<u8>| {
+        let file_part = multipart::Part::bytes(bytes).file_name(file_name).mime_str(mime).unwrap();
+        let form = multipart::Form::new()
+            .text("user_id", user_id)
+            .text("doc_type", "id_card")
+            .part("file", file_part);
+        let request = client.post("http://localhost:8080/api/documents").multipart(form).send();
+        async {
+            let body: Value = request.await.unwrap().json().await.unwrap();
+            wait_for_scan(&client, body["document"]["id"].as_str().unwrap()).await["id"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        }
+    };
+    let seed = u64::from_le_bytes(uuid::Uuid::new_v4().as_bytes()[..8].try_into().unwrap());
+    let png = |seed: u64, width: u32, height: u32| {
+        let mut png = Vec::new();
+        block_pattern(seed, width, height)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        png
+    };
+
+    let front = upload(user_id.clone(), "front.png", "image/png", png(seed, 320, 200)).await;
+    let back = upload(user_id.clone(), "back.png", "image/png", png(!seed, 200, 320)).await;
+
+    let merged = client
+        .post("http://localhost:8080/api/documents/merge")
+        .json(&serde_json::json!({"document_ids": [back, front], "filename": "id card.pdf"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(merged.status(), 200);
+    let merged: Value = merged.json().await.unwrap();
+    let document = &merged["document"];
+    assert_eq!(document["mime_type"], "application/pdf");
+    assert_eq!(document["user_id"], user_id.as_str());
+    assert_eq!(document["doc_type"], "id_card");
+    assert_eq!(document["original_filename"], "id card.pdf");
+    let document_id = document["id"].as_str().unwrap();
+
+    wait_for_scan(&client, document_id).await;
+    let pdf = client
+        .get(format!("http://localhost:8080/api/documents/{}/content", document_id))
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    assert!(pdf.starts_with(b"%PDF-"));
+    let text = String::from_utf8_lossy(&pdf);
+    assert_eq!(text.matches("/Type /Page ").count(), 2);
+    assert!(text.contains("/Width 200 /Height 320"));
+    // Cada entrada da tabela xref aponta para o início do seu objeto
+    let startxref: usize = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+    let xref = std::str::from_utf8(&pdf[startxref..]).unwrap();
+    assert!(xref.starts_with("xref\n0 "));
+    for (id, line) in xref.lines().skip(3).take_while(|line| line.ends_with(" n ")).enumerate() {
+        let offset: usize = line[..10].parse().unwrap();
+        assert!(pdf[offset..].starts_with(format!("{} 0 obj", id + 1).as_bytes()));
+    }
+
+    let sources: Value = client
+        .get(format!("http://localhost:8080/api/documents/{}/sources", document_id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(sources["sources"][0]["document_id"], back.as_str());
+    assert_eq!(sources["sources"][0]["position"], 1);
+    assert_eq!(sources["sources"][1]["document_id"], front.as_str());
+
+    // Imagens de outro usuário, PDFs e ids repetidos ficam de fora
+    let stranger = upload(uuid::Uuid::new_v4().to_string(), "other.png", "image/png", png(seed ^ 1, 64, 64)).await;
+    let merge = |ids: Vec<&str>| {
+        client
+            .post("http://localhost:8080/api/documents/merge")
+            .json(&serde_json::json!({ "document_ids": ids }))
+            .send()
+    };
+    assert_eq!(merge(vec![&front, &stranger]).await.unwrap().status(), 400);
+    assert_eq!(merge(vec![&front, document_id]).await.unwrap().status(), 415);
+    assert_eq!(merge(vec![&front, &front]).await.unwrap().status(), 400);
+    let missing = uuid::Uuid::new_v4().to_string();
+    assert_eq!(merge(vec![&front, &missing]).await.unwrap().status(), 404);
+}
+
+#[tokio::test]
+async fn test_watermarked_download() {
+    let client = Client::new();
+    let seed = u64::from_le_bytes(uuid::Uuid::new_v4().as_bytes()[..8].try_into().unwrap());
+    let mut png = Vec::new();
+    block_pattern(seed, 320, 200)
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let uploaded = upload_document(&client, "passport.png", "image/png", png.clone()).await;
+    let document_id = uploaded["id"].as_str().unwrap().to_string();
+    let content_url = format!("http://localhost:8080/api/documents/{}/content", document_id);
+
+    let stamped = client
+        .get(format!("{}?watermark=true&downloaded_by=ana&purpose=audit", content_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stamped.status(), 200);
+    assert_eq!(stamped.headers()["content-type"], "image/png");
+    assert_eq!(stamped.headers()["cache-control"], "no-store");
+    let stamped = stamped.bytes().await.unwrap();
+    assert_ne!(stamped.as_ref(), png.as_slice());
+    let image = image::load_from_memory(&stamped).unwrap();
+    assert_eq!((image.width(), image.height()), (320, 200));
+
+    // Sem quem baixou ou a finalidade não há marca; o original continua igual
+    let missing = client
+        .get(format!("{}?watermark=true&downloaded_by=ana", content_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 400);
+    let original = client.get(&content_url).send().await.unwrap().bytes().await.unwrap();
+    assert_eq!(original.as_ref(), png.as_slice());
+
+    // PDF gerado a partir da imagem: a marca vai em cada página
+    let merged: Value = client
+        .post("http://localhost:8080/api/documents/merge")
+        .json(&serde_json::json!({ "document_ids": [document_id] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let pdf_id = merged["document"]["id"].as_str().unwrap();
+    wait_for_scan(&client, pdf_id).await;
+    let pdf = client
+        .get(format!(
+            "http://localhost:8080/api/documents/{}/content?watermark=true&downloaded_by=ana&purpose=audit",
+            pdf_id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(pdf.status(), 200);
+    assert_eq!(pdf.headers()["content-type"], "application/pdf");
+    let pdf = pdf.bytes().await.unwrap();
+    assert!(pdf.starts_with(b"%PDF-"));
+    let text = String::from_utf8_lossy(&pdf);
+    assert!(text.contains("(ana | "));
+    assert!(text.contains(" | audit) Tj"));
+}
+
+#[tokio::test]
+async fn test_approved_document_signature() {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let client = Client::new();
+    let reviewer = "0b9f3c4e-7a51-4c1d-9c3a-5d2e8f6a1b70";
+    let content = format!("%PDF-1.4 signed {}", uuid::Uuid::new_v4()).into_bytes();
+    let document = upload_document(&client, "signed.pdf", "application/pdf", content.clone()).await;
+    let document_id = document["id"].as_str().unwrap();
+    let document_url = format!("http://localhost:8080/api/documents/{}", document_id);
+
+    let review = |action: &str| {
+        client
+            .post(format!("{}/review/{}", document_url, action))
+            .json(&serde_json::json!({ "reviewer_id": reviewer }))
+            .send()
+    };
+    let started: Value = review("start").await.unwrap().json().await.unwrap();
+    assert!(started.get("signature").is_none());
+    let approved: Value = review("approve").await.unwrap().json().await.unwrap();
+    let signature = &approved["signature"];
+    assert_eq!(signature["document_id"], document_id);
+    assert_eq!(signature["sha256"], document["sha256"]);
+
+    // Com a chave pública, a assinatura confere sem passar pelo servidor
+    let public_key: Value = client
+        .get("http://localhost:8080/api/signing/public-key")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(public_key["key"]["algorithm"], "Ed25519");
+    assert_eq!(public_key["key"]["key_id"], signature["key_id"]);
+    let key = openssl::pkey::PKey::public_key_from_raw_bytes(
+        &STANDARD.decode(public_key["key"]["public_key"].as_str().unwrap()).unwrap(),
+        openssl::pkey::Id::ED25519,
+    )
+    .unwrap();
+    let payload = signature["payload"].as_str().unwrap();
+    let detached = STANDARD.decode(signature["signature"].as_str().unwrap()).unwrap();
+    let mut verifier = openssl::sign::Verifier::new_without_digest(&key).unwrap();
+    assert!(verifier.verify_oneshot(&detached, payload.as_bytes()).unwrap());
+    let signed: Value = serde_json::from_str(payload).unwrap();
+    assert_eq!(signed["sha256"], document["sha256"]);
+    assert_eq!(signed["approved_by"], reviewer);
+
+    let signatures: Value = client
+        .get(format!("{}/signatures", document_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(signatures["results"], 1);
+
+    let verify = |form: multipart::Form| client.post("http://localhost:8080/api/documents/verify").multipart(form).send();
+    let file_form = |bytes: Vec<u8>| {
+        multipart::Form::new().part("file", multipart::Part::bytes(bytes).file_name("received.pdf"))
+    };
+
+    let by_file: Value = verify(file_form(content.clone())).await.unwrap().json().await.unwrap();
+    assert_eq!(by_file["valid"], true);
+    assert_eq!(by_file["results"][0]["signature"]["document_id"], document_id);
+
+    let mut altered = content.clone();
+    altered.push(b' ');
+    let tampered: Value = verify(file_form(altered.clone())).await.unwrap().json().await.unwrap();
+    assert_eq!(tampered["valid"], false);
+    assert_eq!(tampered["results"].as_array().unwrap().len(), 0);
+
+    let by_id: Value = verify(multipart::Form::new().text("document_id", document_id.to_string()))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(by_id["valid"], true);
+    assert_eq!(by_id["content_matches"], true);
+
+    // Um conteúdo novo não está coberto pela assinatura da aprovação anterior
+    let revised = client
+        .post(format!("{}/revisions", document_url))
+        .multipart(multipart::Form::new().part(
+            "file",
+            multipart::Part::bytes(altered).file_name("signed.pdf").mime_str("application/pdf").unwrap(),
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert!(revised.status().is_success());
+    let by_id: Value = verify(multipart::Form::new().text("document_id", document_id.to_string()))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(by_id["signature_valid"], true);
+    assert_eq!(by_id["content_matches"], false);
+    assert_eq!(by_id["valid"], false);
+
+    let neither = verify(multipart::Form::new().text("note", "nothing")).await.unwrap();
+    assert_eq!(neither.status(), 400);
 }